@@ -0,0 +1,72 @@
+#!/usr/bin/env python3
+"""Layout of the rerun viewer for the pan-tilt recordings.
+
+The rerun Rust SDK cannot create blueprints, so the layout is defined here with the Python SDK
+(`pip install rerun-sdk==0.24.1`, the version used by tfluna_pan_tilt).
+
+Send it to a running viewer before or after starting a scan:
+
+    python3 scripts/rerun_blueprint.py --connect rerun+http://127.0.0.1:9876/proxy
+
+or save it to a file that can be opened together with a recording:
+
+    python3 scripts/rerun_blueprint.py --save pan_tilt.rbl
+    rerun pan_tilt.rrd pan_tilt.rbl
+"""
+
+import argparse
+
+import rerun as rr
+import rerun.blueprint as rrb
+
+# Must match APPLICATION_ID in tfluna_pan_tilt/src/scanner.rs
+APPLICATION_ID = "rpi-lidar"
+
+
+def blueprint() -> rrb.Blueprint:
+    return rrb.Blueprint(
+        rrb.Horizontal(
+            rrb.Vertical(
+                rrb.Spatial3DView(
+                    name="Scan",
+                    origin="/",
+                    contents=["/position/**", "/rig/**", "/refinement/**", "/preview/**"],
+                ),
+                rrb.Horizontal(
+                    rrb.Spatial2DView(name="Distance", origin="/range_image/distance"),
+                    rrb.Spatial2DView(name="Valid", origin="/range_image/valid"),
+                ),
+                row_shares=[3, 1],
+            ),
+            rrb.Vertical(
+                rrb.TimeSeriesView(name="Angles", contents=["/yaw", "/pitch"]),
+                rrb.TimeSeriesView(name="Distance", contents=["/distance"]),
+                rrb.TimeSeriesView(
+                    name="Signal strength and temperature",
+                    contents=["/signal_strength", "/temperature"],
+                ),
+                rrb.TextLogView(name="Log", origin="/log"),
+            ),
+            column_shares=[2, 1],
+        ),
+        rrb.TimePanel(state="expanded"),
+        collapse_panels=True,
+    )
+
+
+def main() -> None:
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    group = parser.add_mutually_exclusive_group(required=True)
+    group.add_argument("--connect", metavar="URL", help="URL of a running rerun viewer")
+    group.add_argument("--save", metavar="FILE", help="Blueprint file (.rbl) to write")
+    args = parser.parse_args()
+
+    if args.save:
+        blueprint().save(APPLICATION_ID, args.save)
+    else:
+        rr.init(APPLICATION_ID)
+        rr.connect_grpc(args.connect, default_blueprint=blueprint())
+
+
+if __name__ == "__main__":
+    main()
@@ -6,6 +6,7 @@ RERUN_DATA_FILE="data/pan_tilt_combinations.rrd"
 N_REPEAT=3
 SERVO_MOTOR_DELAYS=("500" "200" "100" "50" "10")
 ANGLE_STEPS=("1" "5" "15.0")
+SAMPLES_PER_POSE=("1" "5")
 RERUN_SERVER_IP=${RERUN_SERVER_IP:-"192.168.178.21"}
 
 # Start rerun server
@@ -24,11 +25,13 @@ sleep 1;
 
 for SERVO_MOTOR_DELAY in "${SERVO_MOTOR_DELAYS[@]}"; do
     for ANGLE_STEP in "${ANGLE_STEPS[@]}"; do
-        echo "=================="
-        for i in $(seq $N_REPEAT); do
-            echo "Iteration $i: Running executable '$EXECUTABLE' on host '$HOST' with servo motor delay $SERVO_MOTOR_DELAY ms and angle step $ANGLE_STEP deg";
-            ssh $HOST "$EXECUTABLE --servo-motor-delay=$SERVO_MOTOR_DELAY --angle-step=$ANGLE_STEP --min-angle-top=0.0";
-            sleep 1;
+        for SAMPLES in "${SAMPLES_PER_POSE[@]}"; do
+            echo "=================="
+            for i in $(seq $N_REPEAT); do
+                echo "Iteration $i: Running executable '$EXECUTABLE' on host '$HOST' with servo motor delay $SERVO_MOTOR_DELAY ms, angle step $ANGLE_STEP deg and $SAMPLES samples per pose";
+                ssh $HOST "$EXECUTABLE --servo-motor-delay=$SERVO_MOTOR_DELAY --angle-step=$ANGLE_STEP --samples-per-pose=$SAMPLES --min-angle-top=0.0";
+                sleep 1;
+            done
         done
     done
 done
@@ -0,0 +1,27 @@
+use std::process::Command;
+
+// Runs git in the source tree and returns its output, `None` if it fails
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// Records the commit the workspace is built from, see `metadata::GIT_COMMIT`
+fn main() {
+    let commit = match git(&["rev-parse", "HEAD"]) {
+        Some(commit) => match git(&["status", "--porcelain", "--untracked-files=no"]) {
+            Some(changes) if !changes.is_empty() => format!("{commit}-dirty"),
+            _ => commit,
+        },
+        None => String::from("unknown"),
+    };
+    println!("cargo:rustc-env=GIT_COMMIT={commit}");
+    // Cargo scans these directories for modifications, new commits change the refs
+    // and staging changes the index
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs");
+    println!("cargo:rerun-if-changed=../.git/index");
+}
@@ -8,6 +8,9 @@ use std::time::Duration;
 use rerun;
 use rppal::i2c::I2c;
 
+use tfluna::metadata::{
+    GIT_COMMIT, command_line, hostname, send_build_properties, send_tfluna_properties,
+};
 use tfluna::tf_luna::TFLuna;
 
 const TF_LUNA_ADDRESS: u16 = 0x10;
@@ -41,6 +44,15 @@ fn main() -> Result<(), Box<dyn Error>> {
         rerun::default_flush_timeout(),
     )?;
 
+    send_tfluna_properties(&rec, &device_information)?;
+    send_build_properties(
+        &rec,
+        &hostname(),
+        env!("CARGO_PKG_VERSION"),
+        GIT_COMMIT,
+        &command_line(),
+    )?;
+
     sleep(Duration::from_secs(1));
 
     for _ in 0..200 {
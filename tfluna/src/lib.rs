@@ -1 +1,2 @@
+pub mod metadata;
 pub mod tf_luna;
@@ -0,0 +1,152 @@
+//! Recording properties describing where and from which sources a recording was made,
+//! shared by the binaries of the workspace so that their recordings can be compared.
+
+use std::env;
+use std::fs;
+
+use rerun::{RecordingStream, RecordingStreamResult};
+use serde::{Deserialize, Serialize};
+
+use crate::tf_luna::constants;
+use crate::tf_luna::{PowerMode, RangingMode};
+
+/// Commit of the source tree the binaries were built from, with a `-dirty` suffix
+/// when it had uncommitted changes, set by the build script
+pub const GIT_COMMIT: &str = env!("GIT_COMMIT");
+
+// Names of the recording properties, text properties unless stated otherwise
+pub const HOSTNAME_PROPERTY: &str = "hostname";
+pub const CRATE_VERSION_PROPERTY: &str = "crate_version";
+pub const GIT_COMMIT_PROPERTY: &str = "git_commit";
+/// Arguments the binary was started with, one per line
+pub const COMMAND_LINE_PROPERTY: &str = "command_line";
+pub const FIRMWARE_VERSION_PROPERTY: &str = "firmware_version";
+pub const SERIAL_NUMBER_PROPERTY: &str = "serial_number";
+/// All the configuration registers of the TF-Luna, as JSON
+pub const TFLUNA_INFORMATION_PROPERTY: &str = "tfluna_information";
+
+/// Configuration registers of the TF-Luna, sent as the `tfluna_information` property
+/// by the binaries of the workspace, so that their recordings can be grouped by sensor settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TFLunaInformation {
+    pub firmware_version: String,
+    pub serial_number: String,
+    /// Frame rate of the continuous ranging mode in Hz
+    pub frame_rate: u16,
+    pub slave_address: u8,
+    pub mode: RangingMode,
+    pub enable: bool,
+    pub power_mode: PowerMode,
+    /// Signal strength below which the dummy distance is reported
+    pub signal_strength_threshold: u16,
+    /// Distances in centimeters
+    pub dummy_distance: u16,
+    pub minimum_distance: u16,
+    pub maximum_distance: u16,
+}
+
+impl TFLunaInformation {
+    /// Reads the configuration registers one by one with `read_register`,
+    /// which returns the content of the register at the given address
+    pub fn read<E>(mut read_register: impl FnMut(u8) -> Result<u8, E>) -> Result<Self, E> {
+        let mut read_registers = |start: u8, count: u8| -> Result<Vec<u8>, E> {
+            (start..start + count).map(&mut read_register).collect()
+        };
+        // Values whose lower byte is at the first register and upper byte at the next one
+        let two_byte_value = |registers: Vec<u8>| u16::from_le_bytes([registers[0], registers[1]]);
+        let version = read_registers(constants::FIRMWARE_VERSION_REGISTER_ADDRESS, 3)?;
+        Ok(TFLunaInformation {
+            firmware_version: firmware_version([version[0], version[1], version[2]]),
+            serial_number: serial_number(&read_registers(
+                constants::SERIAL_NUMBER_REGISTER_ADDRESS,
+                14,
+            )?),
+            frame_rate: two_byte_value(read_registers(constants::FRAMERATE_REGISTER_ADDRESS, 2)?),
+            slave_address: read_registers(constants::SLAVE_ADDRESS_REGISTER_ADDRESS, 1)?[0],
+            mode: match read_registers(constants::RANGING_MODE_REGISTER_ADDRESS, 1)?[0] {
+                0 => RangingMode::Continuous,
+                _ => RangingMode::Trigger,
+            },
+            enable: read_registers(constants::ENABLE_REGISTER_ADDRESS, 1)?[0] == 1,
+            power_mode: match read_registers(constants::POWER_MODE_REGISTER_ADDRESS, 1)?[0] {
+                0 => PowerMode::Normal,
+                _ => PowerMode::LowPower,
+            },
+            signal_strength_threshold: two_byte_value(read_registers(
+                constants::SIGNAL_STRENGTH_THRESHOLD_REGISTER_ADDRESS,
+                2,
+            )?),
+            dummy_distance: two_byte_value(read_registers(
+                constants::DUMMY_DISTANCE_REGISTER_ADDRESS,
+                2,
+            )?),
+            minimum_distance: two_byte_value(read_registers(
+                constants::MINIMUM_DISTANCE_REGISTER_ADDRESS,
+                2,
+            )?),
+            maximum_distance: two_byte_value(read_registers(
+                constants::MAXIMUM_DISTANCE_REGISTER_ADDRESS,
+                2,
+            )?),
+        })
+    }
+}
+
+/// Firmware version `major.minor.patch` from the version registers, patch first
+pub fn firmware_version(registers: [u8; 3]) -> String {
+    format!("{}.{}.{}", registers[2], registers[1], registers[0])
+}
+
+/// Serial number from its registers, which hold ASCII characters
+pub fn serial_number(registers: &[u8]) -> String {
+    String::from_utf8_lossy(registers)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+/// Name of the host, `unknown` if it cannot be determined
+pub fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|name| name.trim().to_string())
+        .ok()
+        .or_else(|| env::var("HOSTNAME").ok())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+/// Arguments of the running binary
+pub fn command_line() -> Vec<String> {
+    env::args().collect()
+}
+
+/// Sends the host, the version of the crate of the binary, the commit and the command line
+pub fn send_build_properties(
+    rec: &RecordingStream,
+    hostname: &str,
+    crate_version: &str,
+    git_commit: &str,
+    command_line: &[String],
+) -> RecordingStreamResult<()> {
+    let text = |text: &str| rerun::TextDocument::new(text);
+    rec.send_property(HOSTNAME_PROPERTY, &text(hostname))?;
+    rec.send_property(CRATE_VERSION_PROPERTY, &text(crate_version))?;
+    rec.send_property(GIT_COMMIT_PROPERTY, &text(git_commit))?;
+    rec.send_property(COMMAND_LINE_PROPERTY, &text(&command_line.join("\n")))
+}
+
+/// Sends the firmware version and the serial number of the TF-Luna,
+/// and all of its configuration registers as JSON
+pub fn send_tfluna_properties(
+    rec: &RecordingStream,
+    information: &TFLunaInformation,
+) -> RecordingStreamResult<()> {
+    let text = |text: &str| rerun::TextDocument::new(text);
+    rec.send_property(
+        FIRMWARE_VERSION_PROPERTY,
+        &text(&information.firmware_version),
+    )?;
+    rec.send_property(SERIAL_NUMBER_PROPERTY, &text(&information.serial_number))?;
+    // Serializing plain fields cannot fail
+    let information = serde_json::to_string_pretty(information).unwrap_or_default();
+    rec.send_property(TFLUNA_INFORMATION_PROPERTY, &text(&information))
+}
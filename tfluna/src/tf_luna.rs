@@ -1,34 +1,24 @@
-mod constants;
+pub mod constants;
 
 use rppal::i2c::{Error, I2c};
+use serde::{Deserialize, Serialize};
+
+use crate::metadata::{TFLunaInformation, firmware_version, serial_number};
 
 #[derive(Debug)]
 pub struct TFLuna {
     i2c: I2c,
 }
 
-#[derive(Clone, Debug)]
-pub struct TFLunaInformation {
-    firmware_version: String,
-    serial_number: String,
-    frame_rate: u16,
-    slave_address: u8,
-    mode: RangingMode,
-    enable: bool,
-    power_mode: PowerMode,
-    signal_strength_threshold: u16,
-    dummy_distance: u16,
-    minimum_distance: u16,
-    maximum_distance: u16,
-}
-
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum RangingMode {
     Continuous,
     Trigger,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum PowerMode {
     Normal,
     LowPower,
@@ -99,28 +89,18 @@ impl TFLuna {
     pub fn get_firmware_version(&mut self) -> Result<String, Error> {
         let mut buffer = [0; 3];
         for i in 0..=2 {
-            buffer[i] = self.read_register(0x0A + i as u8)?;
+            buffer[i] =
+                self.read_register(constants::FIRMWARE_VERSION_REGISTER_ADDRESS + i as u8)?;
         }
-        let version = format!("{}.{}.{}", buffer[2], buffer[1], buffer[0]);
-        Ok(version)
+        Ok(firmware_version(buffer))
     }
 
     pub fn get_serial_number(&mut self) -> Result<String, Error> {
         let mut buffer = [0; 14];
         for i in 0..14 {
-            buffer[i] = self.read_register(0x10 + i as u8)?;
+            buffer[i] = self.read_register(constants::SERIAL_NUMBER_REGISTER_ADDRESS + i as u8)?;
         }
-        let serial_number = buffer
-            .into_iter()
-            .map(|x| format!("{}", x))
-            .reduce(|acc, e| {
-                let mut acc = acc;
-                acc.push_str(&e);
-                acc
-            })
-            // TODO: error handling
-            .unwrap();
-        Ok(serial_number)
+        Ok(serial_number(&buffer))
     }
 
     // Prints the content of all registers
@@ -133,78 +113,8 @@ impl TFLuna {
         Ok(())
     }
 
-    fn get_frame_rate(&mut self) -> Result<u16, Error> {
-        self.read_two_byte_value(constants::FRAMERATE_REGISTER_ADDRESS)
-    }
-
-    fn get_slave_address(&mut self) -> Result<u8, Error> {
-        self.read_register(constants::SLAVE_ADDRESS_REGISTER_ADDRESS)
-    }
-
-    fn get_ranging_mode(&mut self) -> Result<RangingMode, Error> {
-        match self.read_register(constants::RANGING_MODE_REGISTER_ADDRESS)? {
-            0 => Ok(RangingMode::Continuous),
-            1 => Ok(RangingMode::Trigger),
-            _ => Err(Error::FeatureNotSupported),
-        }
-    }
-    fn get_enable(&mut self) -> Result<bool, Error> {
-        match self.read_register(constants::ENABLE_REGISTER_ADDRESS)? {
-            0 => Ok(false),
-            1 => Ok(true),
-            _ => Err(Error::FeatureNotSupported),
-        }
-    }
-
-    fn get_power_mode(&mut self) -> Result<PowerMode, Error> {
-        match self.read_register(constants::POWER_MODE_REGISTER_ADDRESS)? {
-            0 => Ok(PowerMode::Normal),
-            1 => Ok(PowerMode::LowPower),
-            _ => Err(Error::FeatureNotSupported),
-        }
-    }
-
-    fn get_signal_strength_threshold(&mut self) -> Result<u16, Error> {
-        self.read_two_byte_value(constants::SIGNAL_STRENGTH_THRESHOLD_REGISTER_ADDRESS)
-    }
-
-    fn get_dummy_distance(&mut self) -> Result<u16, Error> {
-        self.read_two_byte_value(constants::DUMMY_DISTANCE_REGISTER_ADDRESS)
-    }
-
-    fn get_minimum_distance(&mut self) -> Result<u16, Error> {
-        self.read_two_byte_value(constants::MINIMUM_DISTANCE_REGISTER_ADDRESS)
-    }
-
-    fn get_maximum_distance(&mut self) -> Result<u16, Error> {
-        self.read_two_byte_value(constants::MAXIMUM_DISTANCE_REGISTER_ADDRESS)
-    }
-
-    // Print important information
+    // Reads all the configuration registers
     pub fn get_device_information(&mut self) -> Result<TFLunaInformation, Error> {
-        let firmware_version = self.get_firmware_version()?;
-        let serial_number = self.get_serial_number()?;
-        let frame_rate = self.get_frame_rate()?;
-        let slave_address = self.get_slave_address()?;
-        let mode = self.get_ranging_mode()?;
-        let enable = self.get_enable()?;
-        let power_mode = self.get_power_mode()?;
-        let signal_strength_threshold = self.get_signal_strength_threshold()?;
-        let dummy_distance = self.get_dummy_distance()?;
-        let minimum_distance = self.get_minimum_distance()?;
-        let maximum_distance = self.get_maximum_distance()?;
-        Ok(TFLunaInformation {
-            firmware_version,
-            serial_number,
-            frame_rate,
-            slave_address,
-            mode,
-            enable,
-            power_mode,
-            signal_strength_threshold,
-            dummy_distance,
-            minimum_distance,
-            maximum_distance,
-        })
+        TFLunaInformation::read(|register_address| self.read_register(register_address))
     }
 }
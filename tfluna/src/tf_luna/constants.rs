@@ -2,8 +2,11 @@ pub const DISTANCE_REGISTER_ADDRESS: u8 = 0x00;
 pub const SIGNAL_STRENGTH_REGISTER_ADDRESS: u8 = 0x02;
 pub const TEMPERATURE_REGISTER_ADDRESS: u8 = 0x04;
 pub const TIMESTAMP_REGISTER_ADDRESS: u8 = 0x06;
+pub const FIRMWARE_VERSION_REGISTER_ADDRESS: u8 = 0x0A;
+pub const SERIAL_NUMBER_REGISTER_ADDRESS: u8 = 0x10;
 pub const SLAVE_ADDRESS_REGISTER_ADDRESS: u8 = 0x22;
 pub const RANGING_MODE_REGISTER_ADDRESS: u8 = 0x23;
+pub const TRIGGER_REGISTER_ADDRESS: u8 = 0x24;
 pub const ENABLE_REGISTER_ADDRESS: u8 = 0x25;
 pub const FRAMERATE_REGISTER_ADDRESS: u8 = 0x26;
 pub const POWER_MODE_REGISTER_ADDRESS: u8 = 0x28;
@@ -11,3 +14,4 @@ pub const SIGNAL_STRENGTH_THRESHOLD_REGISTER_ADDRESS: u8 = 0x2A;
 pub const DUMMY_DISTANCE_REGISTER_ADDRESS: u8 = 0x2C;
 pub const MINIMUM_DISTANCE_REGISTER_ADDRESS: u8 = 0x2E;
 pub const MAXIMUM_DISTANCE_REGISTER_ADDRESS: u8 = 0x30;
+pub const SIGNATURE_REGISTER_ADDRESS: u8 = 0x3C;
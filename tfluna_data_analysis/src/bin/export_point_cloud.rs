@@ -8,6 +8,13 @@ use rerun::dataframe::{QueryEngine, QueryExpression, SparseFillStrategy, Timelin
 use rerun::external::arrow::compute::concat_batches;
 
 use tfluna_data_analysis::convert::rerun_batches_to_polars;
+use tfluna_data_analysis::reconstruct::{
+    kinematics_from_properties, point_records, with_reconstructed_positions,
+};
+use tfluna_pan_tilt::coloring::PointColorizer;
+use tfluna_pan_tilt::config::ColorConfig;
+use tfluna_pan_tilt::point_cloud::{PointCloudFormat, save_point_cloud};
+use tfluna_pan_tilt::range_image::{RangeImage, RangeImageFormat};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let data_file = "data/pan_tilt.rrd";
@@ -57,9 +64,10 @@ fn main() -> Result<(), Box<dyn Error>> {
             .f64()?
             .first()
             .ok_or(PolarsError::NoData("No data for servo_motor_delay".into()))?;
+        let kinematics = kinematics_from_properties(&batch_df)?;
 
         // Filtering and preparing dataframe
-        let mut batch_df = batch_df.lazy().select([
+        let mut columns = vec![
             col("capture_time"),
             col("/distance:Scalars:scalars").list().first().alias("distance"),
             col("/pitch:Scalars:scalars").list().first().alias("pitch"),
@@ -68,11 +76,14 @@ fn main() -> Result<(), Box<dyn Error>> {
             col("/yaw:Scalars:scalars").list().first().alias("yaw"),
             col("property:angle_step:Scalars:scalars").list().first().alias("angle_step"),
             col("property:servo_motor_delay:Scalars:scalars").list().first().alias("servo_motor_delay"),
-            col("/position:Points3D:positions").list().last().arr().to_list().list().first().alias("x"),
-            col("/position:Points3D:positions").list().last().arr().to_list().list().get(1.into(), true).alias("y"),
-            col("/position:Points3D:positions").list().last().arr().to_list().list().last().alias("z"),
-        ])
-        .collect()?;
+        ];
+        // Only logged by recordings made since several samples can be taken per pose
+        if batch_df.column("/distance_spread:Scalars:scalars").is_ok() {
+            columns.push(col("/distance_spread:Scalars:scalars").list().first().alias("distance_spread"));
+        }
+        let batch_df = batch_df.lazy().select(columns).collect()?;
+        // Point positions are computed from the angles and distance with the scanner's kinematic model
+        let mut batch_df = with_reconstructed_positions(batch_df, &kinematics)?;
         println!("Dataframe: {}", batch_df);
 
         println!("Writing dataframe to csv file");
@@ -86,6 +97,32 @@ fn main() -> Result<(), Box<dyn Error>> {
             .with_separator(b',')
             .finish(&mut batch_df)?;
 
+        println!("Writing point cloud to ply and las files");
+        let records = point_records(&batch_df, &kinematics)?;
+        // Colored by distance, as during the scan
+        let colors = PointColorizer::colors(&ColorConfig::default(), &records);
+        for (extension, format) in [
+            ("ply", PointCloudFormat::PlyBinary),
+            ("las", PointCloudFormat::Las),
+        ] {
+            let filepath = format!(
+                "data/point_cloud_{}deg_{}ms.{}",
+                angle_step_val, servo_delay_val, extension
+            );
+            save_point_cloud(filepath, &records, Some(&colors), Some(format))?;
+        }
+
+        println!("Writing range images to png and npy files");
+        let range_image =
+            RangeImage::from_records(&records, angle_step_val as f32, angle_step_val as f32);
+        let prefix = format!(
+            "data/range_image_{}deg_{}ms",
+            angle_step_val, servo_delay_val
+        );
+        for format in [RangeImageFormat::Png, RangeImageFormat::Npy] {
+            range_image.save(&prefix, format)?;
+        }
+
         //plot_point_cloud(point_cloud_positions, angle_step_val, servo_delay_val)?;
     }
 
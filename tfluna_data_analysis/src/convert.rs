@@ -9,17 +9,25 @@ pub fn rerun_batches_to_polars(batches: &[RecordBatch]) -> PolarsResult<DataFram
         return Err(PolarsError::ComputeError("No batches provided".into()));
     }
 
-    // Serialize all batches with Rerun's Arrow
+    // Recordings can have different entities, e.g. a different number of point cloud rows,
+    // so each batch is converted on its own and missing columns are filled with nulls
+    let dfs = batches
+        .iter()
+        .map(rerun_batch_to_polars)
+        .collect::<PolarsResult<Vec<_>>>()?;
+    polars::functions::concat_df_diagonal(&dfs)
+}
+
+fn rerun_batch_to_polars(batch: &RecordBatch) -> PolarsResult<DataFrame> {
+    // Serialize batch with Rerun's Arrow
     let mut buffer = Vec::new();
     {
-        let mut writer = FileWriter::try_new(&mut buffer, &batches[0].schema())
+        let mut writer = FileWriter::try_new(&mut buffer, &batch.schema())
             .map_err(|e| PolarsError::ComputeError(e.to_string().into()))?;
 
-        for batch in batches {
-            writer
-                .write(batch)
-                .map_err(|e| PolarsError::ComputeError(e.to_string().into()))?;
-        }
+        writer
+            .write(batch)
+            .map_err(|e| PolarsError::ComputeError(e.to_string().into()))?;
         writer
             .finish()
             .map_err(|e| PolarsError::ComputeError(e.to_string().into()))?;
@@ -1,6 +1,10 @@
 use nalgebra::{DMatrix, DVector};
 use polars::prelude::*;
 
+use crate::events::with_segments;
+use crate::metadata::{METADATA_COLUMNS, metadata_columns};
+use crate::reconstruct::logged_positions;
+
 #[derive(Debug, Clone)]
 pub struct Plane {
     pub a: f64,
@@ -96,12 +100,30 @@ pub struct PlaneMetrics {
     y_intercept_error: f64,
 }
 
-/// Analyze the dataframe grouped by angle_step and servo_motor_delay
+/// Column containing the number of measurements made at each pose
+pub const SAMPLES_PER_POSE_COLUMN: &str = "property:samples_per_pose:Scalars:scalars";
+
+/// Expression of the "samples_per_pose" parameter.
+/// Recordings made before it was configurable took a single sample per pose.
+pub fn samples_per_pose(df: &DataFrame) -> Expr {
+    match df.column(SAMPLES_PER_POSE_COLUMN) {
+        Ok(_) => col(SAMPLES_PER_POSE_COLUMN).list().first(),
+        Err(_) => lit(1.0),
+    }
+    .alias("samples_per_pose")
+}
+
+/// Analyze the dataframe grouped by angle_step, servo_motor_delay and samples_per_pose,
+/// keeping the metadata of each recording, see `metadata::METADATA_COLUMNS`.
+/// Each scan is analyzed on its own, with the outcome of its segment, see `events::with_segments`.
 pub fn analyze_experiment(df: DataFrame) -> PolarsResult<DataFrame> {
     // First, extract the property columns (they should be constant per recording)
-    // Get unique angle_step and servo_motor_delay values per group
-    let df_with_params = df
+    // Get unique angle_step, servo_motor_delay and samples_per_pose values per group
+    let samples_per_pose = samples_per_pose(&df);
+    let metadata = metadata_columns(&df);
+    let df_with_params = with_segments(df)?
         .lazy()
+        .with_columns(metadata)
         .with_columns([
             col("property:angle_step:Scalars:scalars")
                 .list()
@@ -111,17 +133,35 @@ pub fn analyze_experiment(df: DataFrame) -> PolarsResult<DataFrame> {
                 .list()
                 .first()
                 .alias("servo_motor_delay"),
+            samples_per_pose,
             col("property:RecordingInfo:start_time")
                 .list()
                 .first()
                 .alias("start_time"),
         ])
+        // Rows logged before a scan started hold no measurements,
+        // recordings without events are a single segment
+        .filter(
+            col("segment")
+                .is_not_null()
+                .or(col("segment").count().over([col("start_time")]).eq(lit(0))),
+        )
         .collect()?;
 
     // Group by the parameters
     // We'll need to do custom aggregation for plane fitting
     // For that we collect the data we need per group
-    let grouped = df_with_params.group_by(["start_time", "angle_step", "servo_motor_delay"])?;
+    // The metadata is constant per recording, grouping by it keeps it in the results
+    let mut keys = vec![
+        "start_time",
+        "segment",
+        "segment_outcome",
+        "angle_step",
+        "servo_motor_delay",
+        "samples_per_pose",
+    ];
+    keys.extend(METADATA_COLUMNS);
+    let grouped = df_with_params.group_by(keys)?;
 
     let dataframe = grouped.apply(process_group);
     dataframe
@@ -130,6 +170,7 @@ pub fn analyze_experiment(df: DataFrame) -> PolarsResult<DataFrame> {
 fn process_group(df: DataFrame) -> PolarsResult<DataFrame> {
     let mut angle_steps = Vec::new();
     let mut servo_delays = Vec::new();
+    let mut samples_per_poses = Vec::new();
     let mut total_times = Vec::new();
     let mut plane_a = Vec::new();
     let mut plane_b = Vec::new();
@@ -141,7 +182,7 @@ fn process_group(df: DataFrame) -> PolarsResult<DataFrame> {
     let mut y_intercept_errors = Vec::new();
     let mut num_points = Vec::new();
 
-    // Extract angle_step and servo_delay (should be same for all rows in group)
+    // Extract angle_step, servo_delay and samples_per_pose (should be same for all rows in group)
     let angle_step_col = df.column("angle_step")?;
     let servo_delay_col = df.column("servo_motor_delay")?;
 
@@ -153,6 +194,11 @@ fn process_group(df: DataFrame) -> PolarsResult<DataFrame> {
         .f64()?
         .first()
         .ok_or(PolarsError::NoData("No data for servo_motor_delay".into()))?;
+    let samples_per_pose_val = df
+        .column("samples_per_pose")?
+        .f64()?
+        .first()
+        .ok_or(PolarsError::NoData("No data for samples_per_pose".into()))?;
 
     // Calculate total time in seconds
     let capture_time_col = df.column("capture_time")?;
@@ -165,35 +211,14 @@ fn process_group(df: DataFrame) -> PolarsResult<DataFrame> {
     let total_time_s = total_time_ns as f64 * 10e-9;
 
     // Extract positions for plane fitting
-    let all_positions = {
-        let positions_col = df.column("/position:Points3D:positions")?;
-        let positions_series = positions_col.as_materialized_series();
-        let positions_list = positions_series.list()?;
-        let mut all_positions = Vec::new();
-        // Get last one since it contains all points
-        let series = positions_list
-            .get_as_series(positions_list.len() - 1)
-            .unwrap();
-        if let Ok(inner_array) = series.array() {
-            for j in 0..inner_array.len() {
-                if let Some(point_series) = inner_array.get_as_series(j) {
-                    if let Ok(floats) = point_series.f32() {
-                        let point: Vec<f32> = floats.into_iter().filter_map(|x| x).collect();
-                        if point.len() >= 3 {
-                            all_positions.push(point);
-                        }
-                    }
-                }
-            }
-        }
-        all_positions
-    };
+    let all_positions: Vec<Vec<f32>> = logged_positions(&df)?.into_iter().map(Vec::from).collect();
     // Fit plane
     let plane = fit_plane(&all_positions).unwrap();
     let metrics = calculate_plane_metrics(plane);
 
     angle_steps.push(angle_step_val);
     servo_delays.push(servo_delay_val);
+    samples_per_poses.push(samples_per_pose_val);
     total_times.push(total_time_s);
     plane_a.push(metrics.a);
     plane_b.push(metrics.b);
@@ -205,10 +230,10 @@ fn process_group(df: DataFrame) -> PolarsResult<DataFrame> {
     y_intercept_errors.push(metrics.y_intercept_error);
     num_points.push(all_positions.len() as f64);
 
-    // Create result dataframe
-    DataFrame::new(vec![
+    let mut columns: Vec<Column> = vec![
         Series::new("angle_step".into(), angle_steps).into(),
         Series::new("servo_motor_delay".into(), servo_delays).into(),
+        Series::new("samples_per_pose".into(), samples_per_poses).into(),
         Series::new("total_time_s".into(), total_times).into(),
         Series::new("num_points".into(), num_points).into(),
         Series::new("plane_a".into(), plane_a).into(),
@@ -219,15 +244,35 @@ fn process_group(df: DataFrame) -> PolarsResult<DataFrame> {
         Series::new("distance_error".into(), distance_errors).into(),
         Series::new("y_intercept".into(), y_intercepts).into(),
         Series::new("y_intercept_error".into(), y_intercept_errors).into(),
-    ])
+    ];
+    let segment_outcome = df
+        .column("segment_outcome")?
+        .str()?
+        .get(0)
+        .map(String::from);
+    columns.push(Series::new("segment_outcome".into(), [segment_outcome]).into());
+    for name in METADATA_COLUMNS {
+        let value = df.column(name)?.str()?.get(0).map(String::from);
+        columns.push(Series::new(name.into(), [value]).into());
+    }
+
+    // Create result dataframe
+    DataFrame::new(columns)
 }
 
-/// Calculate repeatability statistics for each parameter combination
+/// Calculate repeatability statistics for each parameter combination and device,
+/// since recordings of different sensors or firmware versions are not repetitions
 pub fn calculate_repeatability(results_df: &DataFrame) -> PolarsResult<DataFrame> {
     results_df
         .clone()
         .lazy()
-        .group_by([col("angle_step"), col("servo_motor_delay")])
+        .group_by([
+            col("angle_step"),
+            col("servo_motor_delay"),
+            col("samples_per_pose"),
+            col("firmware_version"),
+            col("serial_number"),
+        ])
         .agg([
             // Time statistics
             col("total_time_s").mean().alias("avg_time_s"),
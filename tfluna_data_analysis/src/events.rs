@@ -0,0 +1,50 @@
+use polars::prelude::*;
+use tfluna_pan_tilt::events::{
+    EVENTS_ENTITY_PATH, SCAN_ABORTED, SCAN_COMPLETED, SCAN_FAILED, SCAN_STARTED,
+};
+
+/// Column containing the sequence number of the latest event of a kind, see `events::EventLog`
+pub fn event_column(kind: &str) -> String {
+    format!("/{EVENTS_ENTITY_PATH}/{kind}:Scalars:scalars")
+}
+
+/// Expression of the sequence number of the latest event of a kind,
+/// null before the first one and in recordings without such events
+fn event_sequence(df: &DataFrame, kind: &str) -> Expr {
+    let column = event_column(kind);
+    match df.column(&column) {
+        Ok(_) => col(column).list().first(),
+        Err(_) => lit(NULL).cast(DataType::Float64),
+    }
+}
+
+/// Add a "segment" column, the sequence number of the event that started the scan of each row,
+/// and a "segment_outcome" column, how that scan ended: "completed", "aborted", "failed",
+/// or null when the recording ends before the scan does.
+///
+/// Rows logged before the scan started, e.g. the properties, get a null segment,
+/// as do all the rows of recordings made before the events were logged.
+/// The sequence numbers restart with each run, so the parts of a resumed scan share their segment.
+pub fn with_segments(df: DataFrame) -> PolarsResult<DataFrame> {
+    let recording = col("property:RecordingInfo:start_time").list().first();
+    // A scan ended with an event of the given kind if one came after its start
+    let ended_with = |kind: &str| {
+        event_sequence(&df, kind)
+            .max()
+            .over([recording.clone(), col("segment")])
+            .gt(col("segment"))
+    };
+    let outcome = when(ended_with(SCAN_COMPLETED))
+        .then(lit("completed"))
+        .when(ended_with(SCAN_ABORTED))
+        .then(lit("aborted"))
+        .when(ended_with(SCAN_FAILED))
+        .then(lit("failed"))
+        .otherwise(lit(NULL).cast(DataType::String))
+        .alias("segment_outcome");
+    df.clone()
+        .lazy()
+        .with_column(event_sequence(&df, SCAN_STARTED).alias("segment"))
+        .with_column(outcome)
+        .collect()
+}
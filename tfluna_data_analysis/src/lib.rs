@@ -1,3 +1,6 @@
 pub mod convert;
 pub mod evaluate;
+pub mod events;
+pub mod metadata;
 pub mod plot;
+pub mod reconstruct;
@@ -0,0 +1,35 @@
+use polars::prelude::*;
+use tfluna_pan_tilt::metadata::{
+    CRATE_VERSION_PROPERTY, FIRMWARE_VERSION_PROPERTY, GIT_COMMIT_PROPERTY, HOSTNAME_PROPERTY,
+    SERIAL_NUMBER_PROPERTY,
+};
+
+/// Columns added by `metadata_columns`, named after the recording properties they come from
+pub const METADATA_COLUMNS: [&str; 5] = [
+    FIRMWARE_VERSION_PROPERTY,
+    SERIAL_NUMBER_PROPERTY,
+    HOSTNAME_PROPERTY,
+    CRATE_VERSION_PROPERTY,
+    GIT_COMMIT_PROPERTY,
+];
+
+/// Column containing a text property sent by the scanner
+pub fn text_property_column(name: &str) -> String {
+    format!("property:{name}:TextDocument:text")
+}
+
+/// Expressions of the metadata columns, e.g. to group results by firmware or device.
+/// Recordings made before the metadata was sent, or without sensor information, get nulls.
+pub fn metadata_columns(df: &DataFrame) -> Vec<Expr> {
+    METADATA_COLUMNS
+        .iter()
+        .map(|name| {
+            let column = text_property_column(name);
+            match df.column(&column) {
+                Ok(_) => col(column).list().first(),
+                Err(_) => lit(NULL).cast(DataType::String),
+            }
+            .alias(*name)
+        })
+        .collect()
+}
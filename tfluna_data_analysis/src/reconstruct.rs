@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use polars::prelude::*;
+use tfluna_pan_tilt::kinematics::PanTiltKinematics;
+use tfluna_pan_tilt::point_cloud::PointRecord;
+use tfluna_pan_tilt::point_cloud_logger::SAMPLE_TIMELINE;
+
+/// Column containing the kinematic model parameters sent by the scanner
+pub const KINEMATICS_COLUMN: &str = "property:kinematics:Scalars:scalars";
+
+/// Entity under which the scanner logs the point cloud
+pub const POSITION_ENTITY_PATH: &str = "/position";
+
+/// Entity to which the scanner logs the points one by one with the `sample` logging strategy
+pub const SAMPLES_ENTITY_PATH: &str = "/position/samples";
+
+/// Read the kinematic model stored in the recording properties.
+/// Recordings made before the model was configurable fall back to the default model.
+pub fn kinematics_from_properties(df: &DataFrame) -> PolarsResult<PanTiltKinematics> {
+    let Ok(column) = df.column(KINEMATICS_COLUMN) else {
+        return Ok(PanTiltKinematics::default());
+    };
+    let parameters = column
+        .as_materialized_series()
+        .list()?
+        .into_iter()
+        .flatten()
+        .next()
+        .ok_or(PolarsError::NoData("No data for kinematics".into()))?;
+    let parameters: Vec<f32> = parameters
+        .f64()?
+        .into_iter()
+        .flatten()
+        .map(|x| x as f32)
+        .collect();
+    PanTiltKinematics::from_parameters(&parameters).ok_or(PolarsError::ComputeError(
+        format!(
+            "Unexpected number of kinematic parameters: {}",
+            parameters.len()
+        )
+        .into(),
+    ))
+}
+
+/// Compute the 3D position of each row from its "yaw", "pitch" and "distance" columns.
+/// Rows with a missing value get a missing position.
+pub fn reconstruct_positions(
+    df: &DataFrame,
+    kinematics: &PanTiltKinematics,
+) -> PolarsResult<Vec<Option<[f32; 3]>>> {
+    let yaws = df.column("yaw")?.f64()?;
+    let pitches = df.column("pitch")?.f64()?;
+    let distances = df.column("distance")?.f64()?;
+
+    let positions = yaws
+        .into_iter()
+        .zip(pitches.into_iter())
+        .zip(distances.into_iter())
+        .map(|((yaw, pitch), distance)| match (yaw, pitch, distance) {
+            (Some(yaw), Some(pitch), Some(distance)) => Some(
+                kinematics
+                    .pose(yaw as f32, pitch as f32)
+                    .point_at(distance as f32),
+            ),
+            _ => None,
+        })
+        .collect();
+    Ok(positions)
+}
+
+/// Add "x", "y" and "z" columns computed with `reconstruct_positions`
+pub fn with_reconstructed_positions(
+    mut df: DataFrame,
+    kinematics: &PanTiltKinematics,
+) -> PolarsResult<DataFrame> {
+    let positions = reconstruct_positions(&df, kinematics)?;
+    for (i, name) in ["x", "y", "z"].into_iter().enumerate() {
+        let values: Vec<Option<f32>> = positions.iter().map(|p| p.map(|p| p[i])).collect();
+        df.with_column(Series::new(name.into(), values))?;
+    }
+    Ok(df)
+}
+
+/// Convert the rows of the dataframe into point cloud records.
+///
+/// Each measurement is logged as several entities at the same capture time,
+/// so only the last row of each capture time holds the complete measurement.
+/// A resumed scan logs the measurements made before the interruption again,
+/// which is why rows are matched by capture time over the whole dataframe.
+/// Rows with missing values are skipped.
+pub fn point_records(
+    df: &DataFrame,
+    kinematics: &PanTiltKinematics,
+) -> PolarsResult<Vec<PointRecord>> {
+    let positions = reconstruct_positions(df, kinematics)?;
+    let capture_times = df
+        .column("capture_time")?
+        .as_materialized_series()
+        .timestamp(TimeUnit::Nanoseconds)?;
+    let distances = df.column("distance")?.f64()?;
+    let signal_strengths = df.column("signal_strength")?.f64()?;
+    let temperatures = df.column("temperature")?.f64()?;
+    let yaws = df.column("yaw")?.f64()?;
+    let pitches = df.column("pitch")?.f64()?;
+    // Only recorded since several samples can be taken per pose
+    let distance_spreads = match df.column("distance_spread") {
+        Ok(column) => Some(column.f64()?),
+        Err(_) => None,
+    };
+
+    let mut records: Vec<PointRecord> = Vec::new();
+    let mut record_indices: HashMap<i64, usize> = HashMap::new();
+    for i in 0..df.height() {
+        let (
+            Some(position),
+            Some(capture_time),
+            Some(distance),
+            Some(signal_strength),
+            Some(temperature),
+            Some(yaw),
+            Some(pitch),
+        ) = (
+            positions[i],
+            capture_times.get(i),
+            distances.get(i),
+            signal_strengths.get(i),
+            temperatures.get(i),
+            yaws.get(i),
+            pitches.get(i),
+        )
+        else {
+            continue;
+        };
+        let record = PointRecord {
+            position,
+            distance: distance as u16,
+            signal_strength: signal_strength as u16,
+            temperature: temperature as f32,
+            yaw: yaw as f32,
+            pitch: pitch as f32,
+            capture_time: capture_time as f64 * 1e-9,
+            distance_spread: distance_spreads
+                .and_then(|spreads| spreads.get(i))
+                .unwrap_or(0.0) as f32,
+        };
+        // Replace the earlier row if it belongs to the same measurement
+        match record_indices.get(&capture_time) {
+            Some(&index) => records[index] = record,
+            None => {
+                record_indices.insert(capture_time, records.len());
+                records.push(record);
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// Collect the point cloud logged by the scanner.
+///
+/// The scanner logs the points incrementally, one entity per row under `/position`,
+/// whose latest value holds the points of the row,
+/// or every point to `/position/samples` at its own step of the sample timeline.
+/// Recordings made before points were logged one by one resent the growing row after every point,
+/// whose latest value also holds all the points of that row.
+/// Older recordings logged the whole cloud to `/position` itself,
+/// in which case its latest value holds all the points.
+pub fn logged_positions(df: &DataFrame) -> PolarsResult<Vec<[f32; 3]>> {
+    let mut positions = Vec::new();
+    for column in df.get_columns() {
+        let name = column.name().as_str();
+        let is_position_column = name.ends_with(":Points3D:positions")
+            && (name.starts_with(&format!("{POSITION_ENTITY_PATH}:"))
+                || name.starts_with(&format!("{POSITION_ENTITY_PATH}/")));
+        if !is_position_column {
+            continue;
+        }
+        let positions_list = column.as_materialized_series().list()?;
+        if name.starts_with(&format!("{SAMPLES_ENTITY_PATH}:")) {
+            // Rows are filled with the latest sample, so the last row of each step holds its point
+            let steps = df.column(SAMPLE_TIMELINE)?.i64()?;
+            let mut step_indices = HashMap::new();
+            let mut samples = Vec::new();
+            for (step, series) in steps.into_iter().zip(positions_list.into_iter()) {
+                let (Some(step), Some(series)) = (step, series) else {
+                    continue;
+                };
+                match step_indices.get(&step) {
+                    Some(&index) => samples[index] = series,
+                    None => {
+                        step_indices.insert(step, samples.len());
+                        samples.push(series);
+                    }
+                }
+            }
+            for series in samples {
+                push_points(&series, &mut positions)?;
+            }
+        } else if let Some(series) = positions_list.into_iter().flatten().last() {
+            push_points(&series, &mut positions)?;
+        }
+    }
+    Ok(positions)
+}
+
+// Append the points of a single Points3D value
+fn push_points(series: &Series, positions: &mut Vec<[f32; 3]>) -> PolarsResult<()> {
+    let inner_array = series.array()?;
+    for j in 0..inner_array.len() {
+        if let Some(point_series) = inner_array.get_as_series(j) {
+            let point: Vec<f32> = point_series.f32()?.into_iter().flatten().collect();
+            if point.len() >= 3 {
+                positions.push([point[0], point[1], point[2]]);
+            }
+        }
+    }
+    Ok(())
+}
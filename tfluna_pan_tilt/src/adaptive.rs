@@ -0,0 +1,260 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::scan::{GridPose, ScanPattern, ScanPlanner, grid_angle_count};
+
+/// Largest supported value of `AdaptiveSettings::max_depth`
+pub const MAX_DEPTH: u32 = 16;
+
+/// Settings of adaptive-resolution scans
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdaptiveSettings {
+    /// Number of times a cell of the coarse grid can be subdivided,
+    /// the finest spacing is `angle_step / 2^max_depth`
+    pub max_depth: u32,
+    /// A cell is subdivided when the distances at its corners differ by more than this, in centimeters
+    pub distance_threshold: f32,
+    /// A cell is subdivided when the signal strength at one of its corners is below this,
+    /// the TF-Luna reports unreliable distances under 100
+    pub min_signal_strength: u16,
+    /// Maximum number of poses of the whole scan, coarse grid included
+    pub max_poses: Option<usize>,
+    /// Maximum duration of the scan in seconds, no new pose is measured afterwards
+    pub time_budget: Option<u32>,
+}
+
+impl Default for AdaptiveSettings {
+    fn default() -> Self {
+        AdaptiveSettings {
+            max_depth: 3,
+            distance_threshold: 10.0,
+            min_signal_strength: 100,
+            max_poses: None,
+            time_budget: None,
+        }
+    }
+}
+
+/// Square cell of the scan, given by its lowest corner and its size
+/// in lattice units (see `AdaptivePlanner`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    yaw: u32,
+    pitch: u32,
+    size: u32,
+}
+
+impl Cell {
+    fn corners(&self) -> [(u32, u32); 4] {
+        let (yaw, pitch, size) = (self.yaw, self.pitch, self.size);
+        [
+            (yaw, pitch),
+            (yaw + size, pitch),
+            (yaw, pitch + size),
+            (yaw + size, pitch + size),
+        ]
+    }
+
+    fn children(&self) -> [Cell; 4] {
+        let half = self.size / 2;
+        [(0, 0), (half, 0), (0, half), (half, half)].map(|(yaw, pitch)| Cell {
+            yaw: self.yaw + yaw,
+            pitch: self.pitch + pitch,
+            size: half,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Sample {
+    distance: u16,
+    signal_strength: u16,
+}
+
+/// Plans an adaptive-resolution scan, one batch of poses per refinement level.
+///
+/// The first batch is a coarse grid with a spacing of `angle_step`.
+/// Each following batch subdivides the cells of the previous level whose corner
+/// distances differ by more than `distance_threshold` or whose signal strength
+/// is marginal, which adds the midpoints of their edges and their centre.
+/// Cells with the largest distance differences are refined first,
+/// so that the pose budget goes to the most pronounced edges.
+///
+/// Poses live on a lattice whose spacing is the finest one, `angle_step / 2^max_depth`,
+/// so that the vertices shared by neighbouring cells are only measured once.
+#[derive(Debug, Clone)]
+pub struct AdaptivePlanner {
+    settings: AdaptiveSettings,
+    pattern: ScanPattern,
+    yaw_min: f32,
+    pitch_min: f32,
+    /// Lattice spacing in degrees
+    unit: f32,
+    /// Lattice vertices planned so far, with their measurement once it has been recorded
+    samples: HashMap<(u32, u32), Option<Sample>>,
+    /// Cells of the last planned level, `None` before the coarse grid is planned
+    cells: Option<Vec<Cell>>,
+    rows: usize,
+    // Coarse grid, planned by the first call to `next_poses`
+    coarse_yaw_count: u32,
+    coarse_pitch_count: u32,
+}
+
+impl AdaptivePlanner {
+    /// Ranges are given as (minimum, maximum) in degrees.
+    pub fn new(
+        yaw_range: (f32, f32),
+        pitch_range: (f32, f32),
+        angle_step: f32,
+        pattern: ScanPattern,
+        mut settings: AdaptiveSettings,
+    ) -> Self {
+        // Keeps the lattice indices within u32
+        settings.max_depth = settings.max_depth.min(MAX_DEPTH);
+        AdaptivePlanner {
+            pattern,
+            yaw_min: yaw_range.0,
+            pitch_min: pitch_range.0,
+            unit: angle_step / (1u32 << settings.max_depth) as f32,
+            samples: HashMap::new(),
+            cells: None,
+            rows: 0,
+            coarse_yaw_count: grid_angle_count(yaw_range, angle_step) as u32,
+            coarse_pitch_count: grid_angle_count(pitch_range, angle_step) as u32,
+            settings,
+        }
+    }
+
+    fn coarse_grid(&self) -> (Vec<Cell>, Vec<(u32, u32)>) {
+        let size = 1 << self.settings.max_depth;
+        let mut cells = Vec::new();
+        let mut vertices = Vec::new();
+        for i in 0..self.coarse_yaw_count {
+            for j in 0..self.coarse_pitch_count {
+                vertices.push((i * size, j * size));
+                if i + 1 < self.coarse_yaw_count && j + 1 < self.coarse_pitch_count {
+                    cells.push(Cell {
+                        yaw: i * size,
+                        pitch: j * size,
+                        size,
+                    });
+                }
+            }
+        }
+        (cells, vertices)
+    }
+
+    // Difference between the largest and smallest corner distance of a cell,
+    // or `None` if the cell should not be subdivided
+    fn refinement_score(&self, cell: &Cell) -> Option<u16> {
+        if cell.size < 2 {
+            return None;
+        }
+        let mut corners = Vec::with_capacity(4);
+        for corner in cell.corners() {
+            // Corners that were left out because of the budget are never refined
+            corners.push(self.samples.get(&corner).copied().flatten()?);
+        }
+        let min = corners.iter().map(|s| s.distance).min()?;
+        let max = corners.iter().map(|s| s.distance).max()?;
+        let is_edge = (max - min) as f32 > self.settings.distance_threshold;
+        let is_marginal = corners
+            .iter()
+            .any(|s| s.signal_strength < self.settings.min_signal_strength);
+        (is_edge || is_marginal).then_some(max - min)
+    }
+
+    // Sorts vertices into rows of constant yaw, in the order given by the scan pattern
+    fn order(&mut self, mut vertices: Vec<(u32, u32)>) -> Vec<GridPose> {
+        vertices.sort_unstable();
+        let mut poses: Vec<GridPose> = Vec::with_capacity(vertices.len());
+        for row in vertices.chunk_by(|a, b| a.0 == b.0) {
+            let go_up = self.pattern == ScanPattern::Serpentine && self.rows % 2 == 1;
+            let mut row = row.to_vec();
+            if !go_up {
+                row.reverse();
+            }
+            for (column, (yaw, pitch)) in row.into_iter().enumerate() {
+                poses.push(GridPose {
+                    row: self.rows,
+                    column,
+                    yaw: self.yaw_min + yaw as f32 * self.unit,
+                    pitch: self.pitch_min + pitch as f32 * self.unit,
+                });
+            }
+            self.rows += 1;
+        }
+        poses
+    }
+
+    fn remaining_poses(&self) -> usize {
+        self.settings
+            .max_poses
+            .map_or(usize::MAX, |max| max.saturating_sub(self.samples.len()))
+    }
+}
+
+impl ScanPlanner for AdaptivePlanner {
+    fn next_poses(&mut self) -> Vec<GridPose> {
+        let (cells, vertices) = match &self.cells {
+            None => {
+                let (cells, mut vertices) = self.coarse_grid();
+                vertices.truncate(self.remaining_poses());
+                (cells, vertices)
+            }
+            Some(cells) => {
+                let mut candidates: Vec<(u16, Cell)> = cells
+                    .iter()
+                    .filter_map(|cell| Some((self.refinement_score(cell)?, *cell)))
+                    .collect();
+                // Most pronounced edges first, stable so that ties keep the grid order
+                candidates.sort_by_key(|(score, _)| Reverse(*score));
+
+                let mut remaining = self.remaining_poses();
+                let mut refined = Vec::new();
+                let mut vertices: Vec<(u32, u32)> = Vec::new();
+                for (_, cell) in candidates {
+                    let mut new_vertices = Vec::new();
+                    for vertex in cell.children().iter().flat_map(Cell::corners) {
+                        if !self.samples.contains_key(&vertex)
+                            && !vertices.contains(&vertex)
+                            && !new_vertices.contains(&vertex)
+                        {
+                            new_vertices.push(vertex);
+                        }
+                    }
+                    if new_vertices.len() > remaining {
+                        continue;
+                    }
+                    remaining -= new_vertices.len();
+                    vertices.extend(new_vertices);
+                    refined.extend(cell.children());
+                }
+                if refined.is_empty() {
+                    return Vec::new();
+                }
+                (refined, vertices)
+            }
+        };
+        for vertex in &vertices {
+            self.samples.insert(*vertex, None);
+        }
+        self.cells = Some(cells);
+        self.order(vertices)
+    }
+
+    fn record(&mut self, pose: &GridPose, distance: u16, signal_strength: u16) {
+        let yaw = ((pose.yaw - self.yaw_min) / self.unit).round() as u32;
+        let pitch = ((pose.pitch - self.pitch_min) / self.unit).round() as u32;
+        self.samples.insert(
+            (yaw, pitch),
+            Some(Sample {
+                distance,
+                signal_strength,
+            }),
+        );
+    }
+}
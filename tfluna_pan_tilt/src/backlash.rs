@@ -0,0 +1,247 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::sweep::edge_yaw;
+
+/// How the backlash of a servo is compensated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BacklashCompensation {
+    /// Targets are commanded as they are
+    None,
+    /// Targets are always approached from the side given by `BacklashConfig::approach`.
+    /// When coming from the other side, the servo first goes `overshoot` degrees past the target.
+    Overshoot,
+    /// Half of the backlash is added in the direction of motion,
+    /// which cancels the lag of the gears behind the commanded angle
+    Offset,
+}
+
+/// Side from which the overshoot compensation approaches targets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Approach {
+    /// Towards increasing angles
+    FromBelow,
+    /// Towards decreasing angles
+    FromAbove,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BacklashConfig {
+    pub compensation: BacklashCompensation,
+    /// Difference in degrees between the angles reached from below and from above
+    /// for the same command, see `estimate_backlash`
+    pub backlash: f32,
+    /// Only used by the overshoot compensation
+    pub approach: Approach,
+    /// Degrees past the target of the overshoot compensation, should be larger than the backlash
+    pub overshoot: f32,
+    /// Time in milliseconds given to the servo to reach the overshoot before going to the target
+    pub overshoot_delay: u32,
+}
+
+impl Default for BacklashConfig {
+    fn default() -> Self {
+        BacklashConfig {
+            compensation: BacklashCompensation::None,
+            backlash: 0.0,
+            approach: Approach::FromBelow,
+            overshoot: 2.0,
+            overshoot_delay: 100,
+        }
+    }
+}
+
+impl BacklashConfig {
+    pub fn overshoot_delay(&self) -> Duration {
+        Duration::from_millis(self.overshoot_delay as u64)
+    }
+}
+
+/// Follows the direction in which a servo moves to compensate its backlash
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backlash {
+    config: BacklashConfig,
+    /// Last target, `None` before the first one
+    last_target: Option<f32>,
+    /// Whether the servo last moved towards increasing angles, `None` if unknown
+    increasing: Option<bool>,
+}
+
+impl Default for Backlash {
+    fn default() -> Self {
+        Backlash::new(BacklashConfig::default())
+    }
+}
+
+impl Backlash {
+    pub fn new(config: BacklashConfig) -> Self {
+        Backlash {
+            config,
+            last_target: None,
+            increasing: None,
+        }
+    }
+
+    pub fn config(&self) -> &BacklashConfig {
+        &self.config
+    }
+
+    /// Angles to command, in order, for the servo to end up at `target`,
+    /// all of them within `[min_angle, max_angle]`.
+    /// `BacklashConfig::overshoot_delay` must be waited between consecutive angles.
+    ///
+    /// The first target of the overshoot compensation is always approached with an overshoot,
+    /// since the side the servo comes from is unknown.
+    /// Near a limit, the overshoot is shortened to stay within it,
+    /// and a target at the limit is commanded without overshoot.
+    pub fn commands(&mut self, target: f32, min_angle: f32, max_angle: f32) -> Vec<f32> {
+        let increasing = match self.last_target {
+            Some(last) if target > last => Some(true),
+            Some(last) if target < last => Some(false),
+            // The gears stay on the side of the last motion
+            Some(_) => self.increasing,
+            None => None,
+        };
+        let half_backlash = self.config.backlash / 2.0;
+        let clamp = |angle: f32| angle.clamp(min_angle, max_angle);
+        let (commands, increasing) = match self.config.compensation {
+            BacklashCompensation::None => (vec![target], increasing),
+            BacklashCompensation::Offset => {
+                let command = match increasing {
+                    Some(true) => target + half_backlash,
+                    Some(false) => target - half_backlash,
+                    None => target,
+                };
+                (vec![clamp(command)], increasing)
+            }
+            BacklashCompensation::Overshoot => {
+                let from_below = self.config.approach == Approach::FromBelow;
+                let overshoot = clamp(match from_below {
+                    true => target - self.config.overshoot,
+                    false => target + self.config.overshoot,
+                });
+                match increasing == Some(from_below) {
+                    true => (vec![target], increasing),
+                    // The target is at the limit on the side of the approach
+                    false if overshoot == target => (vec![target], increasing),
+                    false => (vec![overshoot, target], Some(from_below)),
+                }
+            }
+        };
+        self.last_target = Some(target);
+        self.increasing = increasing;
+        commands
+    }
+}
+
+/// Estimates the backlash of a servo from two passes over the same edge,
+/// given as (angle, distance) in the order they were measured,
+/// the first towards increasing angles and the second towards decreasing angles.
+///
+/// The gears lag behind the commanded angle by half the backlash in the direction of motion,
+/// so the edge is seen at a commanded angle larger by the backlash in the first pass.
+/// The result is in degrees, and is only as precise as the angle step of the passes.
+pub fn estimate_backlash(increasing: &[(f32, u16)], decreasing: &[(f32, u16)]) -> Option<f32> {
+    Some(edge_yaw(increasing)? - edge_yaw(decreasing)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: (f32, f32) = (-40.0, 40.0);
+
+    fn offset() -> Backlash {
+        Backlash::new(BacklashConfig {
+            compensation: BacklashCompensation::Offset,
+            backlash: 2.0,
+            ..BacklashConfig::default()
+        })
+    }
+
+    fn overshoot(approach: Approach) -> Backlash {
+        Backlash::new(BacklashConfig {
+            compensation: BacklashCompensation::Overshoot,
+            approach,
+            overshoot: 2.0,
+            ..BacklashConfig::default()
+        })
+    }
+
+    // Commands of each target, in order
+    fn commands(backlash: &mut Backlash, targets: &[f32]) -> Vec<Vec<f32>> {
+        targets
+            .iter()
+            .map(|target| backlash.commands(*target, LIMITS.0, LIMITS.1))
+            .collect()
+    }
+
+    #[test]
+    fn uncompensated_targets_are_commanded_as_they_are() {
+        let mut backlash = Backlash::default();
+        assert_eq!(
+            commands(&mut backlash, &[10.0, -5.0, -5.0]),
+            [[10.0], [-5.0], [-5.0]]
+        );
+    }
+
+    #[test]
+    fn offset_follows_direction_of_motion() {
+        // Unknown direction, then increasing, decreasing and still decreasing
+        assert_eq!(
+            commands(&mut offset(), &[0.0, 10.0, 5.0, 5.0]),
+            [[0.0], [11.0], [4.0], [4.0]]
+        );
+    }
+
+    #[test]
+    fn offset_stays_within_limits() {
+        assert_eq!(
+            commands(&mut offset(), &[0.0, 40.0, -40.0]),
+            [[0.0], [40.0], [-40.0]]
+        );
+    }
+
+    #[test]
+    fn overshoot_approaches_from_one_side() {
+        // The first target is always overshot
+        assert_eq!(
+            commands(&mut overshoot(Approach::FromBelow), &[10.0, 20.0, 15.0]),
+            [vec![8.0, 10.0], vec![20.0], vec![13.0, 15.0]]
+        );
+        assert_eq!(
+            commands(&mut overshoot(Approach::FromAbove), &[10.0, 5.0, 8.0]),
+            [vec![12.0, 10.0], vec![5.0], vec![10.0, 8.0]]
+        );
+    }
+
+    #[test]
+    fn overshoot_stays_within_limits() {
+        // Shortened near the limit
+        assert_eq!(
+            commands(&mut overshoot(Approach::FromBelow), &[-39.0]),
+            [[-40.0, -39.0]]
+        );
+        // Left out at the limit
+        assert_eq!(
+            commands(&mut overshoot(Approach::FromBelow), &[-40.0]),
+            [[-40.0]]
+        );
+        assert_eq!(
+            commands(&mut overshoot(Approach::FromAbove), &[0.0, 40.0]),
+            [vec![2.0, 0.0], vec![40.0]]
+        );
+    }
+
+    #[test]
+    fn backlash_is_the_distance_between_the_edges_of_both_passes() {
+        let increasing = [(0.0, 100), (1.0, 100), (2.0, 300), (3.0, 300)];
+        let decreasing = [(3.0, 300), (2.0, 300), (1.0, 300), (0.0, 100)];
+        assert_eq!(estimate_backlash(&increasing, &decreasing), Some(1.0));
+        assert_eq!(estimate_backlash(&increasing[..1], &decreasing), None);
+    }
+}
@@ -1,244 +1,1168 @@
 extern crate tfluna_pan_tilt;
 
-use std::cell::RefCell;
 use std::error::Error;
-use std::rc::Rc;
-use std::sync::Mutex;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
 use clap::Parser;
-use colorgrad::Gradient;
 use embedded_hal_bus::i2c::MutexDevice;
 use embedded_tfluna::{
-    RangingMode,
+    RangingMode, SensorReading,
     i2c::{Address, TFLuna},
 };
-use pwm_pca9685::{Address as PWMAddress, Channel, Pca9685};
+use pwm_pca9685::{Address as PWMAddress, Pca9685};
 use rerun;
 use rppal::hal::Delay;
 use rppal::i2c::I2c;
 
-use tfluna_pan_tilt::servo::ServoMotor;
+use tfluna_pan_tilt::adaptive::AdaptivePlanner;
+use tfluna_pan_tilt::backlash::{BacklashConfig, estimate_backlash};
+use tfluna_pan_tilt::checkpoint::Checkpoint;
+use tfluna_pan_tilt::coloring::{ColorChannel, Colormap};
+use tfluna_pan_tilt::config::{ScanConfig, ServoConfig};
+use tfluna_pan_tilt::hardware_pwm::{self, HardwarePwmServo};
+use tfluna_pan_tilt::metadata::RecordingMetadata;
+use tfluna_pan_tilt::point_cloud::PointCloudFormat;
+use tfluna_pan_tilt::point_cloud_logger::LoggingStrategy;
+use tfluna_pan_tilt::range_image::RangeImageFormat;
+use tfluna_pan_tilt::remote::{self, ScanControl};
+use tfluna_pan_tilt::safe_state::{SafeState, ServoShutdown, install_signal_handler};
+use tfluna_pan_tilt::sampling::Aggregation;
+use tfluna_pan_tilt::scan::{
+    ScanMode, ScanPattern, ScanPlanner, TrajectorySummary, grid_angle_count, plan_grid,
+    summarize_poses, uncovered_angle,
+};
+use tfluna_pan_tilt::scanner::{
+    BOTTOM, LOG_ENTITY_PATH, Rig, SweepClock, TOP, connect_rerun, new_recording_id, scan,
+    scan_ranges, sensor_error, supports_checkpoints, sweep_row,
+};
+use tfluna_pan_tilt::selftest::{
+    check_devices, check_pulse_widths, check_readings_change, check_signal_strength,
+    check_temperature, read_tfluna_information,
+};
+use tfluna_pan_tilt::servo::{
+    NOMINAL_OSCILLATOR_FREQUENCY, Servo, ServoBank, ServoDriver, ServoMotor, SharedPca9685,
+    channel_from_index, pca9685_frequency, pca9685_prescale,
+};
+use tfluna_pan_tilt::settle::{SettleSettings, estimate_settle_time, settle_time_table};
+use tfluna_pan_tilt::simulation::{PCA9685_ADDRESS, RigBus, SimulatedBus, TFLUNA_ADDRESS};
+use tfluna_pan_tilt::sweep::{
+    SweepRow, edge_yaw, estimate_latency, plan_sweep_rows, summarize_rows,
+};
+use tfluna_pan_tilt::timelapse;
+
+// Rerun parameters
+const PREVIEW_RAYS_ENTITY_PATH: &str = "preview/rays";
+const PREVIEW_TRAJECTORY_ENTITY_PATH: &str = "preview/trajectory";
+
+// One of the servos of the rig
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RigServo {
+    Bottom,
+    Top,
+}
 
-// Servo Channels
-const BOTTOM_SERVO_CHANNEL: Channel = Channel::C14;
-const TOP_SERVO_CHANNEL: Channel = Channel::C15;
+impl FromStr for RigServo {
+    type Err = String;
 
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "bottom" => Ok(RigServo::Bottom),
+            "top" => Ok(RigServo::Top),
+            _ => Err(format!("Unknown servo '{s}', expected one of: bottom, top")),
+        }
+    }
+}
+
+/// Values given on the command line take precedence over the preset and the configuration file
 #[derive(clap::Parser, Debug)]
 #[command(version = None, about = "Configurable TFLuna on Pan Tilt", long_about = None)]
 struct Cli {
-    #[arg(long, default_value_t = String::from("10.181.190.150"), help = "IP Address of a running rerun server")]
-    rerun_server_ip: String,
+    #[arg(long, help = "TOML file describing the scan")]
+    config: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Built-in preset (quick, fine or wall-test) applied before the configuration file"
+    )]
+    preset: Option<String>,
+    #[arg(long, help = "IP Address of a running rerun server")]
+    rerun_server_ip: Option<String>,
+    #[arg(
+        long,
+        help = "Save the recording to this .rrd file instead of sending it to the rerun server"
+    )]
+    rrd_file: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Uniform grid (grid), coarse grid refined around edges (adaptive), continuous sweeps of the bottom servo (sweep), tracking of the nearest object (track) or single row at a fixed pitch (planar)"
+    )]
+    mode: Option<ScanMode>,
+    #[arg(long, help = "Delay in milliseconds after servo motor command")]
+    servo_motor_delay: Option<u32>,
+    #[arg(long, help = "Size of servo motor angle increment in degrees")]
+    angle_step: Option<f32>,
+    #[arg(long, help = "Number of measurements made at each pose")]
+    samples_per_pose: Option<u32>,
+    #[arg(
+        long,
+        help = "How the measurements of a pose are combined: mean, median, trimmed-mean or signal-weighted-mean"
+    )]
+    aggregation: Option<Aggregation>,
+    #[arg(
+        long,
+        help = "Order in which the angles are visited (serpentine or raster)"
+    )]
+    pattern: Option<ScanPattern>,
+    #[arg(long, help = "Minimum angle for bottom servo motor")]
+    min_angle_bottom: Option<f32>,
+    #[arg(long, help = "Maximum angle for bottom servo motor")]
+    max_angle_bottom: Option<f32>,
+    #[arg(long, help = "Minimum angle for top servo motor")]
+    min_angle_top: Option<f32>,
+    #[arg(long, help = "Maximum angle for top servo motor")]
+    max_angle_top: Option<f32>,
+    #[arg(long, help = "Radius of points in centimeters for viewer")]
+    point_radius: Option<f32>,
+    #[arg(long, help = "Maximum distance in centimeters")]
+    maximum_distance: Option<f32>,
+    #[arg(long, help = "Minimum distance in centimeters")]
+    minimum_distance: Option<f32>,
+    #[arg(
+        long,
+        help = "Measurement from which the point colors are computed: distance, signal-strength, temperature, height, order or validity"
+    )]
+    color_channel: Option<ColorChannel>,
+    #[arg(
+        long,
+        help = "Color gradient: spectral, turbo, viridis, inferno, magma, plasma, cividis, rainbow, sinebow or greys"
+    )]
+    colormap: Option<Colormap>,
+    #[arg(
+        long,
+        help = "Map the running percentiles of the color channel onto the gradient instead of fixed bounds"
+    )]
+    autoscale: bool,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        num_args = 3,
+        help = "Offset in centimeters from the pan axis to the tilt axis, as x,y,z"
+    )]
+    pan_to_tilt_offset: Option<Vec<f32>>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        num_args = 3,
+        help = "Offset in centimeters from the tilt axis to the sensor aperture, as x,y,z"
+    )]
+    tilt_to_sensor_offset: Option<Vec<f32>>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        num_args = 2,
+        help = "Rotation in degrees of the pan axis around the base x and y axes"
+    )]
+    pan_axis_misalignment: Option<Vec<f32>>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        num_args = 2,
+        help = "Rotation in degrees of the tilt axis around the pan y and z axes"
+    )]
+    tilt_axis_misalignment: Option<Vec<f32>>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        num_args = 2,
+        help = "Elevation and azimuth in degrees of the beam relative to the sensor"
+    )]
+    beam_misalignment: Option<Vec<f32>>,
+    #[arg(
+        long,
+        help = "File to which the point cloud is written at the end of the scan (.ply, .pcd, .xyz or .las)"
+    )]
+    point_cloud_output: Option<PathBuf>,
     #[arg(
         long,
-        default_value_t = 100,
-        help = "Delay in milliseconds after servo motor command"
+        help = "Point cloud file format (ply, ply-ascii, pcd, pcd-ascii, xyz or las), deduced from the file extension by default"
     )]
-    servo_motor_delay: u32,
+    point_cloud_format: Option<PointCloudFormat>,
     #[arg(
         long,
-        default_value_t = 30.0,
-        help = "Size of servo motor angle increment in degrees"
+        help = "Send every point as soon as it is measured (sample) or the points of a row once it is finished (row, the default)"
     )]
-    angle_step: f32,
+    logging_strategy: Option<LoggingStrategy>,
     #[arg(
         long,
-        default_value_t = -30.0,
-        help = "Minimum angle for bottom servo motor"
+        help = "Prefix of the files to which the range image is written at the end of the scan, e.g. data/scan gives data/scan_distance.png"
     )]
-    min_angle_bottom: f32,
+    range_image_output: Option<PathBuf>,
+    #[arg(long, help = "Range image file format (png or npy)")]
+    range_image_format: Option<RangeImageFormat>,
     #[arg(
         long,
-        default_value_t = 30.0,
-        help = "Maximum angle for bottom servo motor"
+        help = "JSON file to which planar scans are written as a laser scan at the end of the scan"
     )]
-    max_angle_bottom: f32,
+    laser_scan_output: Option<PathBuf>,
     #[arg(
         long,
-        default_value_t = -30.0,
-        help = "Minimum angle for top servo motor"
+        help = "File to which the events of the scan are appended as JSON lines, in addition to the rerun text log"
     )]
-    min_angle_top: f32,
+    event_log: Option<PathBuf>,
     #[arg(
         long,
-        default_value_t = 30.0,
-        help = "Maximum angle for top servo motor"
+        help = "Periodically save the progress of the scan to this file so that it can be resumed"
     )]
-    max_angle_top: f32,
+    checkpoint: Option<PathBuf>,
     #[arg(
         long,
-        default_value_t = 0.1,
-        help = "Radius of points in centimeters for viewer"
+        help = "Resume an interrupted scan from its checkpoint file, with the same configuration, which other options cannot change"
     )]
-    point_radius: f32,
+    resume: Option<PathBuf>,
     #[arg(
         long,
-        default_value_t = 200.0,
-        help = "Maximum distance in centimeters"
+        help = "Sweep the middle row in both directions to estimate the sweep latency, then exit"
     )]
-    maximum_distance: f32,
-    #[arg(long, default_value_t = 10.0, help = "Minimum distance in centimeters")]
-    minimum_distance: f32,
+    estimate_sweep_latency: bool,
+    #[arg(
+        long,
+        help = "Step the bottom servo over a vertical edge (bottom) or the top servo over a horizontal edge (top) in both directions to estimate its backlash, then exit"
+    )]
+    estimate_backlash: Option<RigServo>,
+    #[arg(
+        long,
+        help = "Repeatedly step the bottom servo (bottom) or the top servo (top) to just past a nearby edge while ranging continuously, print its settle time for each step size of the settle section of the configuration, then exit"
+    )]
+    characterise_settle_time: Option<RigServo>,
+    #[arg(
+        long,
+        help = "Check the I2C devices, the sensor readings and the servo limits, print a pass/fail report, then exit"
+    )]
+    selftest: bool,
+    #[arg(
+        long,
+        help = "Repeat the scan every timelapse.interval minutes until stopped, compare each scan to the reference scan and log the changes"
+    )]
+    timelapse: bool,
+    #[arg(
+        long,
+        help = "Run on a simulated head in the virtual scene of the simulation section of the configuration instead of the hardware"
+    )]
+    simulate: bool,
+    #[arg(
+        long,
+        help = "Keep running as a remote control server listening on this address, e.g. 0.0.0.0:8080, instead of scanning once"
+    )]
+    serve: Option<String>,
+    #[arg(
+        long,
+        help = "Print the number of poses, estimated duration and servo travel, and log a preview of the planned poses to rerun, without moving the servos"
+    )]
+    dry_run: bool,
+}
+
+impl Cli {
+    /// Loads the preset and configuration file, then applies the command line values
+    fn resolve_config(&self) -> Result<ScanConfig, Box<dyn Error>> {
+        let config = ScanConfig::load(self.preset.as_deref(), self.config.as_deref())?;
+        Ok(self.apply_overrides(config))
+    }
+
+    /// Configuration of a resumed scan, which must not be changed by the other options,
+    /// so that the resumed scan gives the same result as an uninterrupted one
+    fn resume_config(&self, checkpoint: &Checkpoint) -> Result<ScanConfig, Box<dyn Error>> {
+        let config = ScanConfig::from_toml(&checkpoint.config)?;
+        let base = match self.preset.is_some() || self.config.is_some() {
+            true => ScanConfig::load(self.preset.as_deref(), self.config.as_deref())?,
+            false => config.clone(),
+        };
+        let mut requested = self.apply_overrides(base);
+        // The checkpoint may have been moved
+        requested.output.checkpoint = config.output.checkpoint.clone();
+        let differences = requested.differences(&config)?;
+        if !differences.is_empty() {
+            return Err(format!(
+                "The options given with --resume change the configuration of the checkpoint: {}",
+                differences.join(", ")
+            )
+            .into());
+        }
+        Ok(config)
+    }
+
+    // Applies the command line values
+    fn apply_overrides(&self, mut config: ScanConfig) -> ScanConfig {
+        let output = &mut config.output;
+        if let Some(value) = &self.rerun_server_ip {
+            output.rerun_server_ip = value.clone();
+        }
+        if let Some(value) = &self.rrd_file {
+            output.rrd_file = Some(value.clone());
+        }
+        if let Some(value) = &self.point_cloud_output {
+            output.point_cloud = Some(value.clone());
+        }
+        if let Some(value) = self.point_cloud_format {
+            output.point_cloud_format = Some(value);
+        }
+        if let Some(value) = self.logging_strategy {
+            output.logging_strategy = value;
+        }
+        if let Some(value) = &self.range_image_output {
+            output.range_image = Some(value.clone());
+        }
+        if let Some(value) = self.range_image_format {
+            output.range_image_format = value;
+        }
+        if let Some(value) = &self.laser_scan_output {
+            output.laser_scan = Some(value.clone());
+        }
+        if let Some(value) = &self.event_log {
+            output.event_log = Some(value.clone());
+        }
+        if let Some(value) = &self.checkpoint {
+            output.checkpoint = Some(value.clone());
+        }
+        let scan = &mut config.scan;
+        if let Some(value) = self.mode {
+            scan.mode = value;
+        }
+        if let Some(value) = self.servo_motor_delay {
+            scan.servo_motor_delay = value;
+        }
+        if let Some(value) = self.angle_step {
+            scan.angle_step = value;
+        }
+        if let Some(value) = self.pattern {
+            scan.pattern = value;
+        }
+        let sensor = &mut config.sensor;
+        if let Some(value) = self.samples_per_pose {
+            sensor.samples_per_pose = value;
+        }
+        if let Some(value) = self.aggregation {
+            sensor.aggregation = value;
+        }
+        let servos = &mut config.servos;
+        if let Some(value) = self.min_angle_bottom {
+            servos.bottom.min_angle = value;
+        }
+        if let Some(value) = self.max_angle_bottom {
+            servos.bottom.max_angle = value;
+        }
+        if let Some(value) = self.min_angle_top {
+            servos.top.min_angle = value;
+        }
+        if let Some(value) = self.max_angle_top {
+            servos.top.max_angle = value;
+        }
+        let color = &mut config.color;
+        if let Some(value) = self.point_radius {
+            color.point_radius = value;
+        }
+        if let Some(value) = self.maximum_distance {
+            color.maximum_distance = value;
+        }
+        if let Some(value) = self.minimum_distance {
+            color.minimum_distance = value;
+        }
+        if let Some(value) = self.color_channel {
+            color.channel = value;
+        }
+        if let Some(value) = self.colormap {
+            color.colormap = value;
+        }
+        if self.autoscale {
+            color.autoscale = true;
+        }
+        let kinematics = &mut config.kinematics;
+        if let Some(value) = &self.pan_to_tilt_offset {
+            kinematics.pan_to_tilt = [value[0], value[1], value[2]];
+        }
+        if let Some(value) = &self.tilt_to_sensor_offset {
+            kinematics.tilt_to_sensor = [value[0], value[1], value[2]];
+        }
+        if let Some(value) = &self.pan_axis_misalignment {
+            kinematics.pan_axis_misalignment = [value[0], value[1]];
+        }
+        if let Some(value) = &self.tilt_axis_misalignment {
+            kinematics.tilt_axis_misalignment = [value[0], value[1]];
+        }
+        if let Some(value) = &self.beam_misalignment {
+            kinematics.beam_misalignment = [value[0], value[1]];
+        }
+        config
+    }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Cli::parse();
-    let rerun_server_ip = args.rerun_server_ip;
-    let servo_motor_delay = Duration::from_millis(args.servo_motor_delay as u64);
-    let angle_step = args.angle_step;
+    // A resumed scan continues with the configuration of the interrupted scan
+    let (config, checkpoint) = match &args.resume {
+        Some(path) => {
+            let checkpoint = Checkpoint::load(path)?;
+            let mut config = args.resume_config(&checkpoint)?;
+            config.output.checkpoint = Some(path.clone());
+            (config, checkpoint)
+        }
+        None => {
+            let config = args.resolve_config()?;
+            let checkpoint = Checkpoint::new(new_recording_id()?, config.to_toml()?);
+            (config, checkpoint)
+        }
+    };
+    // Checkpoints store poses, which sweep and tracking scans do not plan
+    if !supports_checkpoints(config.scan.mode) && config.output.checkpoint.is_some() {
+        return Err("Checkpoints are only supported in grid, adaptive and planar modes".into());
+    }
+    if args.timelapse {
+        check_timelapse(&config)?;
+    }
+    if args.dry_run {
+        return dry_run(&config, &checkpoint.recording_id);
+    }
+    let interrupted = install_signal_handler()?;
     // Instantiate I2C peripheral
-    let i2c = match I2c::new() {
-        Ok(i2c) => Mutex::new(i2c),
-        Err(err) => {
-            println!("Failed getting access to I2c due to {}", err);
-            panic!();
+    let i2c = match args.simulate {
+        true => {
+            if config.servos.driver != ServoDriver::Pca9685 {
+                return Err("The simulated head only supports the pca9685 servo driver".into());
+            }
+            Mutex::new(RigBus::Simulated(Box::new(SimulatedBus::new(&config))))
         }
+        false => match I2c::new() {
+            Ok(i2c) => Mutex::new(RigBus::Hardware(i2c)),
+            Err(err) => return Err(format!("Failed getting access to I2c due to {err}").into()),
+        },
     };
+    // The devices are checked before their drivers access them
+    if args.selftest {
+        let pca9685_address =
+            (config.servos.driver == ServoDriver::Pca9685).then_some(PCA9685_ADDRESS);
+        let report = check_devices(&mut MutexDevice::new(&i2c), TFLUNA_ADDRESS, pca9685_address);
+        if !report.passed() {
+            println!("{report}");
+            return Err("Self-test failed".into());
+        }
+    }
     let i2c_tfluna = MutexDevice::new(&i2c);
-    let mut tfluna = TFLuna::new(i2c_tfluna, Address::default(), Delay::new()).unwrap();
-    tfluna.enable().unwrap();
-    tfluna.set_ranging_mode(RangingMode::Trigger).unwrap();
+    let mut tfluna = TFLuna::new(i2c_tfluna, Address::default(), Delay::new())
+        .map_err(sensor_error("accessing the TF-Luna"))?;
+    tfluna
+        .enable()
+        .map_err(sensor_error("enabling the TF-Luna"))?;
+    tfluna
+        .set_ranging_mode(RangingMode::Trigger)
+        .map_err(sensor_error("setting the ranging mode"))?;
     thread::sleep(Duration::from_millis(100));
 
-    let i2c_servo = MutexDevice::new(&i2c);
-    let address = PWMAddress::default();
-    let mut pwm = Pca9685::new(i2c_servo, address).unwrap();
-    // This corresponds to a frequency of 50 Hz.
-    pwm.set_prescale(122).unwrap();
-    // It is necessary to enable the device.
-    pwm.enable().unwrap();
-
-    let pwm = Rc::new(RefCell::new(pwm));
-
-    let mut servo_bottom = ServoMotor::new(
-        pwm.clone(),
-        BOTTOM_SERVO_CHANNEL,
-        args.min_angle_bottom,
-        args.max_angle_bottom,
-        true,
-    )
-    .unwrap();
-
-    let mut servo_top = ServoMotor::new(
-        pwm.clone(),
-        TOP_SERVO_CHANNEL,
-        args.min_angle_top,
-        args.max_angle_top,
-        true,
-    )
-    .unwrap();
+    let bottom = &config.servos.bottom;
+    let top = &config.servos.top;
+    // The backlash and the settle times are estimated without compensation.
+    // Sweeps command the servos continuously, so the compensation would overshoot at every step.
+    let uncompensated = args.estimate_backlash.is_some()
+        || args.characterise_settle_time.is_some()
+        || args.estimate_sweep_latency
+        || config.scan.mode == ScanMode::Sweep;
+    let backlash = |servo: &ServoConfig| match uncompensated {
+        true => BacklashConfig::default(),
+        false => servo.backlash.clone(),
+    };
+    let safe_sensor = TFLuna::new(MutexDevice::new(&i2c), Address::default(), Delay::new())
+        .map_err(sensor_error("accessing the TF-Luna"))?;
+    let settle_time = Duration::from_millis(1000);
+    match config.servos.driver {
+        ServoDriver::Pca9685 => {
+            let i2c_servo = MutexDevice::new(&i2c);
+            let address = PWMAddress::default();
+            let pwm = SharedPca9685::new(
+                Pca9685::new(i2c_servo, address)
+                    .map_err(|err| format!("Failed accessing the PCA9685: {err:?}"))?,
+                config.servos.frequency,
+                config.servos.oscillator_frequency,
+            )?;
+            // It is necessary to enable the device.
+            pwm.with(|pwm| pwm.enable())
+                .map_err(|err| format!("Failed enabling the PCA9685: {err:?}"))?;
 
-    thread::sleep(Duration::from_millis(1000));
+            // From here on, the rig is put back in a safe state when main returns,
+            // with or without an error, or panics
+            let _safe_state = SafeState::new(
+                ServoShutdown::Pca9685 {
+                    pwm: pwm.clone(),
+                    neutral_pulse_widths: vec![
+                        (
+                            channel_from_index(bottom.channel)?,
+                            bottom.calibration.pulse_width(0.0, bottom.reversed),
+                        ),
+                        (
+                            channel_from_index(top.channel)?,
+                            top.calibration.pulse_width(0.0, top.reversed),
+                        ),
+                    ],
+                },
+                safe_sensor,
+                settle_time,
+            )
+            .with_shared_bus(&i2c);
+
+            let servo_bottom = ServoMotor::new(
+                pwm.clone(),
+                channel_from_index(bottom.channel)?,
+                bottom.min_angle,
+                bottom.max_angle,
+                bottom.reversed,
+                bottom.calibration,
+            )?
+            .with_backlash(backlash(bottom));
+
+            let servo_top = ServoMotor::new(
+                pwm.clone(),
+                channel_from_index(top.channel)?,
+                top.min_angle,
+                top.max_angle,
+                top.reversed,
+                top.calibration,
+            )?
+            .with_backlash(backlash(top));
+
+            let rig = Rig {
+                tfluna,
+                bus: MutexDevice::new(&i2c),
+                servos: ServoBank::new(vec![servo_bottom, servo_top])?,
+            };
+            run(&config, &args, checkpoint, rig, interrupted)
+        }
+        ServoDriver::HardwarePwm => {
+            let frequency = config.servos.frequency;
+            let pwm_bottom = hardware_pwm::open_channel(bottom.channel, frequency)?;
+            let pwm_top = hardware_pwm::open_channel(top.channel, frequency)?;
+
+            // From here on, the rig is put back in a safe state when main returns,
+            // with or without an error, or panics
+            let _safe_state = SafeState::<MutexDevice<RigBus>, _, _>::new(
+                ServoShutdown::HardwarePwm(vec![
+                    (
+                        pwm_bottom.clone(),
+                        bottom.calibration.pulse_width(0.0, bottom.reversed),
+                    ),
+                    (
+                        pwm_top.clone(),
+                        top.calibration.pulse_width(0.0, top.reversed),
+                    ),
+                ]),
+                safe_sensor,
+                settle_time,
+            )
+            .with_shared_bus(&i2c);
+
+            let servo_bottom = HardwarePwmServo::new(
+                pwm_bottom,
+                bottom.min_angle,
+                bottom.max_angle,
+                bottom.reversed,
+                bottom.calibration,
+            )?
+            .with_backlash(backlash(bottom));
+
+            let servo_top = HardwarePwmServo::new(
+                pwm_top,
+                top.min_angle,
+                top.max_angle,
+                top.reversed,
+                top.calibration,
+            )?
+            .with_backlash(backlash(top));
 
-    // Color gradient generator for point cloud
-    let g = colorgrad::preset::spectral();
-    // Vector to store all points
-    let mut positions = Vec::new();
-    let mut colors = Vec::new();
+            let rig = Rig {
+                tfluna,
+                bus: MutexDevice::new(&i2c),
+                servos: ServoBank::new(vec![servo_bottom, servo_top])?,
+            };
+            run(&config, &args, checkpoint, rig, interrupted)
+        }
+    }
+}
 
-    let mut angle_bottom = servo_bottom.get_min_angle();
-    servo_bottom.set_angle(angle_bottom).unwrap();
+// Scans with the servos of any backend, see `main`
+fn run<S: Servo>(
+    config: &ScanConfig,
+    args: &Cli,
+    mut checkpoint: Checkpoint,
+    mut rig: Rig<S>,
+    interrupted: Arc<AtomicBool>,
+) -> Result<(), Box<dyn Error>> {
     thread::sleep(Duration::from_millis(1000));
+    let control = Arc::new(ScanControl::new(interrupted));
+
+    if args.estimate_sweep_latency {
+        return estimate_sweep_latency(config, &mut rig, &control);
+    }
+    if let Some(servo) = args.estimate_backlash {
+        return estimate_servo_backlash(config, &mut rig, servo, &control);
+    }
+    if let Some(servo) = args.characterise_settle_time {
+        return characterise_settle_time(config, &mut rig, servo, &control);
+    }
+    if args.selftest {
+        return selftest(config, &mut rig, &checkpoint.recording_id, &control);
+    }
+    if let Some(address) = &args.serve {
+        remote::serve(config, &mut rig, address, control)?;
+        return Ok(());
+    }
+    if args.timelapse {
+        timelapse::run(config, &mut rig, &control)?;
+        return Ok(());
+    }
+    scan(config, &mut checkpoint, &mut rig, &control, None)?;
+    Ok(())
+}
 
-    // Rerun parameters
-    let application_id = "rpi-lidar";
-    let yaw_entity_path = "yaw";
-    let pitch_entity_path = "pitch";
-    let distance_entity_path = "distance";
-    let signal_strength_entity_path = "signal_strength";
-    let temperature_entity_path = "temperature";
-    let position_entity_path = "position";
-
-    // Connect to rerun server
-    let rec = rerun::RecordingStreamBuilder::new(application_id).connect_grpc_opts(
-        format!("rerun+http://{}:9876/proxy", rerun_server_ip),
-        rerun::default_flush_timeout(),
+// Time-lapse scans compare the poses of every scan and save them to a recording each
+fn check_timelapse(config: &ScanConfig) -> Result<(), Box<dyn Error>> {
+    // Poses are compared by their pixel of the scan grid, which only identifies a pose
+    // when there is one per pixel, unlike the refined poses of adaptive scans
+    if !matches!(config.scan.mode, ScanMode::Grid | ScanMode::Planar) {
+        return Err("Time-lapse scans are only supported in grid and planar modes".into());
+    }
+    if config.output.checkpoint.is_some() {
+        return Err("Time-lapse scans cannot be checkpointed".into());
+    }
+    if config.output.rrd_file.is_some() {
+        return Err(
+            "Time-lapse recordings are saved to timelapse.directory, not to a single .rrd file"
+                .into(),
+        );
+    }
+    Ok(())
+}
+
+// Prints the number of poses, expected duration and servo travel of the scan,
+// and logs the planned poses and sensor rays to rerun, without accessing I2C
+fn dry_run(config: &ScanConfig, recording_id: &str) -> Result<(), Box<dyn Error>> {
+    let (yaw_range, pitch_range) = scan_ranges(config);
+    let angle_step = config.scan.angle_step;
+    let servo_motor_delay = Duration::from_millis(config.scan.servo_motor_delay as u64);
+    let measurement_delay = Duration::from_millis(config.sensor.measurement_delay as u64);
+
+    let ranges = match config.scan.mode {
+        // Sweep rows cover the whole yaw range
+        ScanMode::Sweep => vec![("top", pitch_range)],
+        ScanMode::Planar => vec![("bottom", yaw_range)],
+        _ => vec![("bottom", yaw_range), ("top", pitch_range)],
+    };
+    for (servo, range) in ranges {
+        let uncovered = uncovered_angle(range, angle_step);
+        if uncovered > 1e-3 {
+            println!(
+                "Warning: the {} servo range [{}, {}] is not a multiple of the angle step {}, its last {:.3} degrees are not scanned",
+                servo, range.0, range.1, angle_step, uncovered
+            );
+        }
+    }
+
+    // Servo angles (yaw, pitch) in visiting order
+    let (angles, summary): (Vec<(f32, f32)>, TrajectorySummary) = match config.scan.mode {
+        ScanMode::Grid | ScanMode::Planar => {
+            let poses = plan_grid(yaw_range, pitch_range, angle_step, config.scan.pattern);
+            let summary = summarize_poses(
+                &poses,
+                servo_motor_delay,
+                measurement_delay,
+                config.sensor.samples_per_pose.max(1),
+            );
+            (poses.iter().map(|p| (p.yaw, p.pitch)).collect(), summary)
+        }
+        ScanMode::Track => {
+            let poses = plan_grid(yaw_range, pitch_range, angle_step, ScanPattern::Serpentine);
+            println!(
+                "Tracking: only the search for the target is known in advance, tracking lasts {}",
+                match config.tracking.duration {
+                    Some(seconds) => format!("{seconds} s"),
+                    None => String::from("until stopped"),
+                }
+            );
+            let summary = summarize_poses(
+                &poses,
+                servo_motor_delay,
+                measurement_delay,
+                config.sensor.samples_per_pose.max(1),
+            );
+            (poses.iter().map(|p| (p.yaw, p.pitch)).collect(), summary)
+        }
+        ScanMode::Adaptive => {
+            let poses = AdaptivePlanner::new(
+                yaw_range,
+                pitch_range,
+                angle_step,
+                config.scan.pattern,
+                config.adaptive.clone(),
+            )
+            .next_poses();
+            println!(
+                "Adaptive scan: only the coarse grid is known in advance, refinement adds {} poses",
+                match config.adaptive.max_poses {
+                    Some(max_poses) => format!("up to {}", max_poses.saturating_sub(poses.len())),
+                    None => String::from("an unbounded number of"),
+                }
+            );
+            let summary = summarize_poses(
+                &poses,
+                servo_motor_delay,
+                measurement_delay,
+                config.sensor.samples_per_pose.max(1),
+            );
+            (poses.iter().map(|p| (p.yaw, p.pitch)).collect(), summary)
+        }
+        ScanMode::Sweep => {
+            let rows = plan_sweep_rows(yaw_range, pitch_range, angle_step);
+            let summary = summarize_rows(&rows, &config.sweep, servo_motor_delay);
+            // Rays every angle step along each row
+            let count = grid_angle_count(yaw_range, angle_step).max(2);
+            let angles = rows
+                .iter()
+                .flat_map(|row| {
+                    (0..count).map(move |i| {
+                        let t = i as f32 / (count - 1) as f32;
+                        (row.start_yaw + (row.end_yaw - row.start_yaw) * t, row.pitch)
+                    })
+                })
+                .collect();
+            (angles, summary)
+        }
+    };
+    let seconds = summary.duration.as_secs();
+    println!("Measurements: {}", summary.measurements);
+    println!(
+        "Estimated duration: {}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    println!(
+        "Servo travel: {:.1} degrees for the bottom servo, {:.1} degrees for the top servo",
+        summary.yaw_travel, summary.pitch_travel
+    );
+    if config.servos.driver == ServoDriver::Pca9685 {
+        let oscillator_frequency = config
+            .servos
+            .oscillator_frequency
+            .unwrap_or(NOMINAL_OSCILLATOR_FREQUENCY);
+        let prescale = pca9685_prescale(config.servos.frequency, oscillator_frequency)?;
+        println!(
+            "PCA9685 prescale: {}, for a PWM frequency of {:.2} Hz",
+            prescale,
+            pca9685_frequency(prescale, oscillator_frequency)
+        );
+    }
+
+    // Rays with the length of the end of the color gradient
+    let ray_length = config.color.maximum_distance;
+    let poses: Vec<_> = angles
+        .iter()
+        .map(|(yaw, pitch)| config.kinematics.pose(*yaw, *pitch))
+        .collect();
+    let rec = connect_rerun(config, recording_id)?;
+    rec.send_recording_name("dry-run")?;
+    rec.send_property("config", &rerun::TextDocument::new(config.to_toml()?))?;
+    rec.log_static(
+        PREVIEW_RAYS_ENTITY_PATH,
+        &rerun::Arrows3D::from_vectors(poses.iter().map(|pose| {
+            let [x, y, z] = pose.beam_direction();
+            [x * ray_length, y * ray_length, z * ray_length]
+        }))
+        .with_origins(poses.iter().map(|pose| pose.origin())),
     )?;
-    rec.send_recording_name(format!(
-        "{}deg-{}ms",
-        args.angle_step, args.servo_motor_delay,
-    ))?;
-    rec.send_property(
-        "servo_motor_delay",
-        &rerun::Scalars::single(args.servo_motor_delay),
+    rec.log_static(
+        PREVIEW_TRAJECTORY_ENTITY_PATH,
+        &rerun::LineStrips3D::new([poses
+            .iter()
+            .map(|pose| pose.point_at(ray_length))
+            .collect::<Vec<_>>()]),
     )?;
-    rec.send_property("angle_step", &rerun::Scalars::single(args.angle_step))?;
+    rec.flush_blocking();
+    Ok(())
+}
+
+// Checks the sensor and the servos and prints a pass/fail report,
+// the devices were already found on the bus by `main`.
+// The report and the configuration registers of the TF-Luna are stored in a recording.
+fn selftest(
+    config: &ScanConfig,
+    rig: &mut Rig<impl Servo>,
+    recording_id: &str,
+    control: &ScanControl,
+) -> Result<(), Box<dyn Error>> {
+    let servo_motor_delay = Duration::from_millis(config.scan.servo_motor_delay as u64);
+    let measurement_delay = Duration::from_millis(config.sensor.measurement_delay as u64);
+    let pca9685_address = (config.servos.driver == ServoDriver::Pca9685).then_some(PCA9685_ADDRESS);
+    let mut report = check_devices(&mut rig.bus, TFLUNA_ADDRESS, pca9685_address);
+    let information = read_tfluna_information(&mut rig.bus, TFLUNA_ADDRESS);
+    match &information {
+        Ok(information) => {
+            report.check(
+                "TF-Luna information",
+                Ok(format!(
+                    "firmware {}, serial number {}",
+                    information.firmware_version, information.serial_number
+                )),
+            );
+            report.check(
+                "TF-Luna enabled",
+                match information.enable {
+                    true => Ok(String::from("enabled")),
+                    false => Err(String::from("the enable register is not set")),
+                },
+            );
+        }
+        Err(err) => report.check(
+            "TF-Luna information",
+            Err(format!("failed reading the registers: {err:?}")),
+        ),
+    }
 
-    // Variable used to determine whether the top servo
-    // should go from top to bottom or from bottom to top
-    let mut go_up = false;
+    // Distance and signal strength at each pose, to check that they change as the head moves
+    let mut readings = Vec::new();
+    let mut measure = |rig: &mut Rig<_>| -> Result<SensorReading, String> {
+        rig.tfluna
+            .trigger_measurement()
+            .map_err(|err| format!("failed triggering a measurement: {err:?}"))?;
+        thread::sleep(measurement_delay);
+        let reading = rig
+            .tfluna
+            .get_measurement()
+            .map_err(|err| format!("failed reading a measurement: {err:?}"))?;
+        readings.push((reading.distance, reading.signal_strength));
+        Ok(reading)
+    };
+    let middle = |servo: &ServoConfig| (servo.min_angle + servo.max_angle) / 2.0;
+    let center = [middle(&config.servos.bottom), middle(&config.servos.top)];
+    rig.servos.set_angles(&center)?;
+    thread::sleep(Duration::from_millis(1000));
+    match measure(rig) {
+        Ok(reading) => {
+            report.check("Temperature", check_temperature(reading.temperature));
+            report.check(
+                "Signal strength",
+                check_signal_strength(reading.signal_strength),
+            );
+        }
+        Err(reason) => report.check("Sensor reading", Err(reason)),
+    }
 
-    while servo_bottom.is_angle_allowed(angle_bottom) {
-        servo_bottom.set_angle(angle_bottom).unwrap();
+    for (index, name, servo) in [
+        (BOTTOM, "Bottom servo", &config.servos.bottom),
+        (TOP, "Top servo", &config.servos.top),
+    ] {
+        let range = (servo.min_angle, servo.max_angle);
+        report.check(
+            &format!("{name} pulse widths"),
+            check_pulse_widths(&servo.calibration, servo.reversed, range),
+        );
+        // Angles past the limits must be refused before anything is sent to the servo
+        let outside = [range.0 - 1.0, range.1 + 1.0];
+        let refused = outside
+            .iter()
+            .all(|angle| rig.servos[index].set_angle(*angle).is_err());
+        report.check(
+            &format!("{name} limits"),
+            match refused {
+                true => Ok(format!(
+                    "angles outside of [{}, {}] are refused",
+                    range.0, range.1
+                )),
+                false => Err(format!(
+                    "an angle outside of [{}, {}] was accepted",
+                    range.0, range.1
+                )),
+            },
+        );
+        for angle in [range.0, range.1] {
+            if control.is_interrupted() {
+                break;
+            }
+            let result = rig.servos[index]
+                .set_angle(angle)
+                .map_err(|err| err.to_string())
+                .and_then(|_| {
+                    // Going from one end of the range to the other takes longer than a step
+                    thread::sleep(servo_motor_delay + Duration::from_millis(500));
+                    measure(rig)
+                })
+                .map(|reading| format!("reached, {} cm measured", reading.distance));
+            report.check(&format!("{name} at {angle}°"), result);
+        }
+        rig.servos.set_angles(&center)?;
         thread::sleep(servo_motor_delay);
+    }
+    report.check("Readings change", check_readings_change(&readings));
+    println!("{report}");
 
-        let mut angle_top = if go_up {
-            servo_top.get_min_angle()
-        } else {
-            servo_top.get_max_angle()
+    let rec = connect_rerun(config, recording_id)?;
+    rec.send_recording_name("selftest")?;
+    RecordingMetadata::collect(information.ok()).send(&rec, config)?;
+    for check in &report.checks {
+        let level = match check.passed {
+            true => rerun::TextLogLevel::INFO,
+            false => rerun::TextLogLevel::ERROR,
         };
+        rec.log(
+            LOG_ENTITY_PATH,
+            &rerun::TextLog::new(format!("{}: {}", check.name, check.reason)).with_level(level),
+        )?;
+    }
+    rec.flush_blocking();
+    match report.passed() {
+        true => Ok(()),
+        false => Err("Self-test failed".into()),
+    }
+}
 
-        while servo_top.is_angle_allowed(angle_top) {
-            servo_top.set_angle(angle_top).unwrap();
+// Sweeps the middle row in both directions and prints the latency
+// that makes the edges seen by both sweeps coincide
+fn estimate_sweep_latency(
+    config: &ScanConfig,
+    rig: &mut Rig<impl Servo>,
+    control: &ScanControl,
+) -> Result<(), Box<dyn Error>> {
+    let pitch = (rig.servos[TOP].get_min_angle() + rig.servos[TOP].get_max_angle()) / 2.0;
+    let forward = SweepRow {
+        pitch,
+        start_yaw: rig.servos[BOTTOM].get_min_angle(),
+        end_yaw: rig.servos[BOTTOM].get_max_angle(),
+    };
+    let backward = SweepRow {
+        pitch,
+        start_yaw: forward.end_yaw,
+        end_yaw: forward.start_yaw,
+    };
+    rig.tfluna
+        .set_framerate(config.sweep.framerate)
+        .map_err(sensor_error("setting the frame rate"))?;
+    rig.tfluna
+        .set_ranging_mode(RangingMode::Continuous)
+        .map_err(sensor_error("setting the ranging mode"))?;
+    let mut clock = SweepClock::new()?;
+    let mut frames = Vec::new();
+    for row in [forward, backward] {
+        let records = sweep_row(config, rig, &row, &mut clock, control)?;
+        frames.push(
+            records
+                .iter()
+                .map(|record| (record.yaw, record.distance))
+                .collect::<Vec<_>>(),
+        );
+    }
+    match estimate_latency(
+        &frames[0],
+        &frames[1],
+        config.sweep.speed,
+        config.sweep.latency,
+    ) {
+        Some(latency) => println!(
+            "Estimated sweep latency: {:.1} ms, set sweep.latency to this value",
+            latency
+        ),
+        None => println!("Not enough frames to estimate the sweep latency"),
+    }
+    Ok(())
+}
+
+// Steps a servo over its range towards increasing then decreasing angles,
+// the other servo staying in the middle of its range,
+// and prints the backlash that makes the edges seen in both directions coincide
+fn estimate_servo_backlash(
+    config: &ScanConfig,
+    rig: &mut Rig<impl Servo>,
+    servo: RigServo,
+    control: &ScanControl,
+) -> Result<(), Box<dyn Error>> {
+    let (index, other, name) = match servo {
+        RigServo::Bottom => (BOTTOM, TOP, "bottom"),
+        RigServo::Top => (TOP, BOTTOM, "top"),
+    };
+    let servo_motor_delay = Duration::from_millis(config.scan.servo_motor_delay as u64);
+    let measurement_delay = Duration::from_millis(config.sensor.measurement_delay as u64);
+    let middle = (rig.servos[other].get_min_angle() + rig.servos[other].get_max_angle()) / 2.0;
+    rig.servos[other].set_angle(middle)?;
+    let range = (
+        rig.servos[index].get_min_angle(),
+        rig.servos[index].get_max_angle(),
+    );
+    let angles: Vec<f32> = (0..grid_angle_count(range, config.scan.angle_step))
+        .map(|i| range.0 + i as f32 * config.scan.angle_step)
+        .collect();
+    let mut passes = Vec::new();
+    for increasing in [true, false] {
+        let mut frames = Vec::with_capacity(angles.len());
+        let mut ordered = angles.clone();
+        if !increasing {
+            ordered.reverse();
+        }
+        for angle in ordered {
+            if control.is_interrupted() {
+                return Ok(());
+            }
+            rig.servos[index].set_angle(angle)?;
             thread::sleep(servo_motor_delay);
+            let reading = rig.read_sensor(measurement_delay)?;
+            frames.push((angle, reading.distance));
+        }
+        passes.push(frames);
+    }
+    match estimate_backlash(&passes[0], &passes[1]) {
+        Some(backlash) => println!(
+            "Estimated backlash of the {name} servo: {backlash:.2} degrees, set servos.{name}.backlash.backlash to this value"
+        ),
+        None => println!("Not enough measurements to estimate the backlash"),
+    }
+    Ok(())
+}
 
-            tfluna.trigger_measurement().unwrap();
-            thread::sleep(Duration::from_millis(20));
-            let measurement = tfluna.get_measurement().unwrap();
-            thread::sleep(Duration::from_millis(20));
-            //println!("Yaw = {}, Pitch = {}, Distance = {}", angle_bottom, angle_top, measurement.distance);
-            // Helper variables
-            let yaw = (angle_bottom as f32).to_radians();
-            let pitch = (angle_top as f32).to_radians();
-            // Point 3D position
-            let px = (measurement.distance as f32) * (pitch as f32).cos() * (yaw as f32).sin();
-            let py = (measurement.distance as f32) * (pitch as f32).cos() * (yaw as f32).cos();
-            let pz = (measurement.distance as f32) * (pitch as f32).sin();
-            let position = [px, py, pz];
-            // Point's color based on distance
-            let color = g
-                .at((measurement.distance as f32 - args.minimum_distance)
-                    / (args.maximum_distance - args.minimum_distance))
-                .to_rgba8();
-            positions.push(position);
-            colors.push(color);
-
-            rec.set_time("capture_time", std::time::SystemTime::now());
-            rec.log(yaw_entity_path, &rerun::Scalars::single(angle_bottom))?;
-            rec.log(pitch_entity_path, &rerun::Scalars::single(angle_top))?;
-            rec.log(
-                distance_entity_path,
-                &rerun::Scalars::single(measurement.distance),
-            )?;
-            rec.log(
-                signal_strength_entity_path,
-                &rerun::Scalars::single(measurement.signal_strength),
-            )?;
-            rec.log(
-                temperature_entity_path,
-                &rerun::Scalars::single(measurement.temperature),
-            )?;
-            rec.log(
-                position_entity_path,
-                &rerun::Points3D::new(positions.clone())
-                    .with_colors(colors.clone())
-                    .with_radii([args.point_radius]),
-            )?;
+// Locates an edge by stepping a servo towards increasing angles, the other servo staying
+// in the middle of its range, then repeatedly steps the servo to `settle.margin` past the edge
+// while the sensor is in continuous ranging mode, and prints the median settle time of each step size.
+// The distance only stabilises once the beam stays past the edge,
+// that is once the servo stays within the margin of its target.
+fn characterise_settle_time(
+    config: &ScanConfig,
+    rig: &mut Rig<impl Servo>,
+    servo: RigServo,
+    control: &ScanControl,
+) -> Result<(), Box<dyn Error>> {
+    let (index, other, name) = match servo {
+        RigServo::Bottom => (BOTTOM, TOP, "bottom"),
+        RigServo::Top => (TOP, BOTTOM, "top"),
+    };
+    let settings = &config.settle;
+    let servo_motor_delay = Duration::from_millis(config.scan.servo_motor_delay as u64);
+    let measurement_delay = Duration::from_millis(config.sensor.measurement_delay as u64);
+    let middle = (rig.servos[other].get_min_angle() + rig.servos[other].get_max_angle()) / 2.0;
+    rig.servos[other].set_angle(middle)?;
+    let range = (
+        rig.servos[index].get_min_angle(),
+        rig.servos[index].get_max_angle(),
+    );
+    let mut frames = Vec::new();
+    for i in 0..grid_angle_count(range, settings.search_step) {
+        if control.is_interrupted() {
+            return Ok(());
+        }
+        let angle = range.0 + i as f32 * settings.search_step;
+        rig.servos[index].set_angle(angle)?;
+        thread::sleep(servo_motor_delay);
+        let reading = rig.read_sensor(measurement_delay)?;
+        frames.push((angle, reading.distance));
+    }
+    let Some(edge) = edge_yaw(&frames) else {
+        println!("Not enough measurements to locate an edge");
+        return Ok(());
+    };
+    let target = edge + settings.margin;
+    if target > range.1 {
+        return Err(format!(
+            "The edge at {edge:.1} degrees is too close to the end of the {name} servo range"
+        )
+        .into());
+    }
+    println!("Edge at {edge:.1} degrees, the steps end at {target:.1} degrees");
 
-            // Increment/decrement after recording the measurements
-            if go_up {
-                angle_top += angle_step;
-            } else {
-                angle_top -= angle_step;
+    rig.tfluna
+        .set_framerate(settings.framerate)
+        .map_err(sensor_error("setting the frame rate"))?;
+    rig.tfluna
+        .set_ranging_mode(RangingMode::Continuous)
+        .map_err(sensor_error("setting the ranging mode"))?;
+    let mut clock = SweepClock::new()?;
+    let mut estimates = Vec::new();
+    'steps: for &step in &settings.step_sizes {
+        let start = target - step;
+        if step <= settings.margin || start < range.0 {
+            println!(
+                "Skipping the step of {step} degrees, which must start before the edge and within the servo range"
+            );
+            continue;
+        }
+        for _ in 0..settings.repetitions {
+            if control.is_interrupted() {
+                break 'steps;
+            }
+            // The servo rests before each step, so that it starts still
+            rig.servos[index].set_angle(start)?;
+            thread::sleep(settings.duration());
+            let frames = record_step(rig, index, target, settings, &mut clock, control)?;
+            match estimate_settle_time(&frames, settings.tolerance) {
+                Some(settle_time) => estimates.push((step, settle_time)),
+                None => println!(
+                    "The distance did not change after a step of {step} degrees, is the edge still there?"
+                ),
             }
         }
-        angle_bottom += angle_step;
-        go_up = !go_up;
     }
-    // Go back to neutral position
-    servo_bottom.set_angle(0.0).unwrap();
-    servo_top.set_angle(0.0).unwrap();
-    thread::sleep(Duration::from_millis(1000));
+    rig.tfluna
+        .set_ranging_mode(RangingMode::Trigger)
+        .map_err(sensor_error("setting the ranging mode"))?;
+
+    let table = settle_time_table(&estimates);
+    if table.is_empty() {
+        println!("Not enough steps to estimate settle times");
+        return Ok(());
+    }
+    println!("Settle time of the {name} servo:");
+    for [step, settle_time] in &table {
+        println!("  {step:>6.1} degrees: {settle_time:>6.0} ms");
+    }
+    let entries: Vec<String> = table
+        .iter()
+        .map(|[step, settle_time]| format!("[{step:.1}, {settle_time:.0}.0]"))
+        .collect();
+    println!(
+        "Set servos.{name}.settle_times to [{}] for scans to wait this long after each move",
+        entries.join(", ")
+    );
     Ok(())
 }
+
+// Commands a servo to `angle` while the sensor is in continuous ranging mode and returns
+// the frames measured during the following `settle.duration`,
+// as (milliseconds since the command, distance)
+fn record_step(
+    rig: &mut Rig<impl Servo>,
+    index: usize,
+    angle: f32,
+    settings: &SettleSettings,
+    clock: &mut SweepClock,
+    control: &ScanControl,
+) -> Result<Vec<(f64, u16)>, Box<dyn Error>> {
+    // Polling twice per frame period so that no frame is missed
+    let polling_interval = Duration::from_secs_f64(0.5 / settings.framerate.max(1) as f64);
+    let command_time = clock.now();
+    rig.servos[index].set_angle(angle)?;
+    let end_time = command_time + settings.duration as f64;
+    let mut frames: Vec<(u64, u16)> = Vec::new();
+    while clock.now() <= end_time && !control.is_interrupted() {
+        let frame = rig
+            .tfluna
+            .get_measurement()
+            .map_err(sensor_error("reading the sensor"))?;
+        let sensor_time = clock.sensor.observe(frame.timestamp, clock.now());
+        // The same frame is read again until the next one is measured
+        if frames.last().is_none_or(|(time, _)| *time != sensor_time) {
+            frames.push((sensor_time, frame.distance));
+        }
+        thread::sleep(polling_interval);
+    }
+    // Frames measured before the command are still read right after it
+    Ok(frames
+        .into_iter()
+        .filter_map(|(sensor_time, distance)| {
+            let time = clock.sensor.to_host(sensor_time)? - command_time;
+            (time >= 0.0).then_some((time, distance))
+        })
+        .collect())
+}
@@ -0,0 +1,275 @@
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::point_cloud::PointRecord;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, std::io::Error),
+    Json(PathBuf, serde_json::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(path, err) => write!(f, "Failed accessing '{}': {err}", path.display()),
+            Error::Json(path, err) => write!(f, "Invalid checkpoint '{}': {err}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Progress of a scan, saved periodically so that an interrupted scan can be resumed.
+///
+/// The file is made of JSON lines: a header with the recording id and the configuration,
+/// then one line per record. Later saves only append the new records,
+/// so that the cost of saving does not grow with the size of the scan.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    /// Recording id of the rerun recording, reused when resuming
+    pub recording_id: String,
+    /// Fully resolved configuration of the scan, as a TOML document
+    pub config: String,
+    /// Measurements of the completed poses, in the order in which the poses were visited
+    pub records: Vec<PointRecord>,
+    // File and number of records it holds, to which the next save appends,
+    // `None` if the whole file must be written
+    saved: Option<(PathBuf, usize)>,
+}
+
+// First line of a checkpoint file
+#[derive(Serialize, Deserialize)]
+struct Header {
+    recording_id: String,
+    config: String,
+}
+
+impl Checkpoint {
+    pub fn new(recording_id: impl Into<String>, config: impl Into<String>) -> Self {
+        Checkpoint {
+            recording_id: recording_id.into(),
+            config: config.into(),
+            records: Vec::new(),
+            saved: None,
+        }
+    }
+
+    /// Number of poses that were completed, i.e. index of the next pose
+    pub fn completed_poses(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Reads a checkpoint file. A last record cut short by an interruption while saving
+    /// is dropped, and the file is written again by the next save.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let json_error = |err| Error::Json(path.to_path_buf(), err);
+        let contents =
+            fs::read_to_string(path).map_err(|err| Error::Io(path.to_path_buf(), err))?;
+        let mut lines = contents.lines();
+        let header: Header =
+            serde_json::from_str(lines.next().unwrap_or_default()).map_err(json_error)?;
+        let mut records = Vec::new();
+        let mut is_truncated = false;
+        while let Some(line) = lines.next() {
+            match serde_json::from_str(line) {
+                Ok(record) => records.push(record),
+                // Only the last line can be incomplete, it has no line break
+                Err(_) if lines.clone().next().is_none() && !contents.ends_with('\n') => {
+                    is_truncated = true;
+                }
+                Err(err) => return Err(json_error(err)),
+            }
+        }
+        let saved = (!is_truncated).then(|| (path.to_path_buf(), records.len()));
+        Ok(Checkpoint {
+            recording_id: header.recording_id,
+            config: header.config,
+            records,
+            saved,
+        })
+    }
+
+    /// Appends the records added since the last save to the file,
+    /// or writes the whole checkpoint to a temporary file first and then renames it,
+    /// so that an interruption while saving does not corrupt the previous checkpoint
+    pub fn save(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        match &self.saved {
+            Some((saved_path, saved_records))
+                if saved_path == path && *saved_records <= self.records.len() =>
+            {
+                let file = OpenOptions::new()
+                    .append(true)
+                    .open(path)
+                    .map_err(|err| Error::Io(path.to_path_buf(), err))?;
+                self.write_records(path, file, *saved_records)?;
+            }
+            _ => {
+                let mut temporary_path = path.as_os_str().to_owned();
+                temporary_path.push(".tmp");
+                let temporary_path = PathBuf::from(temporary_path);
+                let mut file = fs::File::create(&temporary_path)
+                    .map_err(|err| Error::Io(temporary_path.clone(), err))?;
+                let header = Header {
+                    recording_id: self.recording_id.clone(),
+                    config: self.config.clone(),
+                };
+                serde_json::to_writer(&mut file, &header)
+                    .map_err(|err| Error::Json(temporary_path.clone(), err))?;
+                writeln!(file).map_err(|err| Error::Io(temporary_path.clone(), err))?;
+                self.write_records(&temporary_path, file, 0)?;
+                fs::rename(&temporary_path, path)
+                    .map_err(|err| Error::Io(path.to_path_buf(), err))?;
+            }
+        }
+        self.saved = Some((path.to_path_buf(), self.records.len()));
+        Ok(())
+    }
+
+    // Writes the records from the given index, one per line
+    fn write_records(&self, path: &Path, file: fs::File, first: usize) -> Result<(), Error> {
+        let mut writer = BufWriter::new(file);
+        for record in &self.records[first..] {
+            serde_json::to_writer(&mut writer, record)
+                .map_err(|err| Error::Json(path.to_path_buf(), err))?;
+            writeln!(writer).map_err(|err| Error::Io(path.to_path_buf(), err))?;
+        }
+        writer
+            .flush()
+            .map_err(|err| Error::Io(path.to_path_buf(), err))
+    }
+
+    /// Removes the checkpoint file once the scan is complete
+    pub fn remove(path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(Error::Io(path.to_path_buf(), err))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(index: usize) -> PointRecord {
+        PointRecord {
+            position: [index as f32, 100.0, -0.5],
+            distance: 100 + index as u16,
+            signal_strength: 1000,
+            temperature: 35.25,
+            yaw: index as f32 * 2.0,
+            pitch: -3.0,
+            capture_time: 1.7e9 + index as f64 * 0.1,
+            distance_spread: 0.5,
+        }
+    }
+
+    fn checkpoint(records: usize) -> Checkpoint {
+        let mut checkpoint = Checkpoint::new("rpi-lidar-1", "[scan]\nangle_step = 2.0\n");
+        checkpoint.records = (0..records).map(record).collect();
+        checkpoint
+    }
+
+    // Checkpoint file of a test, removed if left by a previous run
+    fn checkpoint_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "tfluna-checkpoint-{name}-{}.jsonl",
+            std::process::id()
+        ));
+        Checkpoint::remove(&path).unwrap();
+        path
+    }
+
+    fn line_count(path: &Path) -> usize {
+        fs::read_to_string(path).unwrap().lines().count()
+    }
+
+    #[test]
+    fn completed_poses_is_the_number_of_records() {
+        assert_eq!(checkpoint(0).completed_poses(), 0);
+        assert_eq!(checkpoint(3).completed_poses(), 3);
+    }
+
+    #[test]
+    fn load_returns_the_saved_checkpoint() {
+        let path = checkpoint_path("roundtrip");
+        let mut saved = checkpoint(3);
+        saved.save(&path).unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap(), saved);
+        Checkpoint::remove(&path).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn save_appends_new_records() {
+        let path = checkpoint_path("append");
+        let mut saved = checkpoint(2);
+        saved.save(&path).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        saved.records.push(record(2));
+        saved.save(&path).unwrap();
+        // The header and the previous records are left as they were
+        let appended = fs::read_to_string(&path).unwrap();
+        assert!(appended.starts_with(&contents));
+        assert_eq!(line_count(&path), 4);
+        assert_eq!(Checkpoint::load(&path).unwrap().records, saved.records);
+        Checkpoint::remove(&path).unwrap();
+    }
+
+    #[test]
+    fn save_to_another_file_writes_the_whole_checkpoint() {
+        let (first, second) = (checkpoint_path("first"), checkpoint_path("second"));
+        let mut saved = checkpoint(2);
+        saved.save(&first).unwrap();
+        saved.records.push(record(2));
+        saved.save(&second).unwrap();
+        assert_eq!(line_count(&first), 3);
+        assert_eq!(line_count(&second), 4);
+        let mut temporary_path = second.clone().into_os_string();
+        temporary_path.push(".tmp");
+        assert!(!PathBuf::from(temporary_path).exists());
+        Checkpoint::remove(&first).unwrap();
+        Checkpoint::remove(&second).unwrap();
+    }
+
+    #[test]
+    fn load_drops_a_truncated_last_record() {
+        let path = checkpoint_path("truncated");
+        let mut saved = checkpoint(2);
+        saved.save(&path).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "{{\"position\":[2.0,10").unwrap();
+        drop(file);
+        let mut loaded = Checkpoint::load(&path).unwrap();
+        assert_eq!(loaded.records, saved.records);
+        // The next save rewrites the file instead of appending to the truncated line
+        loaded.records.push(record(2));
+        loaded.save(&path).unwrap();
+        assert_eq!(line_count(&path), 4);
+        assert_eq!(Checkpoint::load(&path).unwrap(), loaded);
+        Checkpoint::remove(&path).unwrap();
+    }
+
+    #[test]
+    fn load_refuses_an_invalid_record_before_the_last() {
+        let path = checkpoint_path("invalid");
+        let mut saved = checkpoint(1);
+        saved.save(&path).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        writeln!(file, "{{\"position\":[2.0,10").unwrap();
+        drop(file);
+        saved.records.push(record(1));
+        saved.save(&path).unwrap();
+        assert!(matches!(Checkpoint::load(&path), Err(Error::Json(..))));
+        Checkpoint::remove(&path).unwrap();
+    }
+}
@@ -0,0 +1,217 @@
+use std::str::FromStr;
+
+use colorgrad::Gradient;
+use serde::{Deserialize, Serialize};
+
+use crate::config::ColorConfig;
+use crate::point_cloud::PointRecord;
+use crate::range_image::is_valid_measurement;
+
+/// Measurement channel from which the color of a point is computed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorChannel {
+    /// Distance in centimeters
+    Distance,
+    SignalStrength,
+    /// Temperature in degrees Celsius
+    Temperature,
+    /// Z coordinate in centimeters in the base frame of the pan-tilt head
+    Height,
+    /// Index of the point in the scan, i.e. the order in which the points were measured
+    Order,
+    /// Valid points are all drawn with the end of the gradient, see `is_valid_measurement`
+    Validity,
+}
+
+impl FromStr for ColorChannel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "distance" => Ok(ColorChannel::Distance),
+            "signal-strength" => Ok(ColorChannel::SignalStrength),
+            "temperature" => Ok(ColorChannel::Temperature),
+            "height" => Ok(ColorChannel::Height),
+            "order" => Ok(ColorChannel::Order),
+            "validity" => Ok(ColorChannel::Validity),
+            _ => Err(format!(
+                "Unknown color channel '{s}', expected one of: \
+                 distance, signal-strength, temperature, height, order, validity"
+            )),
+        }
+    }
+}
+
+/// Color gradient presets of colorgrad
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Colormap {
+    Spectral,
+    Turbo,
+    Viridis,
+    Inferno,
+    Magma,
+    Plasma,
+    Cividis,
+    Rainbow,
+    Sinebow,
+    Greys,
+}
+
+impl Colormap {
+    pub fn gradient(&self) -> Box<dyn Gradient> {
+        use colorgrad::preset;
+        match self {
+            Colormap::Spectral => Box::new(preset::spectral()),
+            Colormap::Turbo => Box::new(preset::turbo()),
+            Colormap::Viridis => Box::new(preset::viridis()),
+            Colormap::Inferno => Box::new(preset::inferno()),
+            Colormap::Magma => Box::new(preset::magma()),
+            Colormap::Plasma => Box::new(preset::plasma()),
+            Colormap::Cividis => Box::new(preset::cividis()),
+            Colormap::Rainbow => Box::new(preset::rainbow()),
+            Colormap::Sinebow => Box::new(preset::sinebow()),
+            Colormap::Greys => Box::new(preset::greys()),
+        }
+    }
+}
+
+impl FromStr for Colormap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "spectral" => Ok(Colormap::Spectral),
+            "turbo" => Ok(Colormap::Turbo),
+            "viridis" => Ok(Colormap::Viridis),
+            "inferno" => Ok(Colormap::Inferno),
+            "magma" => Ok(Colormap::Magma),
+            "plasma" => Ok(Colormap::Plasma),
+            "cividis" => Ok(Colormap::Cividis),
+            "rainbow" => Ok(Colormap::Rainbow),
+            "sinebow" => Ok(Colormap::Sinebow),
+            "greys" => Ok(Colormap::Greys),
+            _ => Err(format!(
+                "Unknown colormap '{s}', expected one of: spectral, turbo, viridis, \
+                 inferno, magma, plasma, cividis, rainbow, sinebow, greys"
+            )),
+        }
+    }
+}
+
+/// Value of a channel for the point measured at position `order` in the scan
+pub fn channel_value(channel: ColorChannel, record: &PointRecord, order: usize) -> f32 {
+    match channel {
+        ColorChannel::Distance => record.distance as f32,
+        ColorChannel::SignalStrength => record.signal_strength as f32,
+        ColorChannel::Temperature => record.temperature,
+        ColorChannel::Height => record.position[2],
+        ColorChannel::Order => order as f32,
+        ColorChannel::Validity => 1.0,
+    }
+}
+
+/// Colors the points of a scan, in the order in which they were measured.
+///
+/// The channel value is mapped onto the gradient between the configured bounds,
+/// or between running percentiles of the values of the valid points seen so far
+/// when autoscaling. Invalid points get the flag color of the configuration
+/// and are not taken into account by the autoscaling.
+pub struct PointColorizer {
+    channel: ColorChannel,
+    gradient: Box<dyn Gradient>,
+    /// Bounds mapped to the start and end of the gradient, `None` when autoscaling
+    range: Option<(f32, f32)>,
+    percentiles: [f32; 2],
+    invalid_color: [u8; 4],
+    /// Number of points colored so far
+    count: usize,
+    /// Values of the valid points seen so far, sorted
+    values: Vec<f32>,
+}
+
+impl PointColorizer {
+    pub fn new(config: &ColorConfig) -> Self {
+        let range = match config.channel {
+            ColorChannel::Validity => Some((0.0, 1.0)),
+            _ if config.autoscale => None,
+            ColorChannel::Distance => Some((config.minimum_distance, config.maximum_distance)),
+            _ => config.minimum.zip(config.maximum),
+        };
+        PointColorizer {
+            channel: config.channel,
+            gradient: config.colormap.gradient(),
+            range,
+            percentiles: config.percentiles,
+            invalid_color: config.invalid_color,
+            count: 0,
+            values: Vec::new(),
+        }
+    }
+
+    /// Colors of all the points of a finished scan.
+    /// When autoscaling, the percentiles of the whole scan are used for every point.
+    pub fn colors(config: &ColorConfig, records: &[PointRecord]) -> Vec<[u8; 4]> {
+        let mut colorizer = PointColorizer::new(config);
+        for record in records {
+            colorizer.observe(record);
+        }
+        records
+            .iter()
+            .enumerate()
+            .map(|(order, record)| colorizer.color(record, order))
+            .collect()
+    }
+
+    pub fn gradient(&self) -> &dyn Gradient {
+        self.gradient.as_ref()
+    }
+
+    /// Color of the next point of the scan
+    pub fn push(&mut self, record: &PointRecord) -> [u8; 4] {
+        self.observe(record);
+        self.color(record, self.count - 1)
+    }
+
+    // Counts the point and adds its value to the running percentiles
+    fn observe(&mut self, record: &PointRecord) {
+        let value = channel_value(self.channel, record, self.count);
+        self.count += 1;
+        if self.range.is_none()
+            && is_valid_measurement(record.distance, record.signal_strength)
+            && value.is_finite()
+        {
+            let index = self.values.partition_point(|v| *v < value);
+            self.values.insert(index, value);
+        }
+    }
+
+    fn color(&self, record: &PointRecord, order: usize) -> [u8; 4] {
+        if !is_valid_measurement(record.distance, record.signal_strength) {
+            return self.invalid_color;
+        }
+        let (min, max) = self.range.unwrap_or_else(|| {
+            (
+                self.percentile(self.percentiles[0]),
+                self.percentile(self.percentiles[1]),
+            )
+        });
+        let value = channel_value(self.channel, record, order);
+        // A single value or constant channel is drawn with the middle of the gradient
+        let t = match max - min {
+            span if span > 0.0 => (value - min) / span,
+            _ => 0.5,
+        };
+        self.gradient.at(t).to_rgba8()
+    }
+
+    // Nearest-rank percentile of the values seen so far, 0 if there are none
+    fn percentile(&self, percentile: f32) -> f32 {
+        if self.values.is_empty() {
+            return 0.0;
+        }
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * (self.values.len() - 1) as f32).round();
+        self.values[rank as usize]
+    }
+}
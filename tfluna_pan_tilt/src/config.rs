@@ -0,0 +1,483 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::adaptive::AdaptiveSettings;
+use crate::backlash::BacklashConfig;
+use crate::coloring::{ColorChannel, Colormap};
+use crate::kinematics::PanTiltKinematics;
+use crate::laser_scan::PlanarSettings;
+use crate::point_cloud::PointCloudFormat;
+use crate::point_cloud_logger::LoggingStrategy;
+use crate::range_image::RangeImageFormat;
+use crate::sampling::Aggregation;
+use crate::scan::{ScanMode, ScanPattern};
+use crate::servo::{ServoCalibration, ServoDriver};
+use crate::settle::SettleSettings;
+use crate::simulation::SimulationConfig;
+use crate::sweep::SweepSettings;
+use crate::timelapse::TimelapseSettings;
+use crate::tracking::TrackingSettings;
+
+/// Built-in presets, as partial configuration files applied on top of the defaults
+pub const PRESETS: [(&str, &str); 3] = [
+    ("quick", include_str!("../presets/quick.toml")),
+    ("fine", include_str!("../presets/fine.toml")),
+    ("wall-test", include_str!("../presets/wall-test.toml")),
+];
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, std::io::Error),
+    Parse(String, toml::de::Error),
+    Serialize(toml::ser::Error),
+    UnknownPreset(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(path, err) => write!(f, "Failed reading '{}': {err}", path.display()),
+            Error::Parse(source, err) => write!(f, "Invalid configuration in {source}: {err}"),
+            Error::Serialize(err) => write!(f, "Failed serializing configuration: {err}"),
+            Error::UnknownPreset(name) => {
+                let names: Vec<&str> = PRESETS.iter().map(|(name, _)| *name).collect();
+                write!(
+                    f,
+                    "Unknown preset '{name}', expected one of: {}",
+                    names.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Complete description of a scan job
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScanConfig {
+    pub servos: ServosConfig,
+    pub scan: ScanSettings,
+    /// Only used when `scan.mode` is adaptive
+    pub adaptive: AdaptiveSettings,
+    /// Only used when `scan.mode` is sweep
+    pub sweep: SweepSettings,
+    /// Only used when `scan.mode` is track
+    pub tracking: TrackingSettings,
+    /// Only used when `scan.mode` is planar
+    pub planar: PlanarSettings,
+    /// Only used with `--characterise-settle-time`
+    pub settle: SettleSettings,
+    /// Only used with `--timelapse`
+    pub timelapse: TimelapseSettings,
+    pub sensor: SensorConfig,
+    pub kinematics: PanTiltKinematics,
+    pub color: ColorConfig,
+    pub output: OutputConfig,
+    /// Only used with `--simulate`
+    pub simulation: SimulationConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServosConfig {
+    pub driver: ServoDriver,
+    /// PWM frequency of the servo outputs in Hz
+    pub frequency: f32,
+    /// Measured frequency of the PCA9685 internal oscillator in Hz,
+    /// `servo::NOMINAL_OSCILLATOR_FREQUENCY` if not given
+    pub oscillator_frequency: Option<f32>,
+    /// Pan servo, whose angle is the yaw
+    pub bottom: ServoConfig,
+    /// Tilt servo, whose angle is the pitch
+    pub top: ServoConfig,
+}
+
+impl Default for ServosConfig {
+    fn default() -> Self {
+        ServosConfig {
+            driver: ServoDriver::Pca9685,
+            frequency: 50.0,
+            oscillator_frequency: None,
+            bottom: ServoConfig {
+                channel: 14,
+                ..Default::default()
+            },
+            top: ServoConfig {
+                channel: 15,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServoConfig {
+    /// PCA9685 channel, between 0 and 15,
+    /// or hardware PWM channel, between 0 and 3, depending on `ServosConfig::driver`
+    pub channel: u8,
+    /// Minimum angle in degrees, also the start of the scanned range
+    pub min_angle: f32,
+    /// Maximum angle in degrees, also the end of the scanned range
+    pub max_angle: f32,
+    pub reversed: bool,
+    pub calibration: ServoCalibration,
+    pub backlash: BacklashConfig,
+    /// Settle times in milliseconds per step size in degrees, `[step, settle_time]` sorted by step,
+    /// measured with `--characterise-settle-time`.
+    /// When given, they replace `ScanSettings::servo_motor_delay` after the moves of grid,
+    /// adaptive and planar scans, see `settle::settle_delay`.
+    pub settle_times: Vec<[f32; 2]>,
+}
+
+impl Default for ServoConfig {
+    fn default() -> Self {
+        ServoConfig {
+            channel: 0,
+            min_angle: -30.0,
+            max_angle: 30.0,
+            reversed: true,
+            calibration: ServoCalibration::default(),
+            backlash: BacklashConfig::default(),
+            settle_times: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScanSettings {
+    pub mode: ScanMode,
+    /// Size of servo motor angle increment in degrees,
+    /// the spacing of the coarse grid in adaptive mode and between rows in sweep mode
+    pub angle_step: f32,
+    pub pattern: ScanPattern,
+    /// Delay in milliseconds after servo motor command
+    pub servo_motor_delay: u32,
+}
+
+impl Default for ScanSettings {
+    fn default() -> Self {
+        ScanSettings {
+            mode: ScanMode::Grid,
+            angle_step: 30.0,
+            pattern: ScanPattern::Serpentine,
+            servo_motor_delay: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SensorConfig {
+    /// Delay in milliseconds after triggering a measurement and after reading it
+    pub measurement_delay: u32,
+    /// Number of measurements made at each pose of grid, adaptive and planar scans
+    pub samples_per_pose: u32,
+    /// How the measurements of a pose are combined into a single point
+    pub aggregation: Aggregation,
+    /// Fraction of the measurements removed at each end by the trimmed mean
+    pub trim_fraction: f32,
+    /// Number of times a failed sensor access is attempted again before the scan fails
+    pub retries: u32,
+}
+
+impl Default for SensorConfig {
+    fn default() -> Self {
+        SensorConfig {
+            measurement_delay: 20,
+            samples_per_pose: 1,
+            aggregation: Aggregation::Median,
+            trim_fraction: 0.2,
+            retries: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorConfig {
+    /// Measurement from which the color of the points is computed
+    pub channel: ColorChannel,
+    pub colormap: Colormap,
+    /// Distance in centimeters mapped to the start of the color gradient
+    pub minimum_distance: f32,
+    /// Distance in centimeters mapped to the end of the color gradient
+    pub maximum_distance: f32,
+    /// Value mapped to the start of the color gradient for the other channels,
+    /// in the unit of the channel
+    pub minimum: Option<f32>,
+    /// Value mapped to the end of the color gradient for the other channels.
+    /// Channels other than distance are autoscaled unless both bounds are set.
+    pub maximum: Option<f32>,
+    /// Map the running percentiles of the channel onto the gradient instead of fixed bounds
+    pub autoscale: bool,
+    /// Lower and upper percentiles used when autoscaling
+    pub percentiles: [f32; 2],
+    /// RGBA color of the points whose distance is invalid
+    pub invalid_color: [u8; 4],
+    /// Radius of points in centimeters for viewer
+    pub point_radius: f32,
+}
+
+impl Default for ColorConfig {
+    fn default() -> Self {
+        ColorConfig {
+            channel: ColorChannel::Distance,
+            colormap: Colormap::Spectral,
+            minimum_distance: 10.0,
+            maximum_distance: 200.0,
+            minimum: None,
+            maximum: None,
+            autoscale: false,
+            percentiles: [2.0, 98.0],
+            // Magenta, which none of the gradients contain
+            invalid_color: [255, 0, 255, 255],
+            point_radius: 0.1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// IP Address of a running rerun server
+    pub rerun_server_ip: String,
+    /// If set, the recording is saved to this .rrd file instead of being sent to the rerun server
+    pub rrd_file: Option<PathBuf>,
+    pub logging_strategy: LoggingStrategy,
+    /// File to which the point cloud is written at the end of the scan
+    pub point_cloud: Option<PathBuf>,
+    /// Point cloud file format, deduced from the file extension by default
+    pub point_cloud_format: Option<PointCloudFormat>,
+    /// Prefix of the files to which the range image is written at the end of the scan
+    pub range_image: Option<PathBuf>,
+    pub range_image_format: RangeImageFormat,
+    /// File to which planar scans are written as a JSON laser scan
+    pub laser_scan: Option<PathBuf>,
+    /// File to which the events of the scans are appended as JSON lines, see `events::EventRecord`
+    pub event_log: Option<PathBuf>,
+    /// If set, the progress of the scan is saved to this file so that it can be resumed
+    pub checkpoint: Option<PathBuf>,
+    /// Number of poses between two saves of the checkpoint
+    pub checkpoint_interval: usize,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            rerun_server_ip: String::from("10.181.190.150"),
+            rrd_file: None,
+            logging_strategy: LoggingStrategy::Row,
+            point_cloud: None,
+            point_cloud_format: None,
+            range_image: None,
+            range_image_format: RangeImageFormat::Png,
+            laser_scan: None,
+            event_log: None,
+            checkpoint: None,
+            checkpoint_interval: 10,
+        }
+    }
+}
+
+impl ScanConfig {
+    /// Builds a configuration by applying, in order, the defaults,
+    /// the given preset and the given configuration file.
+    pub fn load(preset: Option<&str>, path: Option<&Path>) -> Result<ScanConfig, Error> {
+        let mut table = toml::Table::try_from(ScanConfig::default()).map_err(Error::Serialize)?;
+        if let Some(name) = preset {
+            let preset = PRESETS
+                .iter()
+                .find(|(preset_name, _)| *preset_name == name)
+                .map(|(_, contents)| *contents)
+                .ok_or(Error::UnknownPreset(name.to_string()))?;
+            merge_tables(
+                &mut table,
+                parse_overlay(preset, format!("preset '{name}'"))?,
+            );
+        }
+        if let Some(path) = path {
+            let contents =
+                fs::read_to_string(path).map_err(|err| Error::Io(path.to_path_buf(), err))?;
+            let source = format!("'{}'", path.display());
+            merge_tables(&mut table, parse_overlay(&contents, source)?);
+        }
+        table
+            .try_into()
+            .map_err(|err| Error::Parse(String::from("merged configuration"), err))
+    }
+
+    /// Fully resolved configuration as a TOML document
+    pub fn to_toml(&self) -> Result<String, Error> {
+        toml::to_string(self).map_err(Error::Serialize)
+    }
+
+    /// Copy of this configuration with the values of a partial configuration file applied,
+    /// e.g. the body of a scan request sent to the remote control server
+    pub fn with_overlay(&self, contents: &str, source: &str) -> Result<ScanConfig, Error> {
+        let mut table = toml::Table::try_from(self).map_err(Error::Serialize)?;
+        merge_tables(&mut table, parse_overlay(contents, source.to_string())?);
+        table
+            .try_into()
+            .map_err(|err| Error::Parse(String::from("merged configuration"), err))
+    }
+
+    /// Dotted names of the values that differ from those of another configuration,
+    /// e.g. `scan.angle_step`
+    pub fn differences(&self, other: &ScanConfig) -> Result<Vec<String>, Error> {
+        let table = |config: &ScanConfig| toml::Table::try_from(config).map_err(Error::Serialize);
+        let mut differences = Vec::new();
+        table_differences(&table(self)?, &table(other)?, "", &mut differences);
+        Ok(differences)
+    }
+
+    /// Inverse of `to_toml`
+    pub fn from_toml(contents: &str) -> Result<ScanConfig, Error> {
+        toml::from_str(contents).map_err(|err| Error::Parse(String::from("checkpoint"), err))
+    }
+}
+
+// Parse a partial configuration, checking it on its own
+// so that errors point to the file they come from
+fn parse_overlay(contents: &str, source: String) -> Result<toml::Table, Error> {
+    let overlay = contents
+        .parse::<toml::Table>()
+        .map_err(|err| Error::Parse(source.clone(), err))?;
+    ScanConfig::deserialize(overlay.clone()).map_err(|err| Error::Parse(source, err))?;
+    Ok(overlay)
+}
+
+// Collects the keys whose values differ between both tables, with the given prefix
+fn table_differences(
+    first: &toml::Table,
+    second: &toml::Table,
+    prefix: &str,
+    differences: &mut Vec<String>,
+) {
+    let mut keys: Vec<&String> = first.keys().chain(second.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    for key in keys {
+        let name = format!("{prefix}{key}");
+        match (first.get(key), second.get(key)) {
+            (Some(toml::Value::Table(first)), Some(toml::Value::Table(second))) => {
+                table_differences(first, second, &format!("{name}."), differences);
+            }
+            (first, second) if first != second => differences.push(name),
+            _ => {}
+        }
+    }
+}
+
+// Recursively overwrite the values of base with those of overlay
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_tables(base_table, overlay_table);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::tests::test_path;
+
+    #[test]
+    fn overlay_overrides_only_its_keys() {
+        let base = ScanConfig::default();
+        let config = base
+            .with_overlay("[scan]\nangle_step = 5.0\n", "test")
+            .unwrap();
+        assert_eq!(config.scan.angle_step, 5.0);
+        let mut expected = base.clone();
+        expected.scan.angle_step = 5.0;
+        assert_eq!(config, expected);
+    }
+
+    #[test]
+    fn nested_tables_are_merged() {
+        let mut base = ScanConfig::default();
+        base.servos.top.channel = 3;
+        base.servos.top.min_angle = -10.0;
+        let config = base
+            .with_overlay("[servos.top.calibration]\nfirst_angle = -80.0\n", "test")
+            .unwrap();
+        assert_eq!(config.servos.top.calibration.first_angle, -80.0);
+        assert_eq!(
+            config.servos.top.calibration.second_angle,
+            base.servos.top.calibration.second_angle
+        );
+        assert_eq!(config.servos.top.channel, 3);
+        assert_eq!(config.servos.top.min_angle, -10.0);
+        assert_eq!(config.servos.bottom, base.servos.bottom);
+    }
+
+    #[test]
+    fn unknown_keys_are_refused() {
+        let base = ScanConfig::default();
+        for overlay in [
+            "angle_step = 5.0\n",
+            "[scan]\nangle_stpe = 5.0\n",
+            "[scna]\n",
+        ] {
+            let err = base.with_overlay(overlay, "test").unwrap_err();
+            assert!(matches!(err, Error::Parse(ref source, _) if source == "test"));
+        }
+    }
+
+    #[test]
+    fn file_is_applied_after_the_preset() {
+        let path = test_path("config-overlay", "toml");
+        fs::write(&path, "[scan]\nservo_motor_delay = 50\n").unwrap();
+        let config = ScanConfig::load(Some("quick"), Some(&path));
+        fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+        assert_eq!(config.scan.angle_step, 10.0);
+        assert_eq!(config.scan.servo_motor_delay, 50);
+        assert!(matches!(
+            ScanConfig::load(Some("slow"), None),
+            Err(Error::UnknownPreset(_))
+        ));
+    }
+
+    #[test]
+    fn differences_are_dotted_names() {
+        let base = ScanConfig::default();
+        let config = base
+            .with_overlay(
+                "[scan]\nangle_step = 5.0\n[servos.top.calibration]\nfirst_angle = -80.0\n\
+                 [output]\npoint_cloud = 'scan.ply'\n",
+                "test",
+            )
+            .unwrap();
+        assert_eq!(
+            base.differences(&config).unwrap(),
+            [
+                "output.point_cloud",
+                "scan.angle_step",
+                "servos.top.calibration.first_angle"
+            ]
+        );
+        assert!(config.differences(&config).unwrap().is_empty());
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let config = ScanConfig::load(Some("fine"), None).unwrap();
+        assert_eq!(
+            ScanConfig::from_toml(&config.to_toml().unwrap()).unwrap(),
+            config
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use rerun::{RecordingStream, RecordingStreamError};
+use serde::{Deserialize, Serialize};
+
+use crate::scan::ScanMode;
+
+/// Entity under which the sequence number of each event is logged, as `<EVENTS_ENTITY_PATH>/<kind>`
+pub const EVENTS_ENTITY_PATH: &str = "events";
+
+// Kinds of events, see `Event::kind`
+pub const SCAN_STARTED: &str = "scan-started";
+pub const POSE: &str = "pose";
+pub const ROW_FINISHED: &str = "row-finished";
+pub const INVALID_READING: &str = "invalid-reading";
+pub const RETRY: &str = "retry";
+pub const RECOVERED: &str = "recovered";
+pub const TARGET_ACQUIRED: &str = "target-acquired";
+pub const TARGET_LOST: &str = "target-lost";
+pub const NO_TARGET: &str = "no-target";
+pub const REFERENCE_COMPARED: &str = "reference-compared";
+pub const SCAN_COMPLETED: &str = "scan-completed";
+pub const SCAN_ABORTED: &str = "scan-aborted";
+pub const SCAN_FAILED: &str = "scan-failed";
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, std::io::Error),
+    Rerun(RecordingStreamError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(path, err) => write!(f, "Failed writing '{}': {err}", path.display()),
+            Error::Rerun(err) => write!(f, "Failed logging event: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Severity of an event, the levels of rerun text logs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Severity {
+    /// Level of the rerun text log
+    pub fn text_log_level(self) -> &'static str {
+        match self {
+            Severity::Debug => rerun::TextLogLevel::DEBUG,
+            Severity::Info => rerun::TextLogLevel::INFO,
+            Severity::Warn => rerun::TextLogLevel::WARN,
+            Severity::Error => rerun::TextLogLevel::ERROR,
+        }
+    }
+}
+
+/// Something that happened during a scan. Angles are in degrees and distances in centimeters.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Event {
+    /// A scan started, at a pose after the first when resumed from a checkpoint
+    ScanStarted {
+        mode: ScanMode,
+        first_pose: usize,
+    },
+    /// The pose of the given index, counted from 1, was measured
+    Pose {
+        index: usize,
+        yaw: f32,
+        pitch: f32,
+    },
+    /// The row of the given index, counted from 1, was measured
+    RowFinished {
+        row: usize,
+        points: usize,
+    },
+    /// A measurement has no valid distance
+    InvalidReading {
+        yaw: f32,
+        pitch: f32,
+        distance: u16,
+        signal_strength: u16,
+    },
+    /// Accessing the sensor failed and is attempted again
+    Retry {
+        attempt: u32,
+        error: String,
+    },
+    /// Accessing the sensor succeeded after failed attempts
+    Recovered {
+        attempts: u32,
+    },
+    TargetAcquired {
+        yaw: f32,
+        pitch: f32,
+        distance: f32,
+    },
+    TargetLost,
+    /// A search of the tracking mode found nothing in the distance window
+    NoTarget,
+    /// A time-lapse scan was compared to the reference scan, see `crate::timelapse`
+    ReferenceCompared {
+        reference: String,
+        compared_poses: usize,
+        changed_poses: usize,
+        regions: usize,
+    },
+    ScanCompleted {
+        points: usize,
+    },
+    /// The scan was stopped by a signal or a stop request
+    ScanAborted {
+        points: usize,
+    },
+    ScanFailed {
+        points: usize,
+        error: String,
+    },
+}
+
+impl Event {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::ScanStarted { .. } => SCAN_STARTED,
+            Event::Pose { .. } => POSE,
+            Event::RowFinished { .. } => ROW_FINISHED,
+            Event::InvalidReading { .. } => INVALID_READING,
+            Event::Retry { .. } => RETRY,
+            Event::Recovered { .. } => RECOVERED,
+            Event::TargetAcquired { .. } => TARGET_ACQUIRED,
+            Event::TargetLost => TARGET_LOST,
+            Event::NoTarget => NO_TARGET,
+            Event::ReferenceCompared { .. } => REFERENCE_COMPARED,
+            Event::ScanCompleted { .. } => SCAN_COMPLETED,
+            Event::ScanAborted { .. } => SCAN_ABORTED,
+            Event::ScanFailed { .. } => SCAN_FAILED,
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            Event::Pose { .. } => Severity::Debug,
+            Event::ScanStarted { .. }
+            | Event::RowFinished { .. }
+            | Event::Recovered { .. }
+            | Event::TargetAcquired { .. }
+            | Event::NoTarget
+            | Event::ScanCompleted { .. } => Severity::Info,
+            Event::ReferenceCompared { regions, .. } if *regions == 0 => Severity::Info,
+            Event::ReferenceCompared { .. } => Severity::Warn,
+            Event::InvalidReading { .. }
+            | Event::Retry { .. }
+            | Event::TargetLost
+            | Event::ScanAborted { .. } => Severity::Warn,
+            Event::ScanFailed { .. } => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::ScanStarted { mode, first_pose } => {
+                write!(f, "{mode:?} scan started at pose {first_pose}")
+            }
+            Event::Pose { index, yaw, pitch } => {
+                write!(f, "Pose {index} at yaw {yaw:.1}, pitch {pitch:.1}")
+            }
+            Event::RowFinished { row, points } => {
+                write!(f, "Row {row} finished with {points} points")
+            }
+            Event::InvalidReading {
+                yaw,
+                pitch,
+                distance,
+                signal_strength,
+            } => write!(
+                f,
+                "Invalid reading at yaw {yaw:.1}, pitch {pitch:.1}: {distance} cm with a signal strength of {signal_strength}"
+            ),
+            Event::Retry { attempt, error } => {
+                write!(f, "Sensor access failed ({error}), attempt {attempt}")
+            }
+            Event::Recovered { attempts } => {
+                write!(f, "Sensor access recovered after {attempts} attempts")
+            }
+            Event::TargetAcquired {
+                yaw,
+                pitch,
+                distance,
+            } => write!(
+                f,
+                "Target acquired at yaw {yaw:.1}, pitch {pitch:.1}, {distance:.0} cm"
+            ),
+            Event::TargetLost => write!(f, "Target lost"),
+            Event::NoTarget => write!(f, "No target in the distance window"),
+            Event::ReferenceCompared {
+                reference,
+                compared_poses,
+                changed_poses,
+                regions,
+            } => write!(
+                f,
+                "Compared {compared_poses} poses to the reference {reference}: {changed_poses} changed in {regions} regions"
+            ),
+            Event::ScanCompleted { points } => write!(f, "Scan completed after {points} points"),
+            Event::ScanAborted { points } => write!(f, "Scan stopped after {points} points"),
+            Event::ScanFailed { points, error } => {
+                write!(f, "Scan failed after {points} points: {error}")
+            }
+        }
+    }
+}
+
+/// Line of the local event log file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventRecord {
+    /// Seconds since the Unix epoch
+    pub time: f64,
+    pub recording_id: String,
+    /// Number of the event in its recording, counted from 1
+    pub sequence: u64,
+    pub severity: Severity,
+    pub message: String,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// Structured log of the events of a recording.
+///
+/// Each event is logged as a rerun `TextLog` with its severity under the text log entity,
+/// and its sequence number as a scalar under `<EVENTS_ENTITY_PATH>/<kind>`,
+/// so that every later row of a dataframe holds the number of the latest event of each kind
+/// and the analysis can segment recordings, e.g. by scan start.
+/// Events are also appended to a local file as JSON lines, see `EventRecord`.
+pub struct EventLog {
+    entity_path: String,
+    recording_id: String,
+    file: Option<(PathBuf, BufWriter<File>)>,
+    sequence: u64,
+}
+
+impl EventLog {
+    /// Appends to the file at `path`, if given, so that it collects the events of several recordings
+    pub fn new(
+        entity_path: &str,
+        recording_id: &str,
+        path: Option<&Path>,
+    ) -> Result<EventLog, Error> {
+        let file = match path {
+            Some(path) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|err| Error::Io(path.to_path_buf(), err))?;
+                Some((path.to_path_buf(), BufWriter::new(file)))
+            }
+            None => None,
+        };
+        Ok(EventLog {
+            entity_path: entity_path.to_string(),
+            recording_id: recording_id.to_string(),
+            file,
+            sequence: 0,
+        })
+    }
+
+    pub fn log(&mut self, rec: &RecordingStream, event: Event) -> Result<(), Error> {
+        self.sequence += 1;
+        let severity = event.severity();
+        let message = event.to_string();
+        rec.log(
+            self.entity_path.as_str(),
+            &rerun::TextLog::new(message.as_str()).with_level(severity.text_log_level()),
+        )
+        .map_err(Error::Rerun)?;
+        rec.log(
+            format!("{EVENTS_ENTITY_PATH}/{}", event.kind()),
+            &rerun::Scalars::single(self.sequence as f64),
+        )
+        .map_err(Error::Rerun)?;
+        if let Some((path, writer)) = &mut self.file {
+            let record = EventRecord {
+                time: SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|time| time.as_secs_f64())
+                    .unwrap_or(0.0),
+                recording_id: self.recording_id.clone(),
+                sequence: self.sequence,
+                severity,
+                message,
+                event,
+            };
+            // Flushed after every event, so that the file is complete when the scan crashes
+            serde_json::to_writer(&mut *writer, &record)
+                .map_err(std::io::Error::other)
+                .and_then(|_| writeln!(writer))
+                .and_then(|_| writer.flush())
+                .map_err(|err| Error::Io(path.clone(), err))?;
+        }
+        Ok(())
+    }
+}
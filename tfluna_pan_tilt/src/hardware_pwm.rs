@@ -0,0 +1,112 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use rppal::pwm::{Channel, Polarity, Pwm};
+
+use crate::backlash::{Backlash, BacklashConfig};
+use crate::servo::{Error, Servo, ServoCalibration, ServoSettings, pulse_duration};
+
+/// Converts a channel number between 0 and 3 to the corresponding hardware PWM channel.
+/// The Raspberry Pi 3 only has channels 0 and 1.
+pub fn channel_from_index(index: u8) -> Result<Channel, Error> {
+    match index {
+        0 => Ok(Channel::Pwm0),
+        1 => Ok(Channel::Pwm1),
+        2 => Ok(Channel::Pwm2),
+        3 => Ok(Channel::Pwm3),
+        _ => Err(Error::InvalidParameter(format!(
+            "Hardware PWM channel '{index}' is outside of valid range [0, 3]"
+        ))),
+    }
+}
+
+/// Opens a hardware PWM channel with the given frequency in Hz,
+/// its output is disabled until a pulse is set.
+///
+/// The channel is shared, so that it can be put in a safe state independently of the servo.
+/// rppal disables the output once the last handle is dropped.
+pub fn open_channel(index: u8, frequency: f32) -> Result<Arc<Pwm>, Error> {
+    let channel = channel_from_index(index)?;
+    if !frequency.is_finite() || frequency <= 0.0 {
+        return Err(Error::InvalidParameter(format!(
+            "PWM frequency '{frequency}' Hz must be positive"
+        )));
+    }
+    let period = Duration::from_secs_f32(1.0 / frequency);
+    Pwm::with_period(channel, period, Duration::ZERO, Polarity::Normal, false)
+        .map(Arc::new)
+        .map_err(|err| Error::Other(format!("Failed opening PWM channel {index}: {err}")))
+}
+
+/// Servo driven directly by a hardware PWM channel of the Raspberry Pi
+pub struct HardwarePwmServo {
+    pwm: Arc<Pwm>,
+    settings: ServoSettings,
+}
+
+impl HardwarePwmServo {
+    pub fn new(
+        pwm: Arc<Pwm>,
+        min_angle: f32,
+        max_angle: f32,
+        reversed: bool,
+        calibration: ServoCalibration,
+    ) -> Result<HardwarePwmServo, Error> {
+        let mut servo = HardwarePwmServo {
+            pwm,
+            settings: ServoSettings {
+                min_angle,
+                max_angle,
+                reversed,
+                calibration,
+                backlash: Backlash::default(),
+            },
+        };
+        servo.set_angle(0.0)?;
+        servo
+            .pwm
+            .enable()
+            .map_err(|err| Error::Other(format!("Failed enabling PWM output: {err}")))?;
+        Ok(servo)
+    }
+
+    /// Compensates the backlash of the servo from now on, see `Backlash`
+    pub fn with_backlash(mut self, config: BacklashConfig) -> Self {
+        self.settings.backlash = Backlash::new(config);
+        self
+    }
+
+    /// Pulse width of the given angle, if it is allowed, without backlash compensation
+    pub fn pulse_width(&self, angle: f32) -> Result<Duration, Error> {
+        Ok(pulse_duration(self.settings.pulse_width(angle)?))
+    }
+}
+
+impl Servo for HardwarePwmServo {
+    fn set_angle(&mut self, angle: f32) -> Result<(), Error> {
+        let pulse_widths = self.settings.pulse_widths(angle)?;
+        for (index, pulse_width) in pulse_widths.iter().enumerate() {
+            if index > 0 {
+                thread::sleep(self.settings.overshoot_delay());
+            }
+            let pulse_width = pulse_duration(*pulse_width);
+            self.pwm.set_pulse_width(pulse_width).map_err(|_x| {
+                Error::Other(format!("Failed setting pulse width: {pulse_width:?}"))
+            })?;
+        }
+        Ok(())
+    }
+
+    fn get_min_angle(&self) -> f32 {
+        self.settings.min_angle
+    }
+
+    fn get_max_angle(&self) -> f32 {
+        self.settings.max_angle
+    }
+
+    fn is_reversed(&self) -> bool {
+        self.settings.reversed
+    }
+}
@@ -0,0 +1,254 @@
+use nalgebra::{Isometry3, Point3, Translation3, UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+
+/// Number of values returned by `PanTiltKinematics::to_parameters`.
+pub const NUM_PARAMETERS: usize = 12;
+
+/// Geometry of the pan-tilt bracket.
+///
+/// The kinematic chain is base -> pan -> tilt -> sensor:
+/// - the pan frame rotates around the base z axis by the yaw angle,
+/// - the tilt frame is offset by `pan_to_tilt` in the pan frame and
+///   rotates around its x axis by the pitch angle,
+/// - the sensor frame is offset by `tilt_to_sensor` in the tilt frame,
+///   and the beam leaves the sensor aperture along its y axis.
+///
+/// Offsets are in centimeters, like the distances reported by the TF-Luna,
+/// and misalignment angles are in degrees.
+/// With every value set to zero the model reduces to the spherical formula
+/// `(d·cos(pitch)·sin(yaw), d·cos(pitch)·cos(yaw), d·sin(pitch))`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PanTiltKinematics {
+    /// Offset from the pan axis to the tilt axis, expressed in the pan frame.
+    pub pan_to_tilt: [f32; 3],
+    /// Offset from the tilt axis to the sensor aperture, expressed in the tilt frame.
+    pub tilt_to_sensor: [f32; 3],
+    /// Rotation of the pan axis around the base x and y axes.
+    pub pan_axis_misalignment: [f32; 2],
+    /// Rotation of the tilt axis around the pan y and z axes.
+    pub tilt_axis_misalignment: [f32; 2],
+    /// Elevation and azimuth of the beam relative to the sensor y axis.
+    pub beam_misalignment: [f32; 2],
+}
+
+/// Pose of the pan-tilt head for a single pair of servo angles.
+///
+/// Each isometry maps coordinates from a frame to its parent frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pose {
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Pan frame relative to the base frame.
+    pub pan: Isometry3<f32>,
+    /// Tilt frame relative to the pan frame.
+    pub tilt: Isometry3<f32>,
+    /// Sensor frame relative to the tilt frame.
+    pub sensor: Isometry3<f32>,
+}
+
+impl PanTiltKinematics {
+    /// Computes the pose of the head for the given yaw (bottom servo)
+    /// and pitch (top servo) angles in degrees.
+    pub fn pose(&self, yaw: f32, pitch: f32) -> Pose {
+        let [pan_x, pan_y] = self.pan_axis_misalignment.map(f32::to_radians);
+        let [tilt_y, tilt_z] = self.tilt_axis_misalignment.map(f32::to_radians);
+        let [beam_elevation, beam_azimuth] = self.beam_misalignment.map(f32::to_radians);
+
+        // Positive yaw turns the beam towards +x, i.e. clockwise seen from above
+        let pan = Isometry3::from_parts(
+            Translation3::identity(),
+            rotation_x(pan_x) * rotation_y(pan_y) * rotation_z(-yaw.to_radians()),
+        );
+        let tilt = Isometry3::from_parts(
+            Translation3::from(Vector3::from(self.pan_to_tilt)),
+            rotation_y(tilt_y) * rotation_z(tilt_z) * rotation_x(pitch.to_radians()),
+        );
+        let sensor = Isometry3::from_parts(
+            Translation3::from(Vector3::from(self.tilt_to_sensor)),
+            rotation_z(-beam_azimuth) * rotation_x(beam_elevation),
+        );
+        Pose {
+            yaw,
+            pitch,
+            pan,
+            tilt,
+            sensor,
+        }
+    }
+
+    /// Flattens the model into a list of values, e.g. to store it as a recording property.
+    pub fn to_parameters(&self) -> [f32; NUM_PARAMETERS] {
+        let mut parameters = [0.0; NUM_PARAMETERS];
+        parameters[0..3].copy_from_slice(&self.pan_to_tilt);
+        parameters[3..6].copy_from_slice(&self.tilt_to_sensor);
+        parameters[6..8].copy_from_slice(&self.pan_axis_misalignment);
+        parameters[8..10].copy_from_slice(&self.tilt_axis_misalignment);
+        parameters[10..12].copy_from_slice(&self.beam_misalignment);
+        parameters
+    }
+
+    /// Inverse of `to_parameters`. Returns `None` if the number of values is wrong.
+    pub fn from_parameters(parameters: &[f32]) -> Option<Self> {
+        if parameters.len() != NUM_PARAMETERS {
+            return None;
+        }
+        Some(PanTiltKinematics {
+            pan_to_tilt: [parameters[0], parameters[1], parameters[2]],
+            tilt_to_sensor: [parameters[3], parameters[4], parameters[5]],
+            pan_axis_misalignment: [parameters[6], parameters[7]],
+            tilt_axis_misalignment: [parameters[8], parameters[9]],
+            beam_misalignment: [parameters[10], parameters[11]],
+        })
+    }
+}
+
+impl Pose {
+    /// Sensor frame relative to the base frame.
+    pub fn sensor_to_base(&self) -> Isometry3<f32> {
+        self.pan * self.tilt * self.sensor
+    }
+
+    /// Position of the sensor aperture in the base frame.
+    pub fn origin(&self) -> [f32; 3] {
+        let origin = self.sensor_to_base().translation.vector;
+        [origin.x, origin.y, origin.z]
+    }
+
+    /// Unit vector of the beam direction in the base frame.
+    pub fn beam_direction(&self) -> [f32; 3] {
+        let direction = self.sensor_to_base().rotation * Vector3::y();
+        [direction.x, direction.y, direction.z]
+    }
+
+    /// Position in the base frame of a target measured at the given distance.
+    pub fn point_at(&self, distance: f32) -> [f32; 3] {
+        let point = self.sensor_to_base() * Point3::new(0.0, distance, 0.0);
+        [point.x, point.y, point.z]
+    }
+}
+
+fn rotation_x(angle: f32) -> UnitQuaternion<f32> {
+    UnitQuaternion::from_axis_angle(&Vector3::x_axis(), angle)
+}
+
+fn rotation_y(angle: f32) -> UnitQuaternion<f32> {
+    UnitQuaternion::from_axis_angle(&Vector3::y_axis(), angle)
+}
+
+fn rotation_z(angle: f32) -> UnitQuaternion<f32> {
+    UnitQuaternion::from_axis_angle(&Vector3::z_axis(), angle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        let error = (0..3)
+            .map(|i| (actual[i] - expected[i]).abs())
+            .fold(0.0, f32::max);
+        assert!(error < 1e-3, "{actual:?} differs from {expected:?}");
+    }
+
+    fn spherical(yaw: f32, pitch: f32, distance: f32) -> [f32; 3] {
+        let (yaw, pitch) = (yaw.to_radians(), pitch.to_radians());
+        [
+            distance * pitch.cos() * yaw.sin(),
+            distance * pitch.cos() * yaw.cos(),
+            distance * pitch.sin(),
+        ]
+    }
+
+    #[test]
+    fn zero_parameters_reduce_to_the_spherical_formula() {
+        let kinematics = PanTiltKinematics::default();
+        for yaw in [-90.0, -30.0, 0.0, 45.0, 90.0] {
+            for pitch in [-60.0, -15.0, 0.0, 20.0, 90.0] {
+                let pose = kinematics.pose(yaw, pitch);
+                assert_close(pose.point_at(150.0), spherical(yaw, pitch, 150.0));
+                assert_close(pose.beam_direction(), spherical(yaw, pitch, 1.0));
+                assert_close(pose.origin(), [0.0; 3]);
+            }
+        }
+    }
+
+    #[test]
+    fn offsets_turn_with_the_servos() {
+        let kinematics = PanTiltKinematics {
+            pan_to_tilt: [0.0, 2.0, 5.0],
+            ..Default::default()
+        };
+        assert_close(kinematics.pose(0.0, 0.0).origin(), [0.0, 2.0, 5.0]);
+        // The pan frame turns with the yaw, the tilt axis stays above the pan axis
+        assert_close(kinematics.pose(90.0, 30.0).origin(), [2.0, 0.0, 5.0]);
+        assert_close(
+            kinematics.pose(90.0, 0.0).point_at(100.0),
+            [102.0, 0.0, 5.0],
+        );
+
+        let kinematics = PanTiltKinematics {
+            tilt_to_sensor: [0.0, 1.0, 3.0],
+            ..Default::default()
+        };
+        assert_close(kinematics.pose(0.0, 0.0).origin(), [0.0, 1.0, 3.0]);
+        // Tilting up by 90° moves the sensor above the tilt axis to its back
+        assert_close(kinematics.pose(0.0, 90.0).origin(), [0.0, -3.0, 1.0]);
+        assert_close(kinematics.pose(0.0, 90.0).beam_direction(), [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn misalignments_rotate_the_beam() {
+        let direction = |kinematics: PanTiltKinematics| kinematics.pose(0.0, 0.0).beam_direction();
+        // A pan axis leaning around x tilts the beam up, like the pitch
+        assert_close(
+            direction(PanTiltKinematics {
+                pan_axis_misalignment: [5.0, 0.0],
+                ..Default::default()
+            }),
+            spherical(0.0, 5.0, 1.0),
+        );
+        // A tilt axis turned around z turns the beam like a negative yaw
+        assert_close(
+            direction(PanTiltKinematics {
+                tilt_axis_misalignment: [0.0, 5.0],
+                ..Default::default()
+            }),
+            spherical(-5.0, 0.0, 1.0),
+        );
+        // The beam elevation and azimuth add to the pitch and yaw
+        assert_close(
+            direction(PanTiltKinematics {
+                beam_misalignment: [3.0, 7.0],
+                ..Default::default()
+            }),
+            spherical(7.0, 3.0, 1.0),
+        );
+        // A pan axis leaning around y lowers the beam when it points towards +x
+        let kinematics = PanTiltKinematics {
+            pan_axis_misalignment: [0.0, 5.0],
+            ..Default::default()
+        };
+        assert_close(
+            kinematics.pose(90.0, 0.0).beam_direction(),
+            spherical(90.0, -5.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn parameters_round_trip() {
+        let kinematics = PanTiltKinematics {
+            pan_to_tilt: [1.0, 2.0, 3.0],
+            tilt_to_sensor: [4.0, 5.0, 6.0],
+            pan_axis_misalignment: [7.0, 8.0],
+            tilt_axis_misalignment: [9.0, 10.0],
+            beam_misalignment: [11.0, 12.0],
+        };
+        let parameters = kinematics.to_parameters();
+        assert_eq!(
+            PanTiltKinematics::from_parameters(&parameters),
+            Some(kinematics)
+        );
+        assert_eq!(PanTiltKinematics::from_parameters(&parameters[1..]), None);
+    }
+}
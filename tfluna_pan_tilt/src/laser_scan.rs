@@ -0,0 +1,173 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use rerun::{RecordingStream, RecordingStreamResult};
+use serde::{Deserialize, Serialize};
+
+use crate::point_cloud::PointRecord;
+use crate::range_image::is_valid_measurement;
+use crate::scan::grid_angle_count;
+
+/// Closest distance measured by the TF-Luna, in meters
+pub const RANGE_MIN: f32 = 0.2;
+/// Farthest distance measured by the TF-Luna, in meters
+pub const RANGE_MAX: f32 = 8.0;
+
+/// Settings of planar scans
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlanarSettings {
+    /// Angle of the top servo in degrees while the bottom servo steps over its range
+    pub pitch: f32,
+}
+
+impl Default for PlanarSettings {
+    fn default() -> Self {
+        PlanarSettings { pitch: 0.0 }
+    }
+}
+
+/// Planar scan laid out like the ROS `sensor_msgs/LaserScan` message,
+/// so that 2D mapping code can consume it as the output of a 2D LiDAR.
+///
+/// The frame has its x axis along the beam at a yaw of 0 and its y axis to the left,
+/// so angles are counterclockwise seen from above, the opposite of the yaw.
+/// Angles are in radians, ranges in meters and times in seconds.
+/// Rays without a valid measurement have a range of 0, below `range_min`,
+/// which consumers of laser scans discard.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LaserScan {
+    /// Capture time of the first measurement, in seconds since the Unix epoch
+    pub stamp: f64,
+    /// Angle of the first ray
+    pub angle_min: f32,
+    /// Angle of the last ray
+    pub angle_max: f32,
+    pub angle_increment: f32,
+    /// Time between the measurements of consecutive rays,
+    /// negative when the rays were measured from the last to the first
+    pub time_increment: f32,
+    /// Time between the first and the last measurement
+    pub scan_time: f32,
+    pub range_min: f32,
+    pub range_max: f32,
+    pub ranges: Vec<f32>,
+    /// Signal strengths reported by the TF-Luna
+    pub intensities: Vec<f32>,
+}
+
+impl LaserScan {
+    /// Arranges the measurements of a planar scan on the rays of a grid of `angle_step`
+    /// over the yaw range, given as (minimum, maximum) in degrees.
+    /// Each measurement goes to the nearest ray, and a later measurement replaces an earlier one.
+    pub fn from_records(records: &[PointRecord], yaw_range: (f32, f32), angle_step: f32) -> Self {
+        let count = grid_angle_count(yaw_range, angle_step);
+        // Rays go from the maximum to the minimum yaw, for their angles to increase
+        let last_yaw = yaw_range.0 + count.saturating_sub(1) as f32 * angle_step;
+        let mut ranges = vec![0.0; count];
+        let mut intensities = vec![0.0; count];
+        let mut times = vec![None; count];
+        for record in records {
+            let index = ((last_yaw - record.yaw) / angle_step).round();
+            if index < 0.0 || index as usize >= count {
+                continue;
+            }
+            let index = index as usize;
+            if is_valid_measurement(record.distance, record.signal_strength) {
+                ranges[index] = record.distance as f32 / 100.0;
+            }
+            intensities[index] = record.signal_strength as f32;
+            times[index] = Some(record.capture_time);
+        }
+        let measured: Vec<(usize, f64)> = times
+            .iter()
+            .enumerate()
+            .filter_map(|(index, time)| time.map(|time| (index, time)))
+            .collect();
+        let (stamp, scan_time, time_increment) = match (measured.first(), measured.last()) {
+            (Some((first_index, first_time)), Some((last_index, last_time)))
+                if last_index > first_index =>
+            {
+                let increment = (last_time - first_time) / (last_index - first_index) as f64;
+                (
+                    first_time.min(*last_time),
+                    (last_time - first_time).abs() as f32,
+                    increment as f32,
+                )
+            }
+            (Some((_, time)), _) => (*time, 0.0, 0.0),
+            _ => (0.0, 0.0, 0.0),
+        };
+        LaserScan {
+            stamp,
+            angle_min: -last_yaw.to_radians(),
+            angle_max: -yaw_range.0.to_radians(),
+            angle_increment: angle_step.to_radians(),
+            time_increment,
+            scan_time,
+            range_min: RANGE_MIN,
+            range_max: RANGE_MAX,
+            ranges,
+            intensities,
+        }
+    }
+
+    /// Angle of a ray in radians
+    pub fn angle(&self, index: usize) -> f32 {
+        self.angle_min + index as f32 * self.angle_increment
+    }
+
+    /// Whether the range of a ray is within the limits of the sensor
+    pub fn is_valid(&self, index: usize) -> bool {
+        (self.range_min..=self.range_max).contains(&self.ranges[index])
+    }
+
+    /// Positions (x, y) in meters of the valid rays, `None` for the others
+    pub fn points(&self) -> Vec<Option<[f32; 2]>> {
+        (0..self.ranges.len())
+            .map(|index| {
+                let (sin, cos) = self.angle(index).sin_cos();
+                let range = self.ranges[index];
+                self.is_valid(index).then_some([range * cos, range * sin])
+            })
+            .collect()
+    }
+
+    /// Writes the scan as a JSON document
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self).map_err(io::Error::other)
+    }
+
+    /// Logs the valid rays as `<entity_path>/points` and the outline of the scan,
+    /// broken at invalid rays, as `<entity_path>/outline`, in a 2D view looking down
+    /// with the beam at a yaw of 0 pointing up
+    pub fn log(
+        &self,
+        rec: &RecordingStream,
+        entity_path: &str,
+        radius: f32,
+    ) -> RecordingStreamResult<()> {
+        // 2D views have their y axis pointing down
+        let view = |[x, y]: [f32; 2]| [-y, -x];
+        let points = self.points();
+        let mut strips = vec![Vec::new()];
+        for point in &points {
+            match point {
+                Some(point) => strips.last_mut().unwrap().push(view(*point)),
+                None => strips.push(Vec::new()),
+            }
+        }
+        strips.retain(|strip| strip.len() > 1);
+        rec.log(
+            format!("{entity_path}/points"),
+            &rerun::Points2D::new(points.into_iter().flatten().map(view)).with_radii([radius]),
+        )?;
+        rec.log(
+            format!("{entity_path}/outline"),
+            &rerun::LineStrips2D::new(strips),
+        )?;
+        Ok(())
+    }
+}
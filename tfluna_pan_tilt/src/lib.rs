@@ -1 +1,27 @@
+pub mod adaptive;
+pub mod backlash;
+pub mod checkpoint;
+pub mod coloring;
+pub mod config;
+pub mod events;
+pub mod hardware_pwm;
+pub mod kinematics;
+pub mod laser_scan;
+pub mod metadata;
+pub mod mock_servo;
+pub mod point_cloud;
+pub mod point_cloud_logger;
+pub mod pose_logger;
+pub mod range_image;
+pub mod remote;
+pub mod safe_state;
+pub mod sampling;
+pub mod scan;
+pub mod scanner;
+pub mod selftest;
 pub mod servo;
+pub mod settle;
+pub mod simulation;
+pub mod sweep;
+pub mod timelapse;
+pub mod tracking;
@@ -0,0 +1,71 @@
+use rerun::{RecordingStream, RecordingStreamResult};
+pub use tfluna::metadata::{
+    COMMAND_LINE_PROPERTY, CRATE_VERSION_PROPERTY, FIRMWARE_VERSION_PROPERTY, GIT_COMMIT,
+    GIT_COMMIT_PROPERTY, HOSTNAME_PROPERTY, SERIAL_NUMBER_PROPERTY, TFLUNA_INFORMATION_PROPERTY,
+    TFLunaInformation, hostname,
+};
+use tfluna::metadata::{command_line, send_build_properties, send_tfluna_properties};
+
+use crate::config::ScanConfig;
+
+/// Version of the crate the binary was built from
+pub const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Scalars `[first_angle, first_pulse_width, second_angle, second_pulse_width]`
+pub const BOTTOM_SERVO_CALIBRATION_PROPERTY: &str = "bottom_servo_calibration";
+pub const TOP_SERVO_CALIBRATION_PROPERTY: &str = "top_servo_calibration";
+
+/// Where, from which sources and with which sensor a recording was made,
+/// so that results can be grouped by device or traced back to the code that produced them
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordingMetadata {
+    pub hostname: String,
+    pub crate_version: String,
+    pub git_commit: String,
+    pub command_line: Vec<String>,
+    /// `None` when the sensor could not be read
+    pub tfluna: Option<TFLunaInformation>,
+}
+
+impl RecordingMetadata {
+    /// Metadata of the running binary, with the information read from the sensor
+    pub fn collect(tfluna: Option<TFLunaInformation>) -> Self {
+        RecordingMetadata {
+            hostname: hostname(),
+            crate_version: CRATE_VERSION.to_string(),
+            git_commit: GIT_COMMIT.to_string(),
+            command_line: command_line(),
+            tfluna,
+        }
+    }
+
+    /// Sends the metadata and the servo calibrations of the configuration as recording properties
+    pub fn send(&self, rec: &RecordingStream, config: &ScanConfig) -> RecordingStreamResult<()> {
+        send_build_properties(
+            rec,
+            &self.hostname,
+            &self.crate_version,
+            &self.git_commit,
+            &self.command_line,
+        )?;
+        if let Some(tfluna) = &self.tfluna {
+            send_tfluna_properties(rec, tfluna)?;
+        }
+        for (name, servo) in [
+            (BOTTOM_SERVO_CALIBRATION_PROPERTY, &config.servos.bottom),
+            (TOP_SERVO_CALIBRATION_PROPERTY, &config.servos.top),
+        ] {
+            let calibration = servo.calibration;
+            rec.send_property(
+                name,
+                &rerun::Scalars::new([
+                    calibration.first_angle,
+                    calibration.first_pulse_width,
+                    calibration.second_angle,
+                    calibration.second_pulse_width,
+                ]),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+use crate::backlash::{Backlash, BacklashConfig};
+use crate::servo::{Error, Servo, ServoCalibration, ServoSettings};
+
+/// Servo that drives nothing and records the pulses it is commanded,
+/// to check the scanning logic without hardware.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockServo {
+    settings: ServoSettings,
+    /// Pulse widths in microseconds, in the order they were commanded
+    pulse_widths: Vec<f32>,
+}
+
+impl MockServo {
+    /// Like the other backends, the servo is sent to 0 degrees, which is the first recorded pulse width
+    pub fn new(
+        min_angle: f32,
+        max_angle: f32,
+        reversed: bool,
+        calibration: ServoCalibration,
+    ) -> Result<MockServo, Error> {
+        let mut servo = MockServo {
+            settings: ServoSettings {
+                min_angle,
+                max_angle,
+                reversed,
+                calibration,
+                backlash: Backlash::default(),
+            },
+            pulse_widths: Vec::new(),
+        };
+        servo.set_angle(0.0)?;
+        Ok(servo)
+    }
+
+    /// Compensates the backlash of the servo from now on, see `Backlash`.
+    /// Overshoots are recorded like any other pulse width, without waiting.
+    pub fn with_backlash(mut self, config: BacklashConfig) -> Self {
+        self.settings.backlash = Backlash::new(config);
+        self
+    }
+
+    /// Pulse widths commanded so far, oldest first
+    pub fn pulse_widths(&self) -> &[f32] {
+        &self.pulse_widths
+    }
+
+    /// Last commanded pulse width
+    pub fn pulse_width(&self) -> Option<f32> {
+        self.pulse_widths.last().copied()
+    }
+
+    pub fn clear(&mut self) {
+        self.pulse_widths.clear();
+    }
+}
+
+impl Servo for MockServo {
+    fn set_angle(&mut self, angle: f32) -> Result<(), Error> {
+        let pulse_widths = self.settings.pulse_widths(angle)?;
+        self.pulse_widths.extend(pulse_widths);
+        Ok(())
+    }
+
+    fn get_min_angle(&self) -> f32 {
+        self.settings.min_angle
+    }
+
+    fn get_max_angle(&self) -> f32 {
+        self.settings.max_angle
+    }
+
+    fn is_reversed(&self) -> bool {
+        self.settings.reversed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servo::ServoBank;
+
+    // 10 microseconds per degree, 1500 microseconds at 0 degrees
+    const CALIBRATION: ServoCalibration = ServoCalibration {
+        first_angle: -50.0,
+        first_pulse_width: 1000.0,
+        second_angle: 50.0,
+        second_pulse_width: 2000.0,
+    };
+
+    fn mock_servo(backlash: BacklashConfig) -> MockServo {
+        let mut servo = MockServo::new(-40.0, 40.0, false, CALIBRATION)
+            .unwrap()
+            .with_backlash(backlash);
+        servo.clear();
+        servo
+    }
+
+    #[test]
+    fn new_servo_goes_to_zero() {
+        let servo = MockServo::new(-40.0, 40.0, false, CALIBRATION).unwrap();
+        assert_eq!(servo.pulse_widths(), [1500.0]);
+        let servo = MockServo::new(-40.0, 40.0, true, CALIBRATION).unwrap();
+        assert_eq!(servo.pulse_width(), Some(1500.0));
+    }
+
+    #[test]
+    fn disallowed_angle_is_not_commanded() {
+        let mut servo = mock_servo(BacklashConfig::default());
+        assert!(servo.set_angle(40.5).is_err());
+        assert!(servo.set_angle(-41.0).is_err());
+        assert!(servo.pulse_widths().is_empty());
+    }
+
+    #[test]
+    fn uncompensated_targets_are_commanded_as_they_are() {
+        let mut servo = mock_servo(BacklashConfig::default());
+        for angle in [10.0, -5.0, -5.0, 40.0] {
+            servo.set_angle(angle).unwrap();
+        }
+        assert_eq!(servo.pulse_widths(), [1600.0, 1450.0, 1450.0, 1900.0]);
+    }
+
+    #[test]
+    fn bank_moves_no_servo_if_an_angle_is_not_allowed() {
+        let mut bank = ServoBank::new(vec![
+            mock_servo(BacklashConfig::default()),
+            mock_servo(BacklashConfig::default()),
+        ])
+        .unwrap();
+        assert!(bank.set_angles(&[10.0, 45.0]).is_err());
+        assert!(bank.set_angles(&[10.0]).is_err());
+        assert!(bank[0].pulse_widths().is_empty());
+        bank.set_angles(&[10.0, -10.0]).unwrap();
+        assert_eq!(bank[0].pulse_widths(), [1600.0]);
+        assert_eq!(bank[1].pulse_widths(), [1400.0]);
+    }
+}
@@ -0,0 +1,644 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+/// A single measurement of the scanner together with its 3D position
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PointRecord {
+    /// Position in centimeters in the base frame of the pan-tilt head
+    pub position: [f32; 3],
+    /// Distance in centimeters
+    pub distance: u16,
+    pub signal_strength: u16,
+    /// Temperature in degrees Celsius
+    pub temperature: f32,
+    /// Bottom servo angle in degrees
+    pub yaw: f32,
+    /// Top servo angle in degrees
+    pub pitch: f32,
+    /// Seconds since the Unix epoch
+    pub capture_time: f64,
+    /// Standard deviation in centimeters of the distances of the samples
+    /// aggregated into this point, 0 for a single sample
+    #[serde(default)]
+    pub distance_spread: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PointCloudFormat {
+    PlyAscii,
+    PlyBinary,
+    PcdAscii,
+    PcdBinary,
+    Xyz,
+    Las,
+}
+
+impl PointCloudFormat {
+    /// Guess the format from the file extension.
+    /// Binary variants are preferred when the extension is ambiguous.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ply" => Some(PointCloudFormat::PlyBinary),
+            "pcd" => Some(PointCloudFormat::PcdBinary),
+            "xyz" | "txt" => Some(PointCloudFormat::Xyz),
+            "las" => Some(PointCloudFormat::Las),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for PointCloudFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ply-ascii" => Ok(PointCloudFormat::PlyAscii),
+            "ply" | "ply-binary" => Ok(PointCloudFormat::PlyBinary),
+            "pcd-ascii" => Ok(PointCloudFormat::PcdAscii),
+            "pcd" | "pcd-binary" => Ok(PointCloudFormat::PcdBinary),
+            "xyz" => Ok(PointCloudFormat::Xyz),
+            "las" => Ok(PointCloudFormat::Las),
+            _ => Err(format!(
+                "Unknown point cloud format '{s}', expected one of: \
+                 ply, ply-ascii, pcd, pcd-ascii, xyz, las"
+            )),
+        }
+    }
+}
+
+/// Write the points to a file, with one RGBA color per point if colors are given.
+/// If no format is given, it is deduced from the file extension.
+pub fn save_point_cloud(
+    path: impl AsRef<Path>,
+    points: &[PointRecord],
+    colors: Option<&[[u8; 4]]>,
+    format: Option<PointCloudFormat>,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    let format = format
+        .or_else(|| PointCloudFormat::from_path(path))
+        .ok_or(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Cannot determine point cloud format of '{}'",
+                path.display()
+            ),
+        ))?;
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_point_cloud(&mut writer, points, colors, format)?;
+    writer.flush()
+}
+
+/// The alpha channel of the colors is not written, none of the formats has a standard field for it
+pub fn write_point_cloud<W: Write>(
+    writer: &mut W,
+    points: &[PointRecord],
+    colors: Option<&[[u8; 4]]>,
+    format: PointCloudFormat,
+) -> io::Result<()> {
+    if colors.is_some_and(|colors| colors.len() != points.len()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The number of colors differs from the number of points",
+        ));
+    }
+    match format {
+        PointCloudFormat::PlyAscii => write_ply(writer, points, colors, false),
+        PointCloudFormat::PlyBinary => write_ply(writer, points, colors, true),
+        PointCloudFormat::PcdAscii => write_pcd(writer, points, colors, false),
+        PointCloudFormat::PcdBinary => write_pcd(writer, points, colors, true),
+        PointCloudFormat::Xyz => write_xyz(writer, points, colors),
+        PointCloudFormat::Las => write_las(writer, points, colors),
+    }
+}
+
+// Color of the point at the given index, if there are colors
+fn point_color(colors: Option<&[[u8; 4]]>, index: usize) -> Option<[u8; 3]> {
+    colors.map(|colors| {
+        let [r, g, b, _] = colors[index];
+        [r, g, b]
+    })
+}
+
+fn write_ply<W: Write>(
+    writer: &mut W,
+    points: &[PointRecord],
+    colors: Option<&[[u8; 4]]>,
+    binary: bool,
+) -> io::Result<()> {
+    let format = if binary {
+        "binary_little_endian"
+    } else {
+        "ascii"
+    };
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format {format} 1.0")?;
+    writeln!(writer, "comment generated by tfluna_pan_tilt")?;
+    writeln!(writer, "element vertex {}", points.len())?;
+    for name in ["x", "y", "z"] {
+        writeln!(writer, "property float {name}")?;
+    }
+    writeln!(writer, "property ushort distance")?;
+    writeln!(writer, "property ushort signal_strength")?;
+    writeln!(writer, "property float temperature")?;
+    writeln!(writer, "property float yaw")?;
+    writeln!(writer, "property float pitch")?;
+    writeln!(writer, "property double capture_time")?;
+    if colors.is_some() {
+        for name in ["red", "green", "blue"] {
+            writeln!(writer, "property uchar {name}")?;
+        }
+    }
+    writeln!(writer, "end_header")?;
+    for (i, point) in points.iter().enumerate() {
+        let color = point_color(colors, i);
+        if binary {
+            write_binary_fields(writer, point)?;
+            if let Some(color) = color {
+                writer.write_all(&color)?;
+            }
+        } else {
+            write_ascii_fields(writer, point, color.map(|[r, g, b]| format!("{r} {g} {b}")))?;
+        }
+    }
+    Ok(())
+}
+
+fn write_pcd<W: Write>(
+    writer: &mut W,
+    points: &[PointRecord],
+    colors: Option<&[[u8; 4]]>,
+    binary: bool,
+) -> io::Result<()> {
+    // Colors are packed as 0x00RRGGBB in an rgb field, as done by PCL
+    let (rgb_field, rgb_size, rgb_type, rgb_count) = match colors {
+        Some(_) => (" rgb", " 4", " U", " 1"),
+        None => ("", "", "", ""),
+    };
+    writeln!(writer, "# .PCD v0.7 - Point Cloud Data file format")?;
+    writeln!(writer, "VERSION 0.7")?;
+    writeln!(
+        writer,
+        "FIELDS x y z distance signal_strength temperature yaw pitch capture_time{rgb_field}"
+    )?;
+    writeln!(writer, "SIZE 4 4 4 2 2 4 4 4 8{rgb_size}")?;
+    writeln!(writer, "TYPE F F F U U F F F F{rgb_type}")?;
+    writeln!(writer, "COUNT 1 1 1 1 1 1 1 1 1{rgb_count}")?;
+    writeln!(writer, "WIDTH {}", points.len())?;
+    writeln!(writer, "HEIGHT 1")?;
+    writeln!(writer, "VIEWPOINT 0 0 0 1 0 0 0")?;
+    writeln!(writer, "POINTS {}", points.len())?;
+    writeln!(writer, "DATA {}", if binary { "binary" } else { "ascii" })?;
+    for (i, point) in points.iter().enumerate() {
+        let rgb =
+            point_color(colors, i).map(|[r, g, b]| (r as u32) << 16 | (g as u32) << 8 | b as u32);
+        if binary {
+            write_binary_fields(writer, point)?;
+            if let Some(rgb) = rgb {
+                writer.write_all(&rgb.to_le_bytes())?;
+            }
+        } else {
+            write_ascii_fields(writer, point, rgb.map(|rgb| rgb.to_string()))?;
+        }
+    }
+    Ok(())
+}
+
+fn write_xyz<W: Write>(
+    writer: &mut W,
+    points: &[PointRecord],
+    colors: Option<&[[u8; 4]]>,
+) -> io::Result<()> {
+    // CloudCompare treats lines starting with '//' as a header
+    writeln!(
+        writer,
+        "//X Y Z distance signal_strength temperature yaw pitch capture_time{}",
+        if colors.is_some() { " R G B" } else { "" }
+    )?;
+    for (i, point) in points.iter().enumerate() {
+        let color = point_color(colors, i).map(|[r, g, b]| format!("{r} {g} {b}"));
+        write_ascii_fields(writer, point, color)?;
+    }
+    Ok(())
+}
+
+// Field order shared by the PLY, PCD and XYZ writers, followed by the color fields if any
+fn write_ascii_fields<W: Write>(
+    writer: &mut W,
+    point: &PointRecord,
+    color: Option<String>,
+) -> io::Result<()> {
+    let [x, y, z] = point.position;
+    write!(
+        writer,
+        "{x} {y} {z} {} {} {} {} {} {:.6}",
+        point.distance,
+        point.signal_strength,
+        point.temperature,
+        point.yaw,
+        point.pitch,
+        point.capture_time,
+    )?;
+    match color {
+        Some(color) => writeln!(writer, " {color}"),
+        None => writeln!(writer),
+    }
+}
+
+fn write_binary_fields<W: Write>(writer: &mut W, point: &PointRecord) -> io::Result<()> {
+    for value in point.position {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.write_all(&point.distance.to_le_bytes())?;
+    writer.write_all(&point.signal_strength.to_le_bytes())?;
+    writer.write_all(&point.temperature.to_le_bytes())?;
+    writer.write_all(&point.yaw.to_le_bytes())?;
+    writer.write_all(&point.pitch.to_le_bytes())?;
+    writer.write_all(&point.capture_time.to_le_bytes())?;
+    Ok(())
+}
+
+// LAS 1.4 constants
+const LAS_HEADER_SIZE: u16 = 375;
+const LAS_VLR_HEADER_SIZE: u32 = 54;
+const LAS_EXTRA_BYTES_DESCRIPTOR_SIZE: usize = 192;
+// Point data record format 6 is the smallest format that only exists in LAS 1.4,
+// format 7 adds 16-bit RGB colors
+const LAS_POINT_FORMAT: u8 = 6;
+const LAS_POINT_FORMAT_SIZE: u16 = 30;
+const LAS_COLOR_POINT_FORMAT: u8 = 7;
+const LAS_COLOR_POINT_FORMAT_SIZE: u16 = 36;
+// Positions are stored as integers with a resolution of 0.1 mm
+const LAS_SCALE: f64 = 0.01;
+
+// Extra bytes data types
+const LAS_UNSIGNED_SHORT: u8 = 3;
+const LAS_FLOAT: u8 = 9;
+
+// Attributes that have no dedicated field in point format 6.
+// Signal strength is stored as intensity and capture time as adjusted standard GPS time.
+const LAS_EXTRA_ATTRIBUTES: [(&str, u8, &str); 4] = [
+    ("distance", LAS_UNSIGNED_SHORT, "distance in centimeters"),
+    ("temperature", LAS_FLOAT, "temperature in degrees Celsius"),
+    ("yaw", LAS_FLOAT, "bottom servo angle in degrees"),
+    ("pitch", LAS_FLOAT, "top servo angle in degrees"),
+];
+const LAS_EXTRA_BYTES_SIZE: u16 = 14;
+
+// Unix time of the GPS epoch, 1980-01-06T00:00:00Z
+const GPS_EPOCH_UNIX_TIME: f64 = 315_964_800.0;
+// Leap seconds between GPS time and UTC, unchanged since 2017
+const GPS_LEAP_SECONDS: f64 = 18.0;
+// Offset of adjusted standard GPS time from GPS time
+const LAS_ADJUSTED_GPS_TIME_OFFSET: f64 = 1e9;
+
+fn write_las<W: Write>(
+    writer: &mut W,
+    points: &[PointRecord],
+    colors: Option<&[[u8; 4]]>,
+) -> io::Result<()> {
+    let (point_format, point_format_size) = match colors {
+        Some(_) => (LAS_COLOR_POINT_FORMAT, LAS_COLOR_POINT_FORMAT_SIZE),
+        None => (LAS_POINT_FORMAT, LAS_POINT_FORMAT_SIZE),
+    };
+    let extra_bytes_vlr_size = LAS_EXTRA_ATTRIBUTES.len() * LAS_EXTRA_BYTES_DESCRIPTOR_SIZE;
+    let offset_to_point_data =
+        LAS_HEADER_SIZE as u32 + LAS_VLR_HEADER_SIZE + extra_bytes_vlr_size as u32;
+
+    let mut min = [f64::MAX; 3];
+    let mut max = [f64::MIN; 3];
+    for point in points {
+        for i in 0..3 {
+            min[i] = min[i].min(point.position[i] as f64);
+            max[i] = max[i].max(point.position[i] as f64);
+        }
+    }
+    if points.is_empty() {
+        min = [0.0; 3];
+        max = [0.0; 3];
+    }
+    let (year, day_of_year) = las_creation_date(SystemTime::now());
+
+    // Public header block
+    writer.write_all(b"LASF")?;
+    // File source ID
+    writer.write_all(&0u16.to_le_bytes())?;
+    // Global encoding: times are adjusted standard GPS time (bit 0),
+    // coordinate reference system is WKT, required for point formats 6 and 7 (bit 4)
+    writer.write_all(&(1u16 | 1u16 << 4).to_le_bytes())?;
+    // Project ID (GUID)
+    writer.write_all(&[0; 16])?;
+    // Version 1.4
+    writer.write_all(&[1, 4])?;
+    writer.write_all(&fixed_size_string::<32>("tfluna_pan_tilt"))?;
+    writer.write_all(&fixed_size_string::<32>(concat!(
+        "tfluna_pan_tilt ",
+        env!("CARGO_PKG_VERSION")
+    )))?;
+    writer.write_all(&day_of_year.to_le_bytes())?;
+    writer.write_all(&year.to_le_bytes())?;
+    writer.write_all(&LAS_HEADER_SIZE.to_le_bytes())?;
+    writer.write_all(&offset_to_point_data.to_le_bytes())?;
+    // Number of variable length records
+    writer.write_all(&1u32.to_le_bytes())?;
+    writer.write_all(&[point_format])?;
+    writer.write_all(&(point_format_size + LAS_EXTRA_BYTES_SIZE).to_le_bytes())?;
+    // Legacy point counts must be zero for point formats 6 and 7
+    writer.write_all(&0u32.to_le_bytes())?;
+    writer.write_all(&[0; 20])?;
+    for _ in 0..3 {
+        writer.write_all(&LAS_SCALE.to_le_bytes())?;
+    }
+    for _ in 0..3 {
+        writer.write_all(&0f64.to_le_bytes())?;
+    }
+    for i in 0..3 {
+        writer.write_all(&max[i].to_le_bytes())?;
+        writer.write_all(&min[i].to_le_bytes())?;
+    }
+    // Start of waveform data record and of the first extended variable length record
+    writer.write_all(&0u64.to_le_bytes())?;
+    writer.write_all(&0u64.to_le_bytes())?;
+    // Number of extended variable length records
+    writer.write_all(&0u32.to_le_bytes())?;
+    writer.write_all(&(points.len() as u64).to_le_bytes())?;
+    // Number of points by return, all points are first returns
+    writer.write_all(&(points.len() as u64).to_le_bytes())?;
+    writer.write_all(&[0; 14 * 8])?;
+
+    // Extra bytes variable length record
+    writer.write_all(&0u16.to_le_bytes())?;
+    writer.write_all(&fixed_size_string::<16>("LASF_Spec"))?;
+    writer.write_all(&4u16.to_le_bytes())?;
+    writer.write_all(&(extra_bytes_vlr_size as u16).to_le_bytes())?;
+    writer.write_all(&fixed_size_string::<32>("Extra Bytes"))?;
+    for (name, data_type, description) in LAS_EXTRA_ATTRIBUTES {
+        let mut descriptor = [0; LAS_EXTRA_BYTES_DESCRIPTOR_SIZE];
+        descriptor[2] = data_type;
+        descriptor[4..36].copy_from_slice(&fixed_size_string::<32>(name));
+        descriptor[160..192].copy_from_slice(&fixed_size_string::<32>(description));
+        writer.write_all(&descriptor)?;
+    }
+
+    // Point data records
+    for (i, point) in points.iter().enumerate() {
+        for value in point.position {
+            let value = (value as f64 / LAS_SCALE).round() as i32;
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&point.signal_strength.to_le_bytes())?;
+        // Return number 1 of 1
+        writer.write_all(&[0x11])?;
+        // Classification flags, scanner channel, scan direction and edge of flight line
+        writer.write_all(&[0])?;
+        // Classification: created, never classified
+        writer.write_all(&[0])?;
+        // User data
+        writer.write_all(&[0])?;
+        // Scan angle
+        writer.write_all(&0i16.to_le_bytes())?;
+        // Point source ID
+        writer.write_all(&0u16.to_le_bytes())?;
+        writer.write_all(&las_gps_time(point.capture_time).to_le_bytes())?;
+        if let Some(color) = point_color(colors, i) {
+            // Colors are scaled to 16 bits
+            for value in color {
+                writer.write_all(&(value as u16 * 257).to_le_bytes())?;
+            }
+        }
+        // Extra bytes, in the order of LAS_EXTRA_ATTRIBUTES
+        writer.write_all(&point.distance.to_le_bytes())?;
+        writer.write_all(&point.temperature.to_le_bytes())?;
+        writer.write_all(&point.yaw.to_le_bytes())?;
+        writer.write_all(&point.pitch.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Adjusted standard GPS time of LAS files, GPS seconds minus 10⁹,
+/// of a time in seconds since the Unix epoch
+pub fn las_gps_time(unix_time: f64) -> f64 {
+    unix_time - GPS_EPOCH_UNIX_TIME + GPS_LEAP_SECONDS - LAS_ADJUSTED_GPS_TIME_OFFSET
+}
+
+// Null-padded string as used in LAS headers, truncated if too long
+fn fixed_size_string<const N: usize>(value: &str) -> [u8; N] {
+    let mut buffer = [0; N];
+    let length = value.len().min(N);
+    buffer[..length].copy_from_slice(&value.as_bytes()[..length]);
+    buffer
+}
+
+// Year and day of year (starting at 1) in UTC
+fn las_creation_date(time: SystemTime) -> (u16, u16) {
+    let seconds = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut days = seconds / 86_400;
+    let mut year = 1970;
+    loop {
+        let is_leap_year = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+        let days_in_year = if is_leap_year { 366 } else { 365 };
+        if days < days_in_year {
+            break;
+        }
+        days -= days_in_year;
+        year += 1;
+    }
+    (year as u16, days as u16 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(count: usize) -> Vec<PointRecord> {
+        (0..count)
+            .map(|i| PointRecord {
+                position: [i as f32, 100.0, -2.5],
+                distance: 100,
+                signal_strength: 1000,
+                temperature: 25.0,
+                yaw: 10.0,
+                pitch: -5.0,
+                capture_time: 1_700_000_000.0 + i as f64,
+                distance_spread: 0.0,
+            })
+            .collect()
+    }
+
+    fn write(
+        points: &[PointRecord],
+        colors: Option<&[[u8; 4]]>,
+        format: PointCloudFormat,
+    ) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        write_point_cloud(&mut buffer, points, colors, format).unwrap();
+        buffer
+    }
+
+    // Header lines and the bytes that follow them
+    fn split_header<'a>(buffer: &'a [u8], last_line: &str) -> (Vec<&'a str>, &'a [u8]) {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        while let Some(length) = buffer[start..].iter().position(|&b| b == b'\n') {
+            let line = std::str::from_utf8(&buffer[start..start + length]).unwrap();
+            lines.push(line);
+            start += length + 1;
+            if line.starts_with(last_line) {
+                break;
+            }
+        }
+        (lines, &buffer[start..])
+    }
+
+    fn u16_at(buffer: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(buffer[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(buffer: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(buffer[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(buffer: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(buffer[offset..offset + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn las_header_describes_the_point_records() {
+        let points = points(5);
+        let colors = [[255, 128, 0, 255]; 5];
+        for (colors, point_format, record_length) in
+            [(None, 6, 30 + 14), (Some(&colors[..]), 7, 36 + 14)]
+        {
+            let buffer = write(&points, colors, PointCloudFormat::Las);
+            let offset_to_point_data = 375 + 54 + 4 * 192;
+            assert_eq!(&buffer[..4], b"LASF");
+            assert_eq!(u16_at(&buffer, 94), 375);
+            assert_eq!(u32_at(&buffer, 96), offset_to_point_data);
+            assert_eq!(buffer[104], point_format);
+            assert_eq!(u16_at(&buffer, 105), record_length);
+            assert_eq!(u64_at(&buffer, 247), 5);
+            assert_eq!(
+                buffer.len(),
+                offset_to_point_data as usize + 5 * record_length as usize
+            );
+        }
+    }
+
+    #[test]
+    fn las_positions_are_scaled() {
+        let buffer = write(&points(2), None, PointCloudFormat::Las);
+        let second_point = 375 + 54 + 4 * 192 + 44;
+        assert_eq!(u32_at(&buffer, second_point) as i32, 100);
+        assert_eq!(u32_at(&buffer, second_point + 4) as i32, 10_000);
+        assert_eq!(u32_at(&buffer, second_point + 8) as i32, -250);
+    }
+
+    #[test]
+    fn ply_header_matches_the_point_records() {
+        let points = points(3);
+        let colors = [[1, 2, 3, 4]; 3];
+        for (colors, point_size) in [(None, 36), (Some(&colors[..]), 39)] {
+            let buffer = write(&points, colors, PointCloudFormat::PlyBinary);
+            let (header, data) = split_header(&buffer, "end_header");
+            assert!(header.contains(&"element vertex 3"));
+            let size: usize = header
+                .iter()
+                .filter_map(|line| line.strip_prefix("property "))
+                .map(|property| match property.split(' ').next().unwrap() {
+                    "uchar" => 1,
+                    "ushort" => 2,
+                    "float" => 4,
+                    "double" => 8,
+                    other => panic!("Unexpected property type {other}"),
+                })
+                .sum();
+            assert_eq!(size, point_size);
+            assert_eq!(data.len(), 3 * point_size);
+
+            let buffer = write(&points, colors, PointCloudFormat::PlyAscii);
+            let (header, data) = split_header(&buffer, "end_header");
+            let properties = header
+                .iter()
+                .filter(|line| line.starts_with("property "))
+                .count();
+            let data = std::str::from_utf8(data).unwrap();
+            assert_eq!(data.lines().count(), 3);
+            assert!(
+                data.lines()
+                    .all(|line| line.split(' ').count() == properties)
+            );
+        }
+    }
+
+    #[test]
+    fn pcd_header_matches_the_point_records() {
+        let points = points(3);
+        let colors = [[1, 2, 3, 4]; 3];
+        for (colors, point_size) in [(None, 36), (Some(&colors[..]), 40)] {
+            let buffer = write(&points, colors, PointCloudFormat::PcdBinary);
+            let (header, data) = split_header(&buffer, "DATA");
+            let values = |key: &str| -> Vec<&str> {
+                let line = header.iter().find(|line| line.starts_with(key)).unwrap();
+                line.split(' ').skip(1).collect()
+            };
+            let fields = values("FIELDS").len();
+            assert_eq!(values("SIZE").len(), fields);
+            assert_eq!(values("TYPE").len(), fields);
+            assert_eq!(values("COUNT").len(), fields);
+            let size: usize = values("SIZE")
+                .iter()
+                .map(|size| size.parse::<usize>().unwrap())
+                .sum();
+            assert_eq!(size, point_size);
+            assert_eq!(values("POINTS"), ["3"]);
+            assert_eq!(data.len(), 3 * point_size);
+
+            let buffer = write(&points, colors, PointCloudFormat::PcdAscii);
+            let (_, data) = split_header(&buffer, "DATA");
+            let data = std::str::from_utf8(data).unwrap();
+            assert_eq!(data.lines().count(), 3);
+            assert!(data.lines().all(|line| line.split(' ').count() == fields));
+        }
+    }
+
+    #[test]
+    fn colors_must_match_the_points() {
+        let mut buffer = Vec::new();
+        let colors = [[0; 4]; 2];
+        let result = write_point_cloud(
+            &mut buffer,
+            &points(3),
+            Some(&colors),
+            PointCloudFormat::Xyz,
+        );
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn las_gps_time_is_adjusted_standard_gps_time() {
+        // 2017-01-01T00:00:00Z, after the last leap second, is GPS time 1167264018
+        assert_eq!(las_gps_time(1_483_228_800.0), 1_167_264_018.0 - 1e9);
+    }
+
+    #[test]
+    fn las_creation_date_is_day_of_year() {
+        let date = |unix_time| {
+            las_creation_date(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(unix_time))
+        };
+        assert_eq!(date(0), (1970, 1));
+        // 2024-12-31T12:00:00Z, in a leap year
+        assert_eq!(date(1_735_646_400), (2024, 366));
+        // 2025-03-01T00:00:00Z
+        assert_eq!(date(1_740_787_200), (2025, 60));
+    }
+}
@@ -0,0 +1,119 @@
+use std::str::FromStr;
+
+use rerun::{RecordingStream, RecordingStreamResult};
+use serde::{Deserialize, Serialize};
+
+/// When the points of a row are sent to rerun
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LoggingStrategy {
+    /// Every point is sent as soon as it is measured, at its own step of the sample timeline
+    Sample,
+    /// The points of a row are sent once, when the row is finished
+    Row,
+}
+
+impl FromStr for LoggingStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "sample" => Ok(LoggingStrategy::Sample),
+            "row" => Ok(LoggingStrategy::Row),
+            _ => Err(format!(
+                "Unknown logging strategy '{s}', expected one of: sample, row"
+            )),
+        }
+    }
+}
+
+/// Timeline counting the points sent with the `Sample` strategy
+pub const SAMPLE_TIMELINE: &str = "sample";
+
+/// Logs a point cloud incrementally with one entity per row of the scan,
+/// i.e. `<entity_path>/row_0000`, `<entity_path>/row_0001`, ...,
+/// or with the `Sample` strategy every point to `<entity_path>/samples`,
+/// at its own step of the `SAMPLE_TIMELINE`.
+///
+/// Every point is sent once, so the amount of data sent per log call is bounded
+/// by the row length instead of growing with the size of the scan.
+/// The full cloud is the union of the latest value of every row entity,
+/// and of all the values of the samples entity.
+#[derive(Debug, Clone)]
+pub struct PointCloudLogger {
+    entity_path: String,
+    strategy: LoggingStrategy,
+    radius: f32,
+    row: usize,
+    sample: i64,
+    positions: Vec<[f32; 3]>,
+    colors: Vec<[u8; 4]>,
+}
+
+impl PointCloudLogger {
+    pub fn new(entity_path: impl Into<String>, strategy: LoggingStrategy, radius: f32) -> Self {
+        PointCloudLogger {
+            entity_path: entity_path.into(),
+            strategy,
+            radius,
+            row: 0,
+            sample: 0,
+            positions: Vec::new(),
+            colors: Vec::new(),
+        }
+    }
+
+    /// Adds a point to the current row
+    pub fn push(
+        &mut self,
+        rec: &RecordingStream,
+        position: [f32; 3],
+        color: [u8; 4],
+    ) -> RecordingStreamResult<()> {
+        if self.strategy == LoggingStrategy::Sample {
+            // Also applies to what is logged afterwards, until the next point
+            rec.set_time_sequence(SAMPLE_TIMELINE, self.sample);
+            rec.log(
+                self.samples_entity_path(),
+                &rerun::Points3D::new([position])
+                    .with_colors([color])
+                    .with_radii([self.radius]),
+            )?;
+            self.sample += 1;
+        }
+        self.positions.push(position);
+        self.colors.push(color);
+        Ok(())
+    }
+
+    /// Sends the pending points, if any, and starts a new row
+    pub fn finish_row(&mut self, rec: &RecordingStream) -> RecordingStreamResult<()> {
+        if self.positions.is_empty() {
+            return Ok(());
+        }
+        if self.strategy == LoggingStrategy::Row {
+            self.log_row(rec)?;
+        }
+        self.row += 1;
+        self.positions.clear();
+        self.colors.clear();
+        Ok(())
+    }
+
+    pub fn row_entity_path(&self) -> String {
+        format!("{}/row_{:04}", self.entity_path, self.row)
+    }
+
+    fn samples_entity_path(&self) -> String {
+        format!("{}/samples", self.entity_path)
+    }
+
+    fn log_row(&self, rec: &RecordingStream) -> RecordingStreamResult<()> {
+        rec.log(
+            self.row_entity_path(),
+            &rerun::Points3D::new(self.positions.iter().copied())
+                .with_colors(self.colors.iter().copied())
+                .with_radii([self.radius]),
+        )
+    }
+}
@@ -0,0 +1,83 @@
+use nalgebra::Isometry3;
+use rerun::{RecordingStream, RecordingStreamResult};
+
+use crate::kinematics::Pose;
+
+/// Full angle of the TF-Luna field of view in degrees
+pub const BEAM_FIELD_OF_VIEW: f32 = 2.0;
+
+// Length in centimeters of the axes drawn for each frame
+const AXIS_LENGTH: f32 = 5.0;
+// Number of segments of the circle at the end of the beam cone
+const CONE_SEGMENTS: usize = 16;
+
+/// Logs the pose of the pan-tilt head as a hierarchy of transforms, following the
+/// kinematic chain of `PanTiltKinematics`:
+/// `<entity_path>/pan`, `<entity_path>/pan/tilt` and `<entity_path>/pan/tilt/sensor`.
+///
+/// The beam is logged in the sensor frame under `<entity_path>/pan/tilt/sensor/beam`,
+/// as an arrow with the length of the measured distance inside the cone of the field of view,
+/// so that replaying the recording shows where the head was pointing at each moment.
+#[derive(Debug, Clone)]
+pub struct PoseLogger {
+    entity_path: String,
+}
+
+impl PoseLogger {
+    pub fn new(entity_path: impl Into<String>) -> Self {
+        PoseLogger {
+            entity_path: entity_path.into(),
+        }
+    }
+
+    /// Logs the pose of the head and a beam of the given length, in centimeters
+    pub fn log(
+        &self,
+        rec: &RecordingStream,
+        pose: &Pose,
+        distance: f32,
+    ) -> RecordingStreamResult<()> {
+        let pan_path = format!("{}/pan", self.entity_path);
+        let tilt_path = format!("{pan_path}/tilt");
+        let sensor_path = format!("{tilt_path}/sensor");
+        rec.log(pan_path, &transform(&pose.pan))?;
+        rec.log(tilt_path, &transform(&pose.tilt))?;
+        rec.log(sensor_path.as_str(), &transform(&pose.sensor))?;
+
+        // The beam leaves the sensor aperture along the y axis of the sensor frame
+        rec.log(
+            format!("{sensor_path}/beam"),
+            &rerun::Arrows3D::from_vectors([[0.0, distance, 0.0]]),
+        )?;
+        let radius = distance * (BEAM_FIELD_OF_VIEW / 2.0).to_radians().tan();
+        let circle: Vec<[f32; 3]> = (0..=CONE_SEGMENTS)
+            .map(|i| {
+                let angle = i as f32 / CONE_SEGMENTS as f32 * std::f32::consts::TAU;
+                [radius * angle.cos(), distance, radius * angle.sin()]
+            })
+            .collect();
+        let mut strips = vec![circle];
+        for angle in [0.0, 90.0, 180.0, 270.0f32] {
+            let angle = angle.to_radians();
+            strips.push(vec![
+                [0.0; 3],
+                [radius * angle.cos(), distance, radius * angle.sin()],
+            ]);
+        }
+        rec.log(
+            format!("{sensor_path}/cone"),
+            &rerun::LineStrips3D::new(strips),
+        )
+    }
+}
+
+// Transform from a frame to its parent frame
+fn transform(isometry: &Isometry3<f32>) -> rerun::Transform3D {
+    let translation = isometry.translation.vector;
+    let rotation = isometry.rotation.coords;
+    rerun::Transform3D::from_translation_rotation(
+        [translation.x, translation.y, translation.z],
+        rerun::Quaternion::from_xyzw([rotation.x, rotation.y, rotation.z, rotation.w]),
+    )
+    .with_axis_length(AXIS_LENGTH)
+}
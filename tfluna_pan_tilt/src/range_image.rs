@@ -0,0 +1,299 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use rerun::{RecordingStream, RecordingStreamResult};
+use serde::{Deserialize, Serialize};
+
+use crate::point_cloud::PointRecord;
+use crate::scan::grid_angle_count;
+
+/// Smallest signal strength for which the TF-Luna reports a reliable distance
+pub const MIN_SIGNAL_STRENGTH: u16 = 100;
+
+/// Signal strength reported by the TF-Luna when its receiver is saturated
+pub const SATURATED_SIGNAL_STRENGTH: u16 = u16::MAX;
+
+/// File format of exported range images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RangeImageFormat {
+    /// 16-bit grayscale PNG files, the temperature is stored in hundredths of degrees
+    Png,
+    /// NumPy arrays
+    Npy,
+}
+
+impl FromStr for RangeImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Ok(RangeImageFormat::Png),
+            "npy" => Ok(RangeImageFormat::Npy),
+            _ => Err(format!(
+                "Unknown range image format '{s}', expected one of: png, npy"
+            )),
+        }
+    }
+}
+
+/// Whether the TF-Luna distance of a measurement can be trusted
+pub fn is_valid_measurement(distance: u16, signal_strength: u16) -> bool {
+    distance > 0
+        && signal_strength >= MIN_SIGNAL_STRENGTH
+        && signal_strength != SATURATED_SIGNAL_STRENGTH
+}
+
+/// Measurements of a scan arranged on a yaw × pitch grid.
+///
+/// Columns go from the minimum to the maximum yaw and rows from the maximum
+/// to the minimum pitch, so that the image shows the scene as seen from the scanner.
+/// Pixels are stored row by row. Each measurement goes to the nearest pixel,
+/// and a later measurement replaces an earlier one.
+/// Pixels that were not measured have a distance and signal strength of 0,
+/// a NaN temperature, and are invalid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeImage {
+    pub width: usize,
+    pub height: usize,
+    yaw_min: f32,
+    pitch_max: f32,
+    yaw_step: f32,
+    pitch_step: f32,
+    /// Distance in centimeters
+    pub distance: Vec<u16>,
+    pub signal_strength: Vec<u16>,
+    /// Temperature in degrees Celsius
+    pub temperature: Vec<f32>,
+    /// Whether the pixel was measured with a reliable distance, see `is_valid_measurement`
+    pub valid: Vec<bool>,
+}
+
+impl RangeImage {
+    /// Empty image covering the given (minimum, maximum) ranges in degrees
+    pub fn new(
+        yaw_range: (f32, f32),
+        pitch_range: (f32, f32),
+        yaw_step: f32,
+        pitch_step: f32,
+    ) -> Self {
+        let width = grid_angle_count(yaw_range, yaw_step);
+        let height = grid_angle_count(pitch_range, pitch_step);
+        let size = width * height;
+        RangeImage {
+            width,
+            height,
+            yaw_min: yaw_range.0,
+            pitch_max: pitch_range.1,
+            yaw_step,
+            pitch_step,
+            distance: vec![0; size],
+            signal_strength: vec![0; size],
+            temperature: vec![f32::NAN; size],
+            valid: vec![false; size],
+        }
+    }
+
+    /// Image covering the angles of the given records
+    pub fn from_records(records: &[PointRecord], yaw_step: f32, pitch_step: f32) -> Self {
+        let range = |values: &mut dyn Iterator<Item = f32>| {
+            values.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), value| {
+                (min.min(value), max.max(value))
+            })
+        };
+        let yaw_range = range(&mut records.iter().map(|record| record.yaw));
+        let pitch_range = range(&mut records.iter().map(|record| record.pitch));
+        let mut image = RangeImage::new(yaw_range, pitch_range, yaw_step, pitch_step);
+        for record in records {
+            image.insert(record);
+        }
+        image
+    }
+
+    /// Index of the pixel nearest to the given angles, if it is inside the image
+    pub fn pixel_index(&self, yaw: f32, pitch: f32) -> Option<usize> {
+        let column = ((yaw - self.yaw_min) / self.yaw_step).round();
+        let row = ((self.pitch_max - pitch) / self.pitch_step).round();
+        let is_inside = |value: f32, size: usize| value >= 0.0 && value < size as f32;
+        (is_inside(column, self.width) && is_inside(row, self.height))
+            .then_some(row as usize * self.width + column as usize)
+    }
+
+    /// Stores a measurement in its pixel, returns false if it is outside the image
+    pub fn insert(&mut self, record: &PointRecord) -> bool {
+        let Some(index) = self.pixel_index(record.yaw, record.pitch) else {
+            return false;
+        };
+        self.distance[index] = record.distance;
+        self.signal_strength[index] = record.signal_strength;
+        self.temperature[index] = record.temperature;
+        self.valid[index] = is_valid_measurement(record.distance, record.signal_strength);
+        true
+    }
+
+    /// Distances with invalid pixels set to 0, which depth images treat as missing
+    pub fn valid_distance(&self) -> Vec<u16> {
+        self.distance
+            .iter()
+            .zip(&self.valid)
+            .map(|(distance, valid)| if *valid { *distance } else { 0 })
+            .collect()
+    }
+
+    /// Logs the images under `<entity_path>/distance`, `<entity_path>/signal_strength`,
+    /// `<entity_path>/temperature` and `<entity_path>/valid`
+    pub fn log(&self, rec: &RecordingStream, entity_path: &str) -> RecordingStreamResult<()> {
+        let size = [self.width as u32, self.height as u32];
+        rec.log(
+            format!("{entity_path}/distance"),
+            // Distances are in centimeters
+            &rerun::DepthImage::new(
+                to_bytes(&self.valid_distance(), u16::to_le_bytes),
+                rerun::ImageFormat::depth(size, rerun::ChannelDatatype::U16),
+            )
+            .with_meter(100.0),
+        )?;
+        rec.log(
+            format!("{entity_path}/signal_strength"),
+            &rerun::Image::new(
+                to_bytes(&self.signal_strength, u16::to_le_bytes),
+                rerun::ImageFormat::from_color_model(
+                    size,
+                    rerun::ColorModel::L,
+                    rerun::ChannelDatatype::U16,
+                ),
+            ),
+        )?;
+        rec.log(
+            format!("{entity_path}/temperature"),
+            &rerun::Image::new(
+                to_bytes(&self.temperature, f32::to_le_bytes),
+                rerun::ImageFormat::from_color_model(
+                    size,
+                    rerun::ColorModel::L,
+                    rerun::ChannelDatatype::F32,
+                ),
+            ),
+        )?;
+        rec.log(
+            format!("{entity_path}/valid"),
+            &rerun::Image::new(
+                self.valid_mask(),
+                rerun::ImageFormat::from_color_model(
+                    size,
+                    rerun::ColorModel::L,
+                    rerun::ChannelDatatype::U8,
+                ),
+            ),
+        )
+    }
+
+    /// Saves the distance, signal strength, temperature and validity images
+    /// to `<prefix>_distance.<ext>`, `<prefix>_signal_strength.<ext>`, ...
+    /// The distance image contains the raw distances, to be combined with the validity mask.
+    pub fn save(&self, prefix: impl AsRef<Path>, format: RangeImageFormat) -> io::Result<()> {
+        if self.width == 0 || self.height == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Range image is empty",
+            ));
+        }
+        let path = |name: &str, extension: &str| {
+            let mut path = prefix.as_ref().as_os_str().to_owned();
+            path.push(format!("_{name}.{extension}"));
+            PathBuf::from(path)
+        };
+        match format {
+            RangeImageFormat::Png => {
+                let ext = |name| path(name, "png");
+                // PNG stores 16-bit samples in big-endian order
+                self.write_png(
+                    ext("distance"),
+                    png::BitDepth::Sixteen,
+                    &to_bytes(&self.distance, u16::to_be_bytes),
+                )?;
+                self.write_png(
+                    ext("signal_strength"),
+                    png::BitDepth::Sixteen,
+                    &to_bytes(&self.signal_strength, u16::to_be_bytes),
+                )?;
+                let temperature: Vec<u16> = self
+                    .temperature
+                    .iter()
+                    .map(|temperature| match temperature.is_nan() {
+                        true => 0,
+                        false => (temperature * 100.0).round() as u16,
+                    })
+                    .collect();
+                self.write_png(
+                    ext("temperature"),
+                    png::BitDepth::Sixteen,
+                    &to_bytes(&temperature, u16::to_be_bytes),
+                )?;
+                self.write_png(ext("valid"), png::BitDepth::Eight, &self.valid_mask())
+            }
+            RangeImageFormat::Npy => {
+                let ext = |name| path(name, "npy");
+                self.write_npy(
+                    ext("distance"),
+                    "<u2",
+                    &to_bytes(&self.distance, u16::to_le_bytes),
+                )?;
+                self.write_npy(
+                    ext("signal_strength"),
+                    "<u2",
+                    &to_bytes(&self.signal_strength, u16::to_le_bytes),
+                )?;
+                self.write_npy(
+                    ext("temperature"),
+                    "<f4",
+                    &to_bytes(&self.temperature, f32::to_le_bytes),
+                )?;
+                let valid: Vec<u8> = self.valid.iter().map(|valid| *valid as u8).collect();
+                self.write_npy(ext("valid"), "|b1", &valid)
+            }
+        }
+    }
+
+    // 255 for valid pixels and 0 for invalid ones
+    fn valid_mask(&self) -> Vec<u8> {
+        self.valid
+            .iter()
+            .map(|valid| if *valid { u8::MAX } else { 0 })
+            .collect()
+    }
+
+    fn write_png(&self, path: PathBuf, depth: png::BitDepth, data: &[u8]) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(depth);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer.write_image_data(data).map_err(io::Error::other)
+    }
+
+    // NumPy format version 1.0, with the header padded so that the data is 64-byte aligned
+    fn write_npy(&self, path: PathBuf, descr: &str, data: &[u8]) -> io::Result<()> {
+        let mut header = format!(
+            "{{'descr': '{descr}', 'fortran_order': False, 'shape': ({}, {}), }}",
+            self.height, self.width
+        );
+        // Magic string, version and header length take 10 bytes, the header ends with a newline
+        let unpadded_length = 10 + header.len() + 1;
+        header.push_str(&" ".repeat((64 - unpadded_length % 64) % 64));
+        header.push('\n');
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(b"\x93NUMPY\x01\x00")?;
+        file.write_all(&(header.len() as u16).to_le_bytes())?;
+        file.write_all(header.as_bytes())?;
+        file.write_all(data)?;
+        file.flush()
+    }
+}
+
+fn to_bytes<T: Copy, const N: usize>(values: &[T], to_bytes: fn(T) -> [u8; N]) -> Vec<u8> {
+    values.iter().flat_map(|value| to_bytes(*value)).collect()
+}
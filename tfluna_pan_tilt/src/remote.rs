@@ -0,0 +1,685 @@
+//! Remote control of the rig over HTTP with JSON responses.
+//!
+//! | Request            | Body                         | Effect                                         |
+//! |--------------------|------------------------------|------------------------------------------------|
+//! | `GET /status`      |                              | State and progress of the current scan         |
+//! | `POST /point`      | `{"yaw": 10, "pitch": -5}`   | Moves the head, only between scans             |
+//! | `POST /read`       |                              | Takes a reading at the current angles          |
+//! | `POST /scan`       | TOML configuration overlay   | Starts a scan                                  |
+//! | `POST /pause`      |                              | Pauses the scan before its next pose           |
+//! | `POST /resume`     |                              | Resumes a paused scan                          |
+//! | `POST /stop`       |                              | Stops the scan, keeping the points measured    |
+//! | `GET /results`     |                              | Points of the last scan                        |
+//!
+//! Requests that need the rig are forwarded as `RigRequest`s to the thread that owns it,
+//! the others are answered from the shared `ScanControl`.
+//! Requests received while a scan runs are refused with 409 Conflict.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::backlash::BacklashCompensation;
+use crate::checkpoint::Checkpoint;
+use crate::config::ScanConfig;
+use crate::point_cloud::PointRecord;
+use crate::scan::ScanMode;
+use crate::scanner::{Rig, new_recording_id, planned_poses, scan, supports_checkpoints};
+use crate::servo::Servo;
+
+// Interval at which the rig thread checks for signals while waiting for commands
+const COMMAND_POLLING_INTERVAL: Duration = Duration::from_millis(200);
+// Largest request body accepted, in bytes
+const MAX_BODY_LENGTH: usize = 1 << 20;
+// Largest request line or header line accepted, in bytes
+const MAX_LINE_LENGTH: u64 = 8 << 10;
+// Largest number of header lines accepted
+const MAX_HEADER_LINES: usize = 100;
+// Time after which a client that stopped sending its request is disconnected
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+// Interval at which a paused scan checks whether it was resumed or stopped
+const PAUSE_POLLING_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// Malformed request
+    Invalid(String),
+    /// Request body larger than the largest accepted, with its length in bytes
+    TooLarge(usize),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "Failed reading request: {err}"),
+            Error::Invalid(message) => write!(f, "{message}"),
+            Error::TooLarge(length) => write!(
+                f,
+                "Request body of {length} bytes is larger than {MAX_BODY_LENGTH} bytes"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScanState {
+    /// No scan was started yet
+    Idle,
+    Running,
+    Paused,
+    Completed,
+    /// Stopped by a request or a signal before the end
+    Stopped,
+    Failed,
+}
+
+/// Progress of the current or last scan
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScanStatus {
+    pub state: ScanState,
+    /// Recording id of the scan, `None` before the first scan
+    pub recording_id: Option<String>,
+    pub measured_points: usize,
+    /// Number of poses of the scan, `None` if it is not known in advance
+    pub planned_poses: Option<usize>,
+    /// Last commanded angles in degrees
+    pub yaw: Option<f32>,
+    pub pitch: Option<f32>,
+    /// Error of a failed scan
+    pub error: Option<String>,
+}
+
+/// State shared between a scan and the requests controlling it
+pub struct ScanControl {
+    /// Set by the signal handler, stops the scan and the server
+    interrupted: Arc<AtomicBool>,
+    /// Set by a stop request, only stops the current scan
+    stopped: AtomicBool,
+    paused: AtomicBool,
+    status: Mutex<ScanStatus>,
+    results: Mutex<Vec<PointRecord>>,
+}
+
+impl ScanControl {
+    pub fn new(interrupted: Arc<AtomicBool>) -> Self {
+        ScanControl {
+            interrupted,
+            stopped: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            status: Mutex::new(ScanStatus {
+                state: ScanState::Idle,
+                recording_id: None,
+                measured_points: 0,
+                planned_poses: None,
+                yaw: None,
+                pitch: None,
+                error: None,
+            }),
+            results: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Whether the scan must stop, after a signal or a stop request
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed) || self.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Whether a signal was received, after which the server stops too
+    pub fn is_terminated(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+    }
+
+    /// Blocks while the scan is paused, unless it is interrupted
+    pub fn wait_while_paused(&self) {
+        while self.paused.load(Ordering::Relaxed) && !self.is_interrupted() {
+            thread::sleep(PAUSE_POLLING_INTERVAL);
+        }
+    }
+
+    // A panic while updating the status cannot leave it inconsistent
+    fn lock_status(&self) -> MutexGuard<'_, ScanStatus> {
+        self.status
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn status(&self) -> ScanStatus {
+        self.lock_status().clone()
+    }
+
+    pub fn update_status(&self, f: impl FnOnce(&mut ScanStatus)) {
+        f(&mut self.lock_status());
+    }
+
+    pub fn is_scanning(&self) -> bool {
+        matches!(
+            self.lock_status().state,
+            ScanState::Running | ScanState::Paused
+        )
+    }
+
+    /// Resets the flags and the progress for a new scan
+    pub fn start(&self, recording_id: &str, planned_poses: Option<usize>) {
+        self.stopped.store(false, Ordering::Relaxed);
+        self.paused.store(false, Ordering::Relaxed);
+        self.update_status(|status| {
+            status.state = ScanState::Running;
+            status.recording_id = Some(recording_id.to_string());
+            status.measured_points = 0;
+            status.planned_poses = planned_poses;
+            status.error = None;
+        });
+    }
+
+    /// Records the outcome and the points of a scan
+    pub fn finish(&self, records: &[PointRecord], error: Option<String>) {
+        let state = match (&error, self.is_interrupted()) {
+            (Some(_), _) => ScanState::Failed,
+            (None, true) => ScanState::Stopped,
+            (None, false) => ScanState::Completed,
+        };
+        self.paused.store(false, Ordering::Relaxed);
+        self.update_status(|status| {
+            status.state = state;
+            status.measured_points = records.len();
+            status.error = error;
+        });
+        *self
+            .results
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = records.to_vec();
+    }
+
+    fn set_paused(&self, paused: bool) -> Response {
+        if !self.is_scanning() {
+            return Response::error(409, "No scan is running");
+        }
+        self.paused.store(paused, Ordering::Relaxed);
+        self.update_status(|status| {
+            status.state = match paused {
+                true => ScanState::Paused,
+                false => ScanState::Running,
+            }
+        });
+        Response::json(200, &self.status())
+    }
+
+    fn stop(&self) -> Response {
+        if !self.is_scanning() {
+            return Response::error(409, "No scan is running");
+        }
+        self.stopped.store(true, Ordering::Relaxed);
+        Response::json(200, &self.status())
+    }
+}
+
+/// Target of a point request, in degrees
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Angles {
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+/// Request executed by the thread that owns the rig
+#[derive(Debug, Clone, PartialEq)]
+pub enum RigCommand {
+    Point(Angles),
+    Read,
+    /// Overlay applied to the configuration of the server, in TOML
+    Scan(String),
+}
+
+/// Command forwarded to the thread that owns the rig, with the channel of its response
+#[derive(Debug)]
+pub struct RigRequest {
+    pub command: RigCommand,
+    /// When the request was received. The thread only reads requests between scans,
+    /// so it must refuse the ones received before the end of the last scan, see `is_stale`.
+    pub received: Instant,
+    pub reply: Sender<Response>,
+}
+
+impl RigRequest {
+    /// Whether the request was received before the end of the last scan
+    pub fn is_stale(&self, last_scan_end: Option<Instant>) -> bool {
+        last_scan_end.is_some_and(|end| self.received < end)
+    }
+
+    /// Sends the response, which the client may no longer wait for
+    pub fn respond(self, response: Response) {
+        let _ = self.reply.send(response);
+    }
+}
+
+/// Reading returned by a read request
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Reading {
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Distance in centimeters
+    pub distance: u16,
+    pub signal_strength: u16,
+    /// Temperature in degrees Celsius
+    pub temperature: f32,
+    /// Position in centimeters in the base frame of the head
+    pub position: [f32; 3],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    /// JSON document
+    pub body: String,
+}
+
+impl Response {
+    pub fn json<T: Serialize>(status: u16, value: &T) -> Response {
+        match serde_json::to_string(value) {
+            Ok(body) => Response { status, body },
+            Err(err) => Response::error(500, &format!("Failed serializing response: {err}")),
+        }
+    }
+
+    pub fn error(status: u16, message: &str) -> Response {
+        Response {
+            status,
+            body: serde_json::json!({ "error": message }).to_string(),
+        }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Reads an HTTP/1.1 request, whose body is given by its `Content-Length` header
+pub fn read_request(reader: &mut impl BufRead) -> Result<Request, Error> {
+    let invalid = |message: &str| Error::Invalid(message.to_string());
+    let mut line = String::new();
+    read_line(reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(invalid("Invalid request line"));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+    let mut content_length = 0;
+    for header_lines in 0.. {
+        line.clear();
+        if read_line(reader, &mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if header_lines == MAX_HEADER_LINES {
+            return Err(invalid("Too many header lines"));
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid("Invalid Content-Length"))?;
+            }
+        }
+    }
+    if content_length > MAX_BODY_LENGTH {
+        return Err(Error::TooLarge(content_length));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|_| invalid("Request body is not UTF-8"))?;
+    Ok(Request { method, path, body })
+}
+
+// Reads a line of at most `MAX_LINE_LENGTH` bytes, returns its length
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> Result<usize, Error> {
+    let length = reader.by_ref().take(MAX_LINE_LENGTH).read_line(line)?;
+    if length as u64 == MAX_LINE_LENGTH && !line.ends_with('\n') {
+        return Err(Error::Invalid(String::from("Request line too long")));
+    }
+    Ok(length)
+}
+
+pub fn write_response(writer: &mut impl Write, response: &Response) -> io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason(response.status),
+        response.body.len(),
+        response.body
+    )?;
+    writer.flush()
+}
+
+/// Answers a request, forwarding the ones that need the rig to its thread
+pub fn handle(request: &Request, control: &ScanControl, commands: &Sender<RigRequest>) -> Response {
+    let received = Instant::now();
+    let command = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/status") => return Response::json(200, &control.status()),
+        ("GET", "/results") => {
+            let results = control
+                .results
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            return Response::json(200, &*results);
+        }
+        ("POST", "/pause") => return control.set_paused(true),
+        ("POST", "/resume") => return control.set_paused(false),
+        ("POST", "/stop") => return control.stop(),
+        ("POST", "/point") => match serde_json::from_str(&request.body) {
+            Ok(angles) => RigCommand::Point(angles),
+            Err(err) => return Response::error(400, &format!("Invalid angles: {err}")),
+        },
+        ("POST", "/read") => RigCommand::Read,
+        ("POST", "/scan") => RigCommand::Scan(request.body.clone()),
+        (
+            _,
+            "/status" | "/results" | "/pause" | "/resume" | "/stop" | "/point" | "/read" | "/scan",
+        ) => {
+            return Response::error(405, "Method not allowed");
+        }
+        _ => return Response::error(404, "Unknown path"),
+    };
+    // Refused here rather than after the scan, a scan starting meanwhile is caught by the rig thread
+    if control.is_scanning() {
+        return Response::error(409, "A scan is running");
+    }
+    let (reply, response) = mpsc::channel();
+    let request = RigRequest {
+        command,
+        received,
+        reply,
+    };
+    if commands.send(request).is_err() {
+        return Response::error(503, "The rig is not available");
+    }
+    response
+        .recv()
+        .unwrap_or_else(|_| Response::error(503, "The rig is not available"))
+}
+
+// Accepts connections in a background thread, each handled in its own thread
+fn accept_connections(
+    listener: TcpListener,
+    control: Arc<ScanControl>,
+    commands: Sender<RigRequest>,
+) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let control = Arc::clone(&control);
+            let commands = commands.clone();
+            thread::spawn(move || {
+                if let Err(err) = handle_connection(stream, &control, &commands) {
+                    eprintln!("Failed handling request: {err}");
+                }
+            });
+        }
+    });
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    control: &ScanControl,
+    commands: &Sender<RigRequest>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = match read_request(&mut reader) {
+        Ok(request) => handle(&request, control, commands),
+        Err(err @ Error::Invalid(_)) => Response::error(400, &err.to_string()),
+        Err(err @ Error::TooLarge(_)) => Response::error(413, &err.to_string()),
+        Err(Error::Io(err)) if err.kind() == io::ErrorKind::InvalidData => {
+            Response::error(400, &err.to_string())
+        }
+        Err(Error::Io(err)) => return Err(err),
+    };
+    let mut stream = stream;
+    write_response(&mut stream, &response)
+}
+
+/// Listens on `address` and executes the commands of the requests with the rig
+/// until a signal is received. Each scan gets a new recording.
+pub fn serve<S: Servo>(
+    config: &ScanConfig,
+    rig: &mut Rig<S>,
+    address: &str,
+    control: Arc<ScanControl>,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    println!("Listening on {}", listener.local_addr()?);
+    let (sender, commands) = mpsc::channel();
+    accept_connections(listener, Arc::clone(&control), sender);
+    let mut last_scan_end = None;
+    while !control.is_terminated() {
+        let request = match commands.recv_timeout(COMMAND_POLLING_INTERVAL) {
+            Ok(request) => request,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        // Requests received during a scan waited for its end
+        if request.is_stale(last_scan_end) {
+            request.respond(Response::error(409, "A scan was running"));
+            continue;
+        }
+        // The client may have disconnected, which does not stop the command
+        match request.command.clone() {
+            RigCommand::Point(angles) => request.respond(point(config, rig, angles, &control)),
+            RigCommand::Read => request.respond(read(config, rig, &control)),
+            RigCommand::Scan(overlay) => {
+                let (job, mut checkpoint) = match scan_job(config, &overlay) {
+                    Ok(job) => job,
+                    Err(err) => {
+                        request.respond(Response::error(400, &err));
+                        continue;
+                    }
+                };
+                println!("Starting scan {}", checkpoint.recording_id);
+                control.start(&checkpoint.recording_id, planned_poses(&job));
+                // The client follows the scan with status requests
+                request.respond(Response::json(202, &control.status()));
+                let result = scan(&job, &mut checkpoint, rig, &control, None);
+                if let Err(err) = &result {
+                    println!("Scan {} failed: {err}", checkpoint.recording_id);
+                }
+                control.finish(&checkpoint.records, result.err().map(|err| err.to_string()));
+                last_scan_end = Some(Instant::now());
+            }
+        }
+    }
+    Ok(())
+}
+
+// Configuration and checkpoint of a scan requested to the server.
+// The servos were set up from the configuration of the server,
+// so a request can only narrow their angle ranges.
+// Requests are not authenticated, so they cannot choose the files written by the server.
+fn scan_job(config: &ScanConfig, overlay: &str) -> Result<(ScanConfig, Checkpoint), String> {
+    let job = config
+        .with_overlay(overlay, "scan request")
+        .map_err(|err| err.to_string())?;
+    let without_ranges = |config: &ScanConfig| {
+        let mut servos = config.servos.clone();
+        for servo in [&mut servos.bottom, &mut servos.top] {
+            servo.min_angle = 0.0;
+            servo.max_angle = 0.0;
+        }
+        servos
+    };
+    if without_ranges(&job) != without_ranges(config) {
+        return Err(String::from(
+            "Only the servo angle ranges can be changed by a scan request",
+        ));
+    }
+    if job.output != config.output {
+        return Err(String::from(
+            "The output settings cannot be changed by a scan request",
+        ));
+    }
+    for (name, servo, limits) in [
+        ("bottom", &job.servos.bottom, &config.servos.bottom),
+        ("top", &job.servos.top, &config.servos.top),
+    ] {
+        if servo.min_angle < limits.min_angle || servo.max_angle > limits.max_angle {
+            return Err(format!(
+                "The {name} servo range [{}, {}] is not within [{}, {}]",
+                servo.min_angle, servo.max_angle, limits.min_angle, limits.max_angle
+            ));
+        }
+    }
+    if !supports_checkpoints(job.scan.mode) && job.output.checkpoint.is_some() {
+        return Err(String::from(
+            "Checkpoints are only supported in grid, adaptive and planar modes",
+        ));
+    }
+    // The servos of the server compensate the backlash unless it sweeps
+    let is_sweep = |config: &ScanConfig| config.scan.mode == ScanMode::Sweep;
+    if compensates_backlash(config) && is_sweep(&job) != is_sweep(config) {
+        return Err(String::from(
+            "Sweeps and the other modes cannot be mixed by a server compensating the servo backlash",
+        ));
+    }
+    let recording_id = new_recording_id().map_err(|err| err.to_string())?;
+    let checkpoint = Checkpoint::new(recording_id, job.to_toml().map_err(|err| err.to_string())?);
+    Ok((job, checkpoint))
+}
+
+// Whether a servo compensates its backlash
+fn compensates_backlash(config: &ScanConfig) -> bool {
+    [&config.servos.bottom, &config.servos.top]
+        .iter()
+        .any(|servo| servo.backlash.compensation != BacklashCompensation::None)
+}
+
+// Moves the head for a point request
+fn point(
+    config: &ScanConfig,
+    rig: &mut Rig<impl Servo>,
+    angles: Angles,
+    control: &ScanControl,
+) -> Response {
+    if let Err(err) = rig.servos.set_angles(&[angles.yaw, angles.pitch]) {
+        return Response::error(400, &err.to_string());
+    }
+    thread::sleep(Duration::from_millis(config.scan.servo_motor_delay as u64));
+    control.update_status(|status| {
+        status.yaw = Some(angles.yaw);
+        status.pitch = Some(angles.pitch);
+    });
+    Response::json(200, &angles)
+}
+
+// Takes a single reading for a read request, where the head was last pointed
+fn read(config: &ScanConfig, rig: &mut Rig<impl Servo>, control: &ScanControl) -> Response {
+    let status = control.status();
+    let (Some(yaw), Some(pitch)) = (status.yaw, status.pitch) else {
+        return Response::error(409, "The head must be pointed before reading");
+    };
+    let measurement_delay = Duration::from_millis(config.sensor.measurement_delay as u64);
+    if let Err(err) = rig.tfluna.trigger_measurement() {
+        return Response::error(500, &format!("Failed triggering a measurement: {err:?}"));
+    }
+    thread::sleep(measurement_delay);
+    match rig.tfluna.get_measurement() {
+        Ok(reading) => Response::json(
+            200,
+            &Reading {
+                yaw,
+                pitch,
+                distance: reading.distance,
+                signal_strength: reading.signal_strength,
+                temperature: reading.temperature,
+                position: config
+                    .kinematics
+                    .pose(yaw, pitch)
+                    .point_at(reading.distance as f32),
+            },
+        ),
+        Err(err) => Response::error(500, &format!("Failed reading the sensor: {err:?}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan_request(body: &str) -> Request {
+        let request = format!(
+            "POST /scan HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        read_request(&mut request.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn scan_request_narrows_angle_ranges() {
+        let config = ScanConfig::default();
+        let request =
+            scan_request("[scan]\nangle_step = 4.0\n[servos.bottom]\nmin_angle = -10.0\n");
+        let (job, checkpoint) = scan_job(&config, &request.body).unwrap();
+        assert_eq!(job.scan.angle_step, 4.0);
+        assert_eq!(job.servos.bottom.min_angle, -10.0);
+        assert_eq!(ScanConfig::from_toml(&checkpoint.config).unwrap(), job);
+    }
+
+    #[test]
+    fn scan_request_cannot_widen_angle_ranges() {
+        let config = ScanConfig::default();
+        let overlay = format!(
+            "[servos.top]\nmax_angle = {}\n",
+            config.servos.top.max_angle + 10.0
+        );
+        assert!(scan_job(&config, &scan_request(&overlay).body).is_err());
+    }
+
+    #[test]
+    fn scan_request_cannot_change_outputs() {
+        let config = ScanConfig::default();
+        for overlay in [
+            "[output]\npoint_cloud = \"/home/pi/.bashrc\"\n",
+            "[output]\nrrd_file = \"../scan.rrd\"\n",
+            "[output]\nevent_log = \"/tmp/events.jsonl\"\n",
+            "[output]\ncheckpoint = \"scan.checkpoint\"\n",
+            "[output]\nrerun_server_ip = \"192.0.2.1\"\n",
+        ] {
+            let request = scan_request(overlay);
+            assert_eq!(
+                scan_job(&config, &request.body).err().as_deref(),
+                Some("The output settings cannot be changed by a scan request"),
+                "{overlay}"
+            );
+        }
+    }
+}
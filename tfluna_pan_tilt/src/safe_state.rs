@@ -0,0 +1,143 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c as I2cTrait;
+use embedded_tfluna::{RangingMode, i2c::TFLuna};
+use pwm_pca9685::Channel;
+use rppal::pwm::Pwm;
+use signal_hook::consts::TERM_SIGNALS;
+use signal_hook::flag;
+
+use crate::servo::{SharedPca9685, pulse_duration};
+
+/// Frame rate of the TF-Luna after power-on, in Hz
+pub const DEFAULT_FRAMERATE: u16 = 100;
+
+/// Returns a flag that is set when SIGINT, SIGTERM or SIGQUIT is received,
+/// so that the scan can stop at the next pose and clean up.
+/// A second signal terminates the process immediately.
+pub fn install_signal_handler() -> std::io::Result<Arc<AtomicBool>> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    for signal in TERM_SIGNALS {
+        // Registered first, so that it only sees the flag set by a previous signal
+        flag::register_conditional_shutdown(*signal, 1, Arc::clone(&interrupted))?;
+        flag::register(*signal, Arc::clone(&interrupted))?;
+    }
+    Ok(interrupted)
+}
+
+/// How the servos are put in a safe state, depending on what drives them
+pub enum ServoShutdown<P: I2cTrait> {
+    /// The servos are sent to their neutral position, given as the channel and pulse width
+    /// in microseconds of each servo, then the PCA9685 is put to sleep
+    Pca9685 {
+        pwm: SharedPca9685<P>,
+        neutral_pulse_widths: Vec<(Channel, f32)>,
+    },
+    /// The servos are sent to their neutral position, given as the pulse width in microseconds
+    /// on each channel, then the outputs are disabled
+    HardwarePwm(Vec<(Arc<Pwm>, f32)>),
+}
+
+impl<P: I2cTrait> ServoShutdown<P> {
+    fn move_to_neutral(&self) -> bool {
+        match self {
+            ServoShutdown::Pca9685 {
+                pwm,
+                neutral_pulse_widths,
+            } => {
+                let pulses: Vec<(Channel, u16)> = neutral_pulse_widths
+                    .iter()
+                    .map(|(channel, pulse_width)| (*channel, pwm.pulse_counts(*pulse_width)))
+                    .collect();
+                pwm.set_pulses(&pulses).is_ok()
+            }
+            // Every channel is tried, even if a previous one failed
+            ServoShutdown::HardwarePwm(channels) => {
+                channels
+                    .iter()
+                    .filter(|(pwm, pulse_width)| {
+                        pwm.set_pulse_width(pulse_duration(*pulse_width)).is_err()
+                    })
+                    .count()
+                    == 0
+            }
+        }
+    }
+
+    fn disable(&self) -> bool {
+        match self {
+            ServoShutdown::Pca9685 { pwm, .. } => pwm.with(|pwm| pwm.disable()).is_ok(),
+            ServoShutdown::HardwarePwm(channels) => {
+                channels
+                    .iter()
+                    .filter(|(pwm, _)| pwm.disable().is_err())
+                    .count()
+                    == 0
+            }
+        }
+    }
+}
+
+/// Puts the rig in a safe state when dropped, whether the scan completed,
+/// returned an error or panicked:
+/// - the servos are sent back to their neutral position,
+/// - the servo outputs are turned off, which de-energises the servos,
+/// - the TF-Luna is set back to continuous ranging at its default frame rate.
+///
+/// The sensor is accessed through its own device on the shared I2C bus,
+/// so that the scan keeps exclusive access to its own instance.
+pub struct SafeState<'a, P: I2cTrait, S: I2cTrait, D: DelayNs> {
+    servos: ServoShutdown<P>,
+    sensor: TFLuna<S, D>,
+    /// Time given to the servos to reach their neutral position
+    settle_time: Duration,
+    /// Clears the poison of the mutex of the shared I2C bus, see `with_shared_bus`
+    clear_poison: Option<Box<dyn Fn() + 'a>>,
+}
+
+impl<'a, P: I2cTrait, S: I2cTrait, D: DelayNs> SafeState<'a, P, S, D> {
+    pub fn new(servos: ServoShutdown<P>, sensor: TFLuna<S, D>, settle_time: Duration) -> Self {
+        SafeState {
+            servos,
+            sensor,
+            settle_time,
+            clear_poison: None,
+        }
+    }
+
+    /// Lets the clean-up access the devices through the mutex of a shared I2C bus
+    /// even when a panic poisoned it. `embedded_hal_bus::i2c::MutexDevice` panics on a poisoned lock,
+    /// which would abort the process while unwinding, whereas the devices are still usable.
+    pub fn with_shared_bus<B>(mut self, bus: &'a Mutex<B>) -> Self {
+        self.clear_poison = Some(Box::new(move || bus.clear_poison()));
+        self
+    }
+}
+
+impl<P: I2cTrait, S: I2cTrait, D: DelayNs> Drop for SafeState<'_, P, S, D> {
+    fn drop(&mut self) {
+        if let Some(clear_poison) = &self.clear_poison {
+            clear_poison();
+        }
+        // Errors can only be reported here, the rig is put in the safest state possible regardless
+        if !self.servos.move_to_neutral() {
+            eprintln!("Failed moving the servos to their neutral position");
+        }
+        thread::sleep(self.settle_time);
+        if !self.servos.disable() {
+            eprintln!("Failed disabling the servo outputs");
+        }
+        if self.sensor.set_framerate(DEFAULT_FRAMERATE).is_err()
+            || self
+                .sensor
+                .set_ranging_mode(RangingMode::Continuous)
+                .is_err()
+        {
+            eprintln!("Failed restoring the TF-Luna ranging mode");
+        }
+    }
+}
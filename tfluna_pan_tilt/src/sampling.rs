@@ -0,0 +1,136 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::range_image::is_valid_measurement;
+
+/// How the samples measured at a single pose are combined into one point
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Aggregation {
+    Mean,
+    Median,
+    /// Mean of the samples left after removing the `trim_fraction` smallest and largest distances
+    TrimmedMean,
+    /// Mean of the distances weighted by their signal strength
+    SignalWeightedMean,
+}
+
+impl FromStr for Aggregation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mean" => Ok(Aggregation::Mean),
+            "median" => Ok(Aggregation::Median),
+            "trimmed-mean" => Ok(Aggregation::TrimmedMean),
+            "signal-weighted-mean" => Ok(Aggregation::SignalWeightedMean),
+            _ => Err(format!(
+                "Unknown aggregation '{s}', expected one of: \
+                 mean, median, trimmed-mean, signal-weighted-mean"
+            )),
+        }
+    }
+}
+
+/// A single reading of the TF-Luna
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    /// Distance in centimeters
+    pub distance: u16,
+    pub signal_strength: u16,
+    /// Temperature in degrees Celsius
+    pub temperature: f32,
+}
+
+/// Samples of a pose combined into one measurement
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AggregatedSample {
+    /// Distance in centimeters
+    pub distance: u16,
+    /// Mean signal strength of the samples the distance was computed from
+    pub signal_strength: u16,
+    /// Mean temperature of all the samples, in degrees Celsius
+    pub temperature: f32,
+    /// Standard deviation in centimeters of the distances the distance was computed from
+    pub distance_spread: f32,
+    /// Number of samples with a valid distance, see `is_valid_measurement`
+    pub valid_samples: usize,
+}
+
+/// Combines the samples of a pose, `None` if there are none.
+///
+/// Only samples with a valid distance are aggregated, so that a dropout
+/// does not pull the distance towards 0. If none of them is valid,
+/// all the samples are aggregated and the result stays invalid.
+/// `trim_fraction` is the fraction of samples removed at each end by the trimmed mean.
+pub fn aggregate(
+    samples: &[Sample],
+    aggregation: Aggregation,
+    trim_fraction: f32,
+) -> Option<AggregatedSample> {
+    if samples.is_empty() {
+        return None;
+    }
+    let valid: Vec<Sample> = samples
+        .iter()
+        .filter(|sample| is_valid_measurement(sample.distance, sample.signal_strength))
+        .copied()
+        .collect();
+    let valid_samples = valid.len();
+    let mut used = if valid.is_empty() {
+        samples.to_vec()
+    } else {
+        valid
+    };
+    used.sort_by_key(|sample| sample.distance);
+
+    let distances: Vec<f32> = used.iter().map(|sample| sample.distance as f32).collect();
+    let distance = match aggregation {
+        Aggregation::Mean => mean(&distances),
+        Aggregation::Median => {
+            let middle = distances.len() / 2;
+            match distances.len() % 2 {
+                0 => (distances[middle - 1] + distances[middle]) / 2.0,
+                _ => distances[middle],
+            }
+        }
+        Aggregation::TrimmedMean => {
+            // At least one sample is kept
+            let trimmed = (distances.len() as f32 * trim_fraction.clamp(0.0, 0.5)) as usize;
+            let trimmed = trimmed.min((distances.len() - 1) / 2);
+            mean(&distances[trimmed..distances.len() - trimmed])
+        }
+        Aggregation::SignalWeightedMean => {
+            let total_weight: f32 = used.iter().map(|s| s.signal_strength as f32).sum();
+            match total_weight > 0.0 {
+                true => {
+                    used.iter()
+                        .map(|s| s.distance as f32 * s.signal_strength as f32)
+                        .sum::<f32>()
+                        / total_weight
+                }
+                false => mean(&distances),
+            }
+        }
+    };
+    let distance_mean = mean(&distances);
+    let variance = distances
+        .iter()
+        .map(|distance| (distance - distance_mean).powi(2))
+        .sum::<f32>()
+        / distances.len() as f32;
+    let signal_strengths: Vec<f32> = used.iter().map(|s| s.signal_strength as f32).collect();
+    let temperatures: Vec<f32> = samples.iter().map(|s| s.temperature).collect();
+    Some(AggregatedSample {
+        distance: distance.round() as u16,
+        signal_strength: mean(&signal_strengths).round() as u16,
+        temperature: mean(&temperatures),
+        distance_spread: variance.sqrt(),
+        valid_samples,
+    })
+}
+
+fn mean(values: &[f32]) -> f32 {
+    values.iter().sum::<f32>() / values.len() as f32
+}
@@ -0,0 +1,211 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Order in which the grid of servo angles is visited.
+/// Rows have a constant yaw (bottom servo) and go through all pitch angles (top servo).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScanPattern {
+    /// Rows alternate between going down and going up,
+    /// which avoids moving the top servo back after each row
+    Serpentine,
+    /// Every row goes from the maximum to the minimum pitch angle
+    Raster,
+}
+
+impl FromStr for ScanPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "serpentine" => Ok(ScanPattern::Serpentine),
+            "raster" => Ok(ScanPattern::Raster),
+            _ => Err(format!(
+                "Unknown scan pattern '{s}', expected one of: serpentine, raster"
+            )),
+        }
+    }
+}
+
+/// How the poses of a scan are chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScanMode {
+    /// Uniform grid with a spacing of `angle_step`
+    Grid,
+    /// Coarse grid with a spacing of `angle_step`, refined where the scene has edges,
+    /// see `AdaptivePlanner`
+    Adaptive,
+    /// Rows of constant pitch measured while the bottom servo moves continuously,
+    /// see `crate::sweep`
+    Sweep,
+    /// Search for the nearest object, then keep the beam on it, see `crate::tracking`
+    Track,
+    /// Single row of yaw angles at a fixed pitch, also exported as a laser scan,
+    /// see `crate::laser_scan`
+    Planar,
+}
+
+impl FromStr for ScanMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "grid" => Ok(ScanMode::Grid),
+            "adaptive" => Ok(ScanMode::Adaptive),
+            "sweep" => Ok(ScanMode::Sweep),
+            "track" => Ok(ScanMode::Track),
+            "planar" => Ok(ScanMode::Planar),
+            _ => Err(format!(
+                "Unknown scan mode '{s}', expected one of: grid, adaptive, sweep, track, planar"
+            )),
+        }
+    }
+}
+
+/// A single pose of a grid scan
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridPose {
+    /// Index of the row, i.e. of the yaw angle
+    pub row: usize,
+    /// Index of the pose within its row, in visiting order
+    pub column: usize,
+    /// Bottom servo angle in degrees
+    pub yaw: f32,
+    /// Top servo angle in degrees
+    pub pitch: f32,
+}
+
+/// Number of angles of a grid with the given step over a range given as (minimum, maximum),
+/// starting from one end of the range.
+///
+/// Angles are computed as `start ± i * angle_step` rather than by repeatedly adding the step,
+/// whose rounding errors could skip the last angle of a range that is a multiple of the step.
+pub fn grid_angle_count((min, max): (f32, f32), angle_step: f32) -> usize {
+    if angle_step <= 0.0 || max < min {
+        return 0;
+    }
+    // Tolerance for ranges that are a multiple of the step up to rounding errors
+    ((max - min) / angle_step + 1e-4).floor() as usize + 1
+}
+
+/// Part of the range, in degrees, that is not reached by a grid starting from one of its ends,
+/// non-zero when the range is not a multiple of the step
+pub fn uncovered_angle(range: (f32, f32), angle_step: f32) -> f32 {
+    match grid_angle_count(range, angle_step) {
+        0 => range.1 - range.0,
+        count => (range.1 - range.0 - (count - 1) as f32 * angle_step).max(0.0),
+    }
+}
+
+/// Computes the list of poses of a grid scan, in visiting order.
+/// Ranges are given as (minimum, maximum) in degrees.
+pub fn plan_grid(
+    yaw_range: (f32, f32),
+    pitch_range: (f32, f32),
+    angle_step: f32,
+    pattern: ScanPattern,
+) -> Vec<GridPose> {
+    let pitch_count = grid_angle_count(pitch_range, angle_step);
+    let mut poses = Vec::new();
+    for row in 0..grid_angle_count(yaw_range, angle_step) {
+        let yaw = yaw_range.0 + row as f32 * angle_step;
+        // Whether the top servo goes from bottom to top in this row
+        let go_up = pattern == ScanPattern::Serpentine && row % 2 == 1;
+        for column in 0..pitch_count {
+            let pitch = match go_up {
+                true => pitch_range.0 + column as f32 * angle_step,
+                false => pitch_range.1 - column as f32 * angle_step,
+            };
+            poses.push(GridPose {
+                row,
+                column,
+                yaw,
+                pitch,
+            });
+        }
+    }
+    poses
+}
+
+/// Expected duration and servo motion of a scan
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrajectorySummary {
+    /// Number of measurements
+    pub measurements: usize,
+    pub duration: Duration,
+    /// Total rotation of the bottom servo in degrees
+    pub yaw_travel: f32,
+    /// Total rotation of the top servo in degrees
+    pub pitch_travel: f32,
+}
+
+/// Summarizes a list of poses visited in order by the scanner, which waits `servo_motor_delay`
+/// after moving each servo and `measurement_delay` after triggering and after reading each
+/// of the `samples_per_pose` measurements.
+pub fn summarize_poses(
+    poses: &[GridPose],
+    servo_motor_delay: Duration,
+    measurement_delay: Duration,
+    samples_per_pose: u32,
+) -> TrajectorySummary {
+    let mut summary = TrajectorySummary {
+        measurements: poses.len(),
+        duration: Duration::ZERO,
+        yaw_travel: 0.0,
+        pitch_travel: 0.0,
+    };
+    let mut previous: Option<&GridPose> = None;
+    for pose in poses {
+        // The bottom servo only moves when the yaw changes
+        if previous.is_none_or(|previous| previous.yaw != pose.yaw) {
+            summary.duration += servo_motor_delay;
+        }
+        summary.duration += servo_motor_delay + 2 * measurement_delay * samples_per_pose;
+        if let Some(previous) = previous {
+            summary.yaw_travel += (pose.yaw - previous.yaw).abs();
+            summary.pitch_travel += (pose.pitch - previous.pitch).abs();
+        }
+        previous = Some(pose);
+    }
+    summary
+}
+
+/// Source of the poses of a scan, which may depend on the measurements already made.
+///
+/// Poses are handed out in batches. Every pose of a batch is measured,
+/// and its measurement passed to `record`, before the next batch is requested.
+pub trait ScanPlanner {
+    /// Next batch of poses in visiting order, empty once the scan is complete
+    fn next_poses(&mut self) -> Vec<GridPose>;
+
+    /// Measurement made at a pose of the current batch
+    fn record(&mut self, _pose: &GridPose, _distance: u16, _signal_strength: u16) {}
+}
+
+/// Plans a uniform grid as a single batch, see `plan_grid`
+#[derive(Debug, Clone)]
+pub struct GridPlanner {
+    poses: Option<Vec<GridPose>>,
+}
+
+impl GridPlanner {
+    pub fn new(
+        yaw_range: (f32, f32),
+        pitch_range: (f32, f32),
+        angle_step: f32,
+        pattern: ScanPattern,
+    ) -> Self {
+        GridPlanner {
+            poses: Some(plan_grid(yaw_range, pitch_range, angle_step, pattern)),
+        }
+    }
+}
+
+impl ScanPlanner for GridPlanner {
+    fn next_poses(&mut self) -> Vec<GridPose> {
+        self.poses.take().unwrap_or_default()
+    }
+}
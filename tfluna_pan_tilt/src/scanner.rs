@@ -0,0 +1,1122 @@
+//! Drives the rig through a scan: moves the servos, measures each pose or frame,
+//! logs the measurements to rerun and writes the outputs of the configuration.
+
+use std::io;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, SystemTimeError};
+
+use embedded_hal_bus::i2c::MutexDevice;
+use embedded_tfluna::{RangingMode, SensorReading, i2c::TFLuna};
+use rerun::RecordingStreamError;
+use rppal::hal::Delay;
+
+use crate::adaptive::{AdaptivePlanner, MAX_DEPTH};
+use crate::checkpoint::{self, Checkpoint};
+use crate::coloring::PointColorizer;
+use crate::config::{self, ScanConfig, ServoConfig};
+use crate::events::{self, Event, EventLog};
+use crate::laser_scan::LaserScan;
+use crate::metadata::RecordingMetadata;
+use crate::point_cloud::{PointRecord, save_point_cloud};
+use crate::point_cloud_logger::PointCloudLogger;
+use crate::pose_logger::PoseLogger;
+use crate::range_image::{RangeImage, is_valid_measurement};
+use crate::remote::ScanControl;
+use crate::sampling::{Sample, aggregate};
+use crate::scan::{GridPlanner, ScanMode, ScanPlanner, grid_angle_count};
+use crate::selftest::read_tfluna_information;
+use crate::servo::{self, Servo, ServoBank};
+use crate::settle::settle_delay;
+use crate::simulation::{RigBus, TFLUNA_ADDRESS};
+use crate::sweep::{SensorClock, SweepRow, SweepTrajectory, plan_sweep_rows};
+use crate::timelapse::detect_changes;
+use crate::tracking::{Probe, Tracker};
+
+// Rerun parameters
+pub const APPLICATION_ID: &str = "rpi-lidar";
+pub const YAW_ENTITY_PATH: &str = "yaw";
+pub const PITCH_ENTITY_PATH: &str = "pitch";
+pub const DISTANCE_ENTITY_PATH: &str = "distance";
+pub const SIGNAL_STRENGTH_ENTITY_PATH: &str = "signal_strength";
+pub const TEMPERATURE_ENTITY_PATH: &str = "temperature";
+pub const DISTANCE_SPREAD_ENTITY_PATH: &str = "distance_spread";
+pub const POSITION_ENTITY_PATH: &str = "position";
+pub const REFINEMENT_LEVEL_ENTITY_PATH: &str = "refinement_level";
+pub const REFINEMENT_ENTITY_PATH: &str = "refinement";
+pub const RANGE_IMAGE_ENTITY_PATH: &str = "range_image";
+pub const LASER_SCAN_ENTITY_PATH: &str = "laser_scan";
+pub const RIG_ENTITY_PATH: &str = "rig";
+pub const LOG_ENTITY_PATH: &str = "log";
+pub const TARGET_ENTITY_PATH: &str = "tracking/target";
+pub const TARGET_DISTANCE_ENTITY_PATH: &str = "tracking/distance";
+pub const CHANGES_ENTITY_PATH: &str = "changes";
+
+/// TF-Luna of the rig, on the shared I2C bus
+pub type Sensor<'a> = TFLuna<MutexDevice<'a, RigBus>, Delay>;
+
+/// Indices of the servos in `Rig::servos`
+pub const BOTTOM: usize = 0;
+pub const TOP: usize = 1;
+
+#[derive(Debug)]
+pub enum Error {
+    Servo(servo::Error),
+    /// Failed access to the TF-Luna, whose driver errors only implement Debug
+    Sensor(String),
+    /// The measurement of a pose has no sample
+    NoSample,
+    Rerun(RecordingStreamError),
+    Events(events::Error),
+    Config(config::Error),
+    Checkpoint(checkpoint::Error),
+    /// Failed writing an output file
+    Io(PathBuf, io::Error),
+    Clock(SystemTimeError),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Servo(err) => write!(f, "{err}"),
+            Error::Sensor(message) => write!(f, "{message}"),
+            Error::NoSample => write!(f, "No sample was measured"),
+            Error::Rerun(err) => write!(f, "Failed logging to rerun: {err}"),
+            Error::Events(err) => write!(f, "{err}"),
+            Error::Config(err) => write!(f, "{err}"),
+            Error::Checkpoint(err) => write!(f, "{err}"),
+            Error::Io(path, err) => write!(f, "Failed writing '{}': {err}", path.display()),
+            Error::Clock(err) => write!(f, "The system clock is before the Unix epoch: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<servo::Error> for Error {
+    fn from(err: servo::Error) -> Self {
+        Error::Servo(err)
+    }
+}
+
+impl From<RecordingStreamError> for Error {
+    fn from(err: RecordingStreamError) -> Self {
+        Error::Rerun(err)
+    }
+}
+
+impl From<events::Error> for Error {
+    fn from(err: events::Error) -> Self {
+        Error::Events(err)
+    }
+}
+
+impl From<config::Error> for Error {
+    fn from(err: config::Error) -> Self {
+        Error::Config(err)
+    }
+}
+
+impl From<checkpoint::Error> for Error {
+    fn from(err: checkpoint::Error) -> Self {
+        Error::Checkpoint(err)
+    }
+}
+
+impl From<SystemTimeError> for Error {
+    fn from(err: SystemTimeError) -> Self {
+        Error::Clock(err)
+    }
+}
+
+/// Measures a whole scan and writes its outputs.
+/// The records are added to the checkpoint, so that they are kept when the scan fails.
+/// A scan of a time-lapse is compared to its reference scan, see `crate::timelapse`.
+pub fn scan<S: Servo>(
+    config: &ScanConfig,
+    checkpoint: &mut Checkpoint,
+    rig: &mut Rig<S>,
+    control: &ScanControl,
+    reference: Option<&Checkpoint>,
+) -> Result<(), Error> {
+    let (yaw_range, pitch_range) = scan_ranges(config);
+
+    // Sweep and tracking scans are not made of planned poses,
+    // see `Scanner::sweep_scan` and `Scanner::track`
+    let planner: Option<Box<dyn ScanPlanner>> = match config.scan.mode {
+        ScanMode::Grid | ScanMode::Planar => Some(Box::new(GridPlanner::new(
+            yaw_range,
+            pitch_range,
+            config.scan.angle_step,
+            config.scan.pattern,
+        ))),
+        ScanMode::Adaptive => Some(Box::new(AdaptivePlanner::new(
+            yaw_range,
+            pitch_range,
+            config.scan.angle_step,
+            config.scan.pattern,
+            config.adaptive.clone(),
+        ))),
+        ScanMode::Sweep | ScanMode::Track => None,
+    };
+
+    rig.servos[BOTTOM].set_angle(yaw_range.0)?;
+    thread::sleep(Duration::from_millis(1000));
+
+    let point_cloud_logger = PointCloudLogger::new(
+        POSITION_ENTITY_PATH,
+        config.output.logging_strategy,
+        config.color.point_radius,
+    );
+
+    // The recording id is kept when resuming, so that both parts end up in the same recording
+    let rec = connect_rerun(config, &checkpoint.recording_id)?;
+    rec.send_recording_name(format!(
+        "{}deg-{}ms",
+        config.scan.angle_step, config.scan.servo_motor_delay,
+    ))?;
+    rec.send_property(
+        "servo_motor_delay",
+        &rerun::Scalars::single(config.scan.servo_motor_delay),
+    )?;
+    rec.send_property(
+        "angle_step",
+        &rerun::Scalars::single(config.scan.angle_step),
+    )?;
+    rec.send_property(
+        "samples_per_pose",
+        &rerun::Scalars::single(config.sensor.samples_per_pose.max(1)),
+    )?;
+    rec.send_property(
+        "kinematics",
+        &rerun::Scalars::new(config.kinematics.to_parameters()),
+    )?;
+    rec.send_property("config", &rerun::TextDocument::new(config.to_toml()?))?;
+    // A sensor whose information cannot be read is left for the scan to report
+    let tfluna = read_tfluna_information(&mut rig.bus, TFLUNA_ADDRESS)
+        .inspect_err(|err| println!("Failed reading the TF-Luna information: {err:?}"))
+        .ok();
+    RecordingMetadata::collect(tfluna).send(&rec, config)?;
+    // Positions are in the base frame of the pan-tilt head, whose z axis points up
+    rec.log_static("/", &rerun::ViewCoordinates::RIGHT_HAND_Z_UP)?;
+    let mut events = EventLog::new(
+        LOG_ENTITY_PATH,
+        &checkpoint.recording_id,
+        config.output.event_log.as_deref(),
+    )?;
+    events.log(
+        &rec,
+        Event::ScanStarted {
+            mode: config.scan.mode,
+            first_pose: checkpoint.completed_poses() + 1,
+        },
+    )?;
+
+    let mut scanner = Scanner {
+        config,
+        rig,
+        rec,
+        events,
+        point_cloud_logger,
+        pose_logger: PoseLogger::new(RIG_ENTITY_PATH),
+        colorizer: PointColorizer::new(&config.color),
+        control,
+    };
+    let result = match planner {
+        Some(planner) => scanner.pose_scan(planner, checkpoint),
+        None if config.scan.mode == ScanMode::Track => scanner.track(&mut checkpoint.records),
+        None => scanner.sweep_scan(&mut checkpoint.records),
+    };
+
+    // Whatever was measured is saved, also when the scan failed or was interrupted
+    let is_complete = result.is_ok() && !control.is_interrupted();
+    // Before the exports, which may fail and would otherwise lose the poses to resume
+    if let Some(path) = &config.output.checkpoint {
+        if is_complete {
+            // The scan is complete, there is nothing left to resume
+            Checkpoint::remove(path)?;
+        } else {
+            checkpoint.save(path)?;
+            println!("Resume the scan with --resume {}", path.display());
+        }
+    }
+    let points = checkpoint.records.len();
+    let event = match &result {
+        Err(err) => Event::ScanFailed {
+            points,
+            error: err.to_string(),
+        },
+        Ok(()) if !is_complete => Event::ScanAborted { points },
+        Ok(()) => Event::ScanCompleted { points },
+    };
+    if !is_complete {
+        println!("{event}");
+    }
+    scanner.event(event)?;
+    let range_image = scan_range_image(config, &checkpoint.records);
+    range_image.log(&scanner.rec, RANGE_IMAGE_ENTITY_PATH)?;
+    // The poses measured before an interruption are compared too
+    if let (Some(reference), Ok(())) = (reference, &result) {
+        let report = detect_changes(
+            &reference.records,
+            &checkpoint.records,
+            &scan_range_image(config, &[]),
+            &config.timelapse,
+        );
+        report.log(
+            &scanner.rec,
+            CHANGES_ENTITY_PATH,
+            config.color.point_radius * 2.0,
+        )?;
+        let event = Event::ReferenceCompared {
+            reference: reference.recording_id.clone(),
+            compared_poses: report.compared_poses,
+            changed_poses: report.changed_poses(),
+            regions: report.regions.len(),
+        };
+        println!("{event}");
+        for region in &report.regions {
+            let (min, max) = region.bounds();
+            println!(
+                "  {} poses between {min:.0?} and {max:.0?} cm, {}",
+                region.changes.len(),
+                match region.mean_difference() {
+                    Some(difference) => format!("{difference:+.1} cm on average"),
+                    None => String::from("valid in one of the scans only"),
+                }
+            );
+        }
+        scanner.event(event)?;
+    }
+    if let Some(prefix) = &config.output.range_image {
+        println!(
+            "Writing {}x{} range image to {}",
+            range_image.width,
+            range_image.height,
+            prefix.display()
+        );
+        range_image
+            .save(prefix, config.output.range_image_format)
+            .map_err(|err| Error::Io(prefix.clone(), err))?;
+    }
+    if config.scan.mode == ScanMode::Planar {
+        let laser_scan =
+            LaserScan::from_records(&checkpoint.records, yaw_range, config.scan.angle_step);
+        // Point radii are in centimeters, laser scans in meters
+        laser_scan.log(
+            &scanner.rec,
+            LASER_SCAN_ENTITY_PATH,
+            config.color.point_radius / 100.0,
+        )?;
+        if let Some(path) = &config.output.laser_scan {
+            println!(
+                "Writing laser scan of {} rays to {}",
+                laser_scan.ranges.len(),
+                path.display()
+            );
+            laser_scan
+                .save(path)
+                .map_err(|err| Error::Io(path.clone(), err))?;
+        }
+    }
+    if let Some(path) = &config.output.point_cloud {
+        println!(
+            "Writing {} points to {}",
+            checkpoint.records.len(),
+            path.display()
+        );
+        // Autoscaled colors use the percentiles of the whole scan
+        let colors = PointColorizer::colors(&config.color, &checkpoint.records);
+        save_point_cloud(
+            path,
+            &checkpoint.records,
+            Some(&colors),
+            config.output.point_cloud_format,
+        )
+        .map_err(|err| Error::Io(path.clone(), err))?;
+    }
+    scanner.point_cloud_logger.finish_row(&scanner.rec)?;
+    scanner.rec.flush_blocking();
+    result
+}
+
+/// Yaw and pitch ranges of the scan, as (minimum, maximum) in degrees.
+/// Planar scans keep the top servo at a fixed pitch.
+pub fn scan_ranges(config: &ScanConfig) -> ((f32, f32), (f32, f32)) {
+    let yaw_range = (
+        config.servos.bottom.min_angle,
+        config.servos.bottom.max_angle,
+    );
+    let pitch_range = match config.scan.mode {
+        ScanMode::Planar => (config.planar.pitch, config.planar.pitch),
+        _ => (config.servos.top.min_angle, config.servos.top.max_angle),
+    };
+    (yaw_range, pitch_range)
+}
+
+/// Number of poses of a scan, for the ones known in advance
+pub fn planned_poses(config: &ScanConfig) -> Option<usize> {
+    let (yaw_range, pitch_range) = scan_ranges(config);
+    match config.scan.mode {
+        ScanMode::Grid | ScanMode::Planar => Some(
+            grid_angle_count(yaw_range, config.scan.angle_step)
+                * grid_angle_count(pitch_range, config.scan.angle_step),
+        ),
+        ScanMode::Adaptive | ScanMode::Sweep | ScanMode::Track => None,
+    }
+}
+
+/// Whether the poses of a scan mode can be resumed from a checkpoint
+pub fn supports_checkpoints(mode: ScanMode) -> bool {
+    matches!(mode, ScanMode::Grid | ScanMode::Adaptive | ScanMode::Planar)
+}
+
+/// Connects to the rerun server or saves to the file of the configuration
+pub fn connect_rerun(
+    config: &ScanConfig,
+    recording_id: &str,
+) -> Result<rerun::RecordingStream, Error> {
+    let builder = rerun::RecordingStreamBuilder::new(APPLICATION_ID).recording_id(recording_id);
+    let rec = match &config.output.rrd_file {
+        Some(path) => builder.save(path)?,
+        None => builder.connect_grpc_opts(
+            format!("rerun+http://{}:9876/proxy", config.output.rerun_server_ip),
+            rerun::default_flush_timeout(),
+        )?,
+    };
+    Ok(rec)
+}
+
+/// Range image with one pixel per pose of the grid, per vertex of the finest lattice
+/// for adaptive scans, per frame for sweep scans and per probe step for tracking
+pub fn scan_range_image(config: &ScanConfig, records: &[PointRecord]) -> RangeImage {
+    let angle_step = config.scan.angle_step;
+    let (yaw_step, pitch_step) = match config.scan.mode {
+        ScanMode::Grid | ScanMode::Planar => (angle_step, angle_step),
+        // Probes around the target are rounded to the closest pixel
+        ScanMode::Track => {
+            let step = config.tracking.probe_step.min(angle_step);
+            (step, step)
+        }
+        ScanMode::Adaptive => {
+            let step = angle_step / (1u32 << config.adaptive.max_depth.min(MAX_DEPTH)) as f32;
+            (step, step)
+        }
+        ScanMode::Sweep => (
+            config.sweep.speed / config.sweep.framerate.max(1) as f32,
+            angle_step,
+        ),
+    };
+    let (yaw_range, pitch_range) = scan_ranges(config);
+    let mut range_image = RangeImage::new(yaw_range, pitch_range, yaw_step, pitch_step);
+    for record in records {
+        range_image.insert(record);
+    }
+    range_image
+}
+
+/// Sensor and servos of the pan-tilt head
+pub struct Rig<'a, S: Servo> {
+    pub tfluna: Sensor<'a>,
+    /// Shared I2C bus, for the registers the drivers do not expose
+    pub bus: MutexDevice<'a, RigBus>,
+    /// Bottom and top servos, see `BOTTOM` and `TOP`
+    pub servos: ServoBank<S>,
+}
+
+impl<S: Servo> Rig<'_, S> {
+    /// Triggers a measurement and reads it, waiting for the given delay after each
+    pub fn read_sensor(&mut self, measurement_delay: Duration) -> Result<SensorReading, Error> {
+        self.tfluna
+            .trigger_measurement()
+            .map_err(sensor_error("triggering a measurement"))?;
+        thread::sleep(measurement_delay);
+        let reading = self
+            .tfluna
+            .get_measurement()
+            .map_err(sensor_error("reading the sensor"))?;
+        thread::sleep(measurement_delay);
+        Ok(reading)
+    }
+}
+
+/// Errors of the TF-Luna driver, which only implement Debug
+pub fn sensor_error<E: std::fmt::Debug>(action: &str) -> impl FnOnce(E) -> Error + '_ {
+    move |err| Error::Sensor(format!("Failed {action}: {err:?}"))
+}
+
+// Everything needed while scanning
+struct Scanner<'r, 'a, S: Servo> {
+    config: &'r ScanConfig,
+    rig: &'r mut Rig<'a, S>,
+    rec: rerun::RecordingStream,
+    events: EventLog,
+    point_cloud_logger: PointCloudLogger,
+    pose_logger: PoseLogger,
+    colorizer: PointColorizer,
+    // Stops and pauses the scan, and follows its progress
+    control: &'r ScanControl,
+}
+
+impl<S: Servo> Scanner<'_, '_, S> {
+    fn is_interrupted(&self) -> bool {
+        self.control.is_interrupted()
+    }
+
+    fn event(&mut self, event: Event) -> Result<(), Error> {
+        self.events.log(&self.rec, event)?;
+        Ok(())
+    }
+
+    // Logs the transforms of the head and its beam for a measurement,
+    // at the time set by `log_record`
+    fn log_pose(&self, record: &PointRecord) -> Result<(), Error> {
+        let pose = self.config.kinematics.pose(record.yaw, record.pitch);
+        self.pose_logger
+            .log(&self.rec, &pose, record.distance as f32)?;
+        Ok(())
+    }
+
+    // Triggers a measurement and reads it.
+    // A failed sensor access is attempted again up to `SensorConfig::retries` times.
+    fn read_sensor(&mut self) -> Result<SensorReading, Error> {
+        let config = self.config;
+        let measurement_delay = Duration::from_millis(config.sensor.measurement_delay as u64);
+        let mut attempt = 0;
+        loop {
+            let tfluna = &mut self.rig.tfluna;
+            let result = tfluna.trigger_measurement().and_then(|_| {
+                thread::sleep(measurement_delay);
+                tfluna.get_measurement()
+            });
+            thread::sleep(measurement_delay);
+            match result {
+                Ok(reading) => {
+                    if attempt > 0 {
+                        self.event(Event::Recovered {
+                            attempts: attempt + 1,
+                        })?;
+                    }
+                    return Ok(reading);
+                }
+                Err(err) if attempt < config.sensor.retries => {
+                    attempt += 1;
+                    self.event(Event::Retry {
+                        attempt,
+                        error: format!("{err:?}"),
+                    })?;
+                }
+                Err(err) => return Err(sensor_error("reading the sensor")(err)),
+            }
+        }
+    }
+
+    // Measures the servo angles at which the head is, with `SensorConfig::samples_per_pose` samples.
+    // A measurement without a valid distance is reported as an event.
+    fn measure(&mut self, yaw: f32, pitch: f32) -> Result<PointRecord, Error> {
+        let config = self.config;
+        let samples_per_pose = config.sensor.samples_per_pose.max(1);
+        let mut samples = Vec::with_capacity(samples_per_pose as usize);
+        for _ in 0..samples_per_pose {
+            let reading = self.read_sensor()?;
+            samples.push(Sample {
+                distance: reading.distance,
+                signal_strength: reading.signal_strength,
+                temperature: reading.temperature,
+            });
+        }
+        // There is at least one sample
+        let measurement = aggregate(
+            &samples,
+            config.sensor.aggregation,
+            config.sensor.trim_fraction,
+        )
+        .ok_or(Error::NoSample)?;
+        // Point 3D position
+        let position = config
+            .kinematics
+            .pose(yaw, pitch)
+            .point_at(measurement.distance as f32);
+        if !is_valid_measurement(measurement.distance, measurement.signal_strength) {
+            self.event(Event::InvalidReading {
+                yaw,
+                pitch,
+                distance: measurement.distance,
+                signal_strength: measurement.signal_strength,
+            })?;
+        }
+        Ok(PointRecord {
+            position,
+            distance: measurement.distance,
+            signal_strength: measurement.signal_strength,
+            temperature: measurement.temperature,
+            yaw,
+            pitch,
+            capture_time: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_secs_f64(),
+            distance_spread: measurement.distance_spread,
+        })
+    }
+
+    // Measures the poses given by the planner.
+    // The records are added to the checkpoint, which is saved periodically.
+    fn pose_scan(
+        &mut self,
+        mut planner: Box<dyn ScanPlanner>,
+        checkpoint: &mut Checkpoint,
+    ) -> Result<(), Error> {
+        let config = self.config;
+        let servo_motor_delay = Duration::from_millis(config.scan.servo_motor_delay as u64);
+        let is_adaptive = config.scan.mode == ScanMode::Adaptive;
+        let time_budget = config
+            .adaptive
+            .time_budget
+            .filter(|_| is_adaptive)
+            .map(|seconds| Duration::from_secs(seconds as u64));
+
+        let resumed_poses = checkpoint.completed_poses();
+        if resumed_poses > 0 {
+            println!("Resuming scan at pose {}", resumed_poses + 1);
+        }
+        let scan_start = Instant::now();
+        let mut index = 0;
+        let mut current_row = None;
+        let mut row_points = 0;
+        // The last row is only finished when the scan goes to its end
+        let mut is_complete = true;
+        let mut angle_bottom = None;
+        let mut angle_top = None;
+        // Adaptive scans plan one batch of poses per refinement level
+        'scan: for level in 0.. {
+            let poses = planner.next_poses();
+            if poses.is_empty() {
+                break;
+            }
+            if is_adaptive {
+                println!("Refinement level {}: {} poses", level, poses.len());
+            }
+            let mut level_positions = Vec::with_capacity(poses.len());
+            for pose in poses {
+                if current_row != Some(pose.row) {
+                    self.point_cloud_logger.finish_row(&self.rec)?;
+                    if let Some(row) = current_row {
+                        self.event(Event::RowFinished {
+                            row: row + 1,
+                            points: row_points,
+                        })?;
+                    }
+                    current_row = Some(pose.row);
+                    row_points = 0;
+                }
+                let record = if index < resumed_poses {
+                    // Measurements made before the interruption are only logged again
+                    checkpoint.records[index]
+                } else {
+                    self.control.wait_while_paused();
+                    if self.is_interrupted() {
+                        is_complete = false;
+                        break 'scan;
+                    }
+                    if time_budget.is_some_and(|budget| scan_start.elapsed() >= budget) {
+                        println!("Time budget exhausted after {} poses", index);
+                        is_complete = false;
+                        break 'scan;
+                    }
+                    let rig = &mut *self.rig;
+                    if angle_bottom != Some(pose.yaw) {
+                        rig.servos[BOTTOM].set_angle(pose.yaw)?;
+                        thread::sleep(move_delay(
+                            &config.servos.bottom,
+                            angle_bottom,
+                            pose.yaw,
+                            servo_motor_delay,
+                        ));
+                        angle_bottom = Some(pose.yaw);
+                    }
+                    rig.servos[TOP].set_angle(pose.pitch)?;
+                    thread::sleep(move_delay(
+                        &config.servos.top,
+                        angle_top,
+                        pose.pitch,
+                        servo_motor_delay,
+                    ));
+                    angle_top = Some(pose.pitch);
+
+                    let record = self.measure(pose.yaw, pose.pitch)?;
+                    self.event(Event::Pose {
+                        index: index + 1,
+                        yaw: pose.yaw,
+                        pitch: pose.pitch,
+                    })?;
+                    checkpoint.records.push(record);
+                    if let Some(path) = &config.output.checkpoint {
+                        if checkpoint.completed_poses() % config.output.checkpoint_interval.max(1)
+                            == 0
+                        {
+                            checkpoint.save(path)?;
+                        }
+                    }
+                    record
+                };
+                index += 1;
+                row_points += 1;
+                self.control.update_status(|status| {
+                    status.measured_points = index;
+                    status.yaw = Some(record.yaw);
+                    status.pitch = Some(record.pitch);
+                });
+                planner.record(&pose, record.distance, record.signal_strength);
+                log_record(&self.rec, &record)?;
+                self.log_pose(&record)?;
+                if is_adaptive {
+                    self.rec.log(
+                        REFINEMENT_LEVEL_ENTITY_PATH,
+                        &rerun::Scalars::single(level as f64),
+                    )?;
+                    level_positions.push(record.position);
+                }
+                let color = self.colorizer.push(&record);
+                self.point_cloud_logger
+                    .push(&self.rec, record.position, color)?;
+            }
+            // One entity per refinement level shows where the extra samples went
+            if !level_positions.is_empty() {
+                self.rec.log(
+                    format!("{}/level_{}", REFINEMENT_ENTITY_PATH, level),
+                    &rerun::Points3D::new(level_positions)
+                        .with_colors([self
+                            .colorizer
+                            .gradient()
+                            .at(level as f32 / (config.adaptive.max_depth.max(1) as f32))
+                            .to_rgba8()])
+                        .with_radii([config.color.point_radius]),
+                )?;
+            }
+        }
+        if let Some(row) = current_row.filter(|_| is_complete) {
+            self.event(Event::RowFinished {
+                row: row + 1,
+                points: row_points,
+            })?;
+        }
+        Ok(())
+    }
+
+    // Continuous ranging with the bottom servo moving along each row,
+    // see `tfluna_pan_tilt::sweep`
+    fn sweep_scan(&mut self, records: &mut Vec<PointRecord>) -> Result<(), Error> {
+        let config = self.config;
+        let rows = plan_sweep_rows(
+            (
+                config.servos.bottom.min_angle,
+                config.servos.bottom.max_angle,
+            ),
+            (config.servos.top.min_angle, config.servos.top.max_angle),
+            config.scan.angle_step,
+        );
+        self.rig
+            .tfluna
+            .set_framerate(config.sweep.framerate)
+            .map_err(sensor_error("setting the frame rate"))?;
+        self.rig
+            .tfluna
+            .set_ranging_mode(RangingMode::Continuous)
+            .map_err(sensor_error("setting the ranging mode"))?;
+        let mut clock = SweepClock::new()?;
+        for (i, row) in rows.iter().enumerate() {
+            self.control.wait_while_paused();
+            if self.is_interrupted() {
+                break;
+            }
+            let row_records = sweep_row(config, self.rig, row, &mut clock, self.control)?;
+            println!(
+                "Sweep {} of {}: {} points",
+                i + 1,
+                rows.len(),
+                row_records.len()
+            );
+            for record in &row_records {
+                log_record(&self.rec, record)?;
+                self.log_pose(record)?;
+                let color = self.colorizer.push(record);
+                self.point_cloud_logger
+                    .push(&self.rec, record.position, color)?;
+            }
+            self.point_cloud_logger.finish_row(&self.rec)?;
+            self.event(Event::RowFinished {
+                row: i + 1,
+                points: row_records.len(),
+            })?;
+            records.extend(row_records);
+            self.control.update_status(|status| {
+                status.measured_points = records.len();
+                status.yaw = Some(row.end_yaw);
+                status.pitch = Some(row.pitch);
+            });
+        }
+        self.rig
+            .tfluna
+            .set_ranging_mode(RangingMode::Trigger)
+            .map_err(sensor_error("setting the ranging mode"))?;
+        Ok(())
+    }
+
+    // Searches for the nearest object in the distance window and keeps the beam on it,
+    // see `tfluna_pan_tilt::tracking`. Every probe is recorded, the target is logged
+    // to `TARGET_ENTITY_PATH` after each update.
+    fn track(&mut self, records: &mut Vec<PointRecord>) -> Result<(), Error> {
+        let config = self.config;
+        let servo_motor_delay = Duration::from_millis(config.scan.servo_motor_delay as u64);
+        let duration = config
+            .tracking
+            .duration
+            .map(|seconds| Duration::from_secs(seconds as u64));
+        let mut tracker = Tracker::new(
+            config.tracking.clone(),
+            (
+                config.servos.bottom.min_angle,
+                config.servos.bottom.max_angle,
+            ),
+            (config.servos.top.min_angle, config.servos.top.max_angle),
+            config.scan.angle_step,
+        );
+        let start = Instant::now();
+        while duration.is_none_or(|duration| start.elapsed() < duration) {
+            self.control.wait_while_paused();
+            if self.is_interrupted() {
+                break;
+            }
+            let was_tracking = tracker.target().is_some();
+            let mut probes = Vec::new();
+            for (yaw, pitch) in tracker.next_probes() {
+                // A search over the whole range can take a while
+                if self.is_interrupted() {
+                    return Ok(());
+                }
+                self.rig.servos.set_angles(&[yaw, pitch])?;
+                thread::sleep(servo_motor_delay);
+                let record = self.measure(yaw, pitch)?;
+                log_record(&self.rec, &record)?;
+                self.log_pose(&record)?;
+                probes.push(Probe {
+                    yaw,
+                    pitch,
+                    distance: record.distance,
+                    signal_strength: record.signal_strength,
+                });
+                records.push(record);
+                self.control.update_status(|status| {
+                    status.measured_points = records.len();
+                    status.yaw = Some(yaw);
+                    status.pitch = Some(pitch);
+                });
+            }
+            let target = tracker.update(&probes);
+            let event = match (was_tracking, target) {
+                (false, Some(target)) => Some(Event::TargetAcquired {
+                    yaw: target.yaw,
+                    pitch: target.pitch,
+                    distance: target.distance,
+                }),
+                (false, None) => Some(Event::NoTarget),
+                (true, None) => Some(Event::TargetLost),
+                (true, Some(_)) => None,
+            };
+            if let Some(event) = event {
+                println!("{event}");
+                self.event(event)?;
+            }
+            if let Some(target) = target {
+                let position = config
+                    .kinematics
+                    .pose(target.yaw, target.pitch)
+                    .point_at(target.distance);
+                self.rec.log(
+                    TARGET_ENTITY_PATH,
+                    &rerun::Points3D::new([position]).with_radii([config.color.point_radius * 3.0]),
+                )?;
+                self.rec.log(
+                    TARGET_DISTANCE_ENTITY_PATH,
+                    &rerun::Scalars::single(target.distance),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Logs the values of a single measurement at its capture time
+fn log_record(rec: &rerun::RecordingStream, record: &PointRecord) -> Result<(), Error> {
+    rec.set_time(
+        "capture_time",
+        SystemTime::UNIX_EPOCH + Duration::from_secs_f64(record.capture_time),
+    );
+    rec.log(YAW_ENTITY_PATH, &rerun::Scalars::single(record.yaw))?;
+    rec.log(PITCH_ENTITY_PATH, &rerun::Scalars::single(record.pitch))?;
+    rec.log(
+        DISTANCE_ENTITY_PATH,
+        &rerun::Scalars::single(record.distance),
+    )?;
+    rec.log(
+        SIGNAL_STRENGTH_ENTITY_PATH,
+        &rerun::Scalars::single(record.signal_strength),
+    )?;
+    rec.log(
+        TEMPERATURE_ENTITY_PATH,
+        &rerun::Scalars::single(record.temperature),
+    )?;
+    rec.log(
+        DISTANCE_SPREAD_ENTITY_PATH,
+        &rerun::Scalars::single(record.distance_spread),
+    )?;
+    Ok(())
+}
+
+/// Host clock of a sweep scan along with the synchronised sensor clock
+pub struct SweepClock {
+    start: Instant,
+    /// Unix time in seconds at `start`
+    start_time: f64,
+    pub sensor: SensorClock,
+}
+
+impl SweepClock {
+    pub fn new() -> Result<Self, Error> {
+        Ok(SweepClock {
+            start: Instant::now(),
+            start_time: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_secs_f64(),
+            sensor: SensorClock::default(),
+        })
+    }
+
+    /// Milliseconds since the start of the scan
+    pub fn now(&self) -> f64 {
+        self.start.elapsed().as_secs_f64() * 1000.0
+    }
+}
+
+/// Moves the bottom servo along a row while the sensor is in continuous ranging mode.
+/// The yaw of every frame is interpolated from the commanded trajectory at its sensor timestamp,
+/// shifted by the configured latency.
+/// An interruption ends the row early, keeping the frames measured so far.
+pub fn sweep_row(
+    config: &ScanConfig,
+    rig: &mut Rig<impl Servo>,
+    row: &SweepRow,
+    clock: &mut SweepClock,
+    control: &ScanControl,
+) -> Result<Vec<PointRecord>, Error> {
+    let settings = &config.sweep;
+    rig.servos.set_angles(&[row.start_yaw, row.pitch])?;
+    thread::sleep(Duration::from_millis(config.scan.servo_motor_delay as u64));
+
+    let trajectory = SweepTrajectory {
+        start_yaw: row.start_yaw,
+        end_yaw: row.end_yaw,
+        speed: settings.speed,
+        start_time: clock.now(),
+    };
+    let command_interval = Duration::from_millis(settings.command_interval as u64);
+    // Polling twice per frame period so that no frame is missed
+    let polling_interval = Duration::from_secs_f64(0.5 / settings.framerate.max(1) as f64);
+    // Frames keep coming in after the end of the trajectory for as long as the latency
+    let end_time = trajectory.end_time() + settings.latency.max(0.0) as f64;
+    let mut last_command: Option<Instant> = None;
+    let mut frames: Vec<(u64, SensorReading)> = Vec::new();
+    while clock.now() <= end_time && !control.is_interrupted() {
+        if last_command.is_none_or(|time| time.elapsed() >= command_interval) {
+            rig.servos[BOTTOM].set_angle(trajectory.yaw_at(clock.now()))?;
+            last_command = Some(Instant::now());
+        }
+        let frame = rig
+            .tfluna
+            .get_measurement()
+            .map_err(sensor_error("reading the sensor"))?;
+        let sensor_time = clock.sensor.observe(frame.timestamp, clock.now());
+        // The same frame is read again until the next one is measured
+        if frames.last().is_none_or(|(time, _)| *time != sensor_time) {
+            frames.push((sensor_time, frame));
+        }
+        thread::sleep(polling_interval);
+    }
+
+    let mut records = Vec::with_capacity(frames.len());
+    for (sensor_time, frame) in frames {
+        let Some(host_time) = clock.sensor.to_host(sensor_time) else {
+            continue;
+        };
+        let time = host_time - settings.latency as f64;
+        if !trajectory.contains(time) {
+            continue;
+        }
+        let yaw = trajectory.yaw_at(time);
+        records.push(PointRecord {
+            position: config
+                .kinematics
+                .pose(yaw, row.pitch)
+                .point_at(frame.distance as f32),
+            distance: frame.distance,
+            signal_strength: frame.signal_strength,
+            temperature: frame.temperature,
+            yaw,
+            pitch: row.pitch,
+            capture_time: clock.start_time + host_time / 1000.0,
+            distance_spread: 0.0,
+        });
+    }
+    Ok(records)
+}
+
+// Time to wait after moving a servo from `from` to `to`, from its settle times when they were measured.
+// The first move of a servo, from an unknown angle, waits `servo_motor_delay`.
+fn move_delay(
+    servo: &ServoConfig,
+    from: Option<f32>,
+    to: f32,
+    servo_motor_delay: Duration,
+) -> Duration {
+    from.and_then(|from| settle_delay(&servo.settle_times, to - from))
+        .unwrap_or(servo_motor_delay)
+}
+
+/// Unique id for a new recording
+pub fn new_recording_id() -> Result<String, Error> {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+    Ok(format!("{}-{:x}", APPLICATION_ID, now.as_nanos()))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::fs;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use embedded_tfluna::i2c::Address;
+    use pwm_pca9685::{Address as PWMAddress, Pca9685};
+
+    use super::*;
+    use crate::servo::{ServoMotor, SharedPca9685, channel_from_index};
+    use crate::simulation::SimulatedBus;
+
+    /// Servo of a simulated rig
+    pub(crate) type SimulatedServo<'a> = ServoMotor<MutexDevice<'a, RigBus>>;
+
+    /// File of a test in the temporary directory
+    pub(crate) fn test_path(name: &str, extension: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("tfluna-{name}-{}.{extension}", std::process::id()))
+    }
+
+    /// Default scan of the simulated head without noise, recorded to a file of the test
+    pub(crate) fn simulated_config(name: &str) -> ScanConfig {
+        let mut config = ScanConfig::default();
+        config.simulation.noise = 0.0;
+        config.output.rrd_file = Some(test_path(name, "rrd"));
+        config
+    }
+
+    /// Bus of a new simulated head, whose simulation starts now
+    pub(crate) fn simulated_bus(config: &ScanConfig) -> Mutex<RigBus> {
+        Mutex::new(RigBus::Simulated(Box::new(SimulatedBus::new(config))))
+    }
+
+    /// Rig on a simulated bus, set up like `main` does with the PCA9685 driver
+    pub(crate) fn simulated_rig<'a>(
+        config: &ScanConfig,
+        i2c: &'a Mutex<RigBus>,
+    ) -> Rig<'a, SimulatedServo<'a>> {
+        let mut tfluna =
+            TFLuna::new(MutexDevice::new(i2c), Address::default(), Delay::new()).unwrap();
+        tfluna.enable().unwrap();
+        tfluna.set_ranging_mode(RangingMode::Trigger).unwrap();
+        let pwm = SharedPca9685::new(
+            Pca9685::new(MutexDevice::new(i2c), PWMAddress::default()).unwrap(),
+            config.servos.frequency,
+            config.servos.oscillator_frequency,
+        )
+        .unwrap();
+        pwm.with(|pwm| pwm.enable()).unwrap();
+        let servo = |servo: &ServoConfig| {
+            ServoMotor::new(
+                pwm.clone(),
+                channel_from_index(servo.channel).unwrap(),
+                servo.min_angle,
+                servo.max_angle,
+                servo.reversed,
+                servo.calibration,
+            )
+            .unwrap()
+        };
+        Rig {
+            tfluna,
+            bus: MutexDevice::new(i2c),
+            servos: ServoBank::new(vec![
+                servo(&config.servos.bottom),
+                servo(&config.servos.top),
+            ])
+            .unwrap(),
+        }
+    }
+
+    // Records without their capture times, which differ between runs
+    fn measurements(records: &[PointRecord]) -> Vec<PointRecord> {
+        records
+            .iter()
+            .map(|record| PointRecord {
+                capture_time: 0.0,
+                ..*record
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resumed_scan_measures_like_an_uninterrupted_scan() {
+        let mut config = simulated_config("resume");
+        let path = test_path("resume", "checkpoint");
+        Checkpoint::remove(&path).unwrap();
+        config.output.checkpoint = Some(path.clone());
+        config.output.checkpoint_interval = 2;
+        let planned_poses = 9;
+
+        let i2c = simulated_bus(&config);
+        let mut rig = simulated_rig(&config, &i2c);
+        let control = ScanControl::new(Arc::new(AtomicBool::new(false)));
+        let mut uninterrupted = Checkpoint::new("uninterrupted", config.to_toml().unwrap());
+        scan(&config, &mut uninterrupted, &mut rig, &control, None).unwrap();
+        assert_eq!(uninterrupted.records.len(), planned_poses);
+        assert!(!path.exists());
+
+        // Interrupted as soon as the first checkpoint is saved.
+        // Each run starts on a new head, like after a restart.
+        let i2c = simulated_bus(&config);
+        let mut rig = simulated_rig(&config, &i2c);
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let control = ScanControl::new(Arc::clone(&interrupted));
+        let mut checkpoint = Checkpoint::new("resumed", config.to_toml().unwrap());
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let start = Instant::now();
+                while !path.exists() && start.elapsed() < Duration::from_secs(10) {
+                    thread::sleep(Duration::from_millis(5));
+                }
+                interrupted.store(true, Ordering::SeqCst);
+            });
+            scan(&config, &mut checkpoint, &mut rig, &control, None).unwrap();
+        });
+        let mut resumed = Checkpoint::load(&path).unwrap();
+        assert!((1..planned_poses).contains(&resumed.completed_poses()));
+
+        let i2c = simulated_bus(&config);
+        let mut rig = simulated_rig(&config, &i2c);
+        let control = ScanControl::new(Arc::new(AtomicBool::new(false)));
+        scan(&config, &mut resumed, &mut rig, &control, None).unwrap();
+        assert_eq!(
+            measurements(&resumed.records),
+            measurements(&uninterrupted.records)
+        );
+        assert!(!path.exists());
+        let _ = fs::remove_file(test_path("resume", "rrd"));
+    }
+}
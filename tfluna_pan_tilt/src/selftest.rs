@@ -0,0 +1,221 @@
+use std::fmt;
+
+use embedded_hal::i2c::I2c;
+use tfluna::metadata::TFLunaInformation;
+use tfluna::tf_luna::constants::DISTANCE_REGISTER_ADDRESS;
+
+use crate::range_image::{MIN_SIGNAL_STRENGTH, SATURATED_SIGNAL_STRENGTH};
+use crate::servo::ServoCalibration;
+
+/// Temperatures in degrees Celsius outside of which a TF-Luna reading is implausible,
+/// the operating range of the sensor with a margin for its own heating
+pub const TEMPERATURE_RANGE: (f32, f32) = (-10.0, 80.0);
+
+/// Pulse widths in microseconds that hobby servos accept without hitting their end stops
+pub const SERVO_PULSE_WIDTH_RANGE: (f32, f32) = (500.0, 2500.0);
+
+/// Smallest difference in centimeters between two distances for the readings to be considered different
+pub const MIN_DISTANCE_CHANGE: u16 = 2;
+
+// PCA9685 registers
+const PCA9685_MODE1: u8 = 0x00;
+
+/// Outcome of one check of the self-test
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    /// What was measured, or why the check failed
+    pub reason: String,
+}
+
+/// Pass/fail report of the self-test
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SelfTestReport {
+    pub checks: Vec<Check>,
+}
+
+impl SelfTestReport {
+    /// Adds a check, which passed with `Ok` and failed with `Err`, both with a reason
+    pub fn check(&mut self, name: &str, result: Result<String, String>) {
+        let (passed, reason) = match result {
+            Ok(reason) => (true, reason),
+            Err(reason) => (false, reason),
+        };
+        self.checks.push(Check {
+            name: name.to_string(),
+            passed,
+            reason,
+        });
+    }
+
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let status = match check.passed {
+                true => "PASS",
+                false => "FAIL",
+            };
+            writeln!(f, "{status}  {}: {}", check.name, check.reason)?;
+        }
+        let failed = self.checks.iter().filter(|check| !check.passed).count();
+        match failed {
+            0 => write!(f, "Self-test passed, {} checks", self.checks.len()),
+            _ => write!(
+                f,
+                "Self-test failed, {} of {} checks failed",
+                failed,
+                self.checks.len()
+            ),
+        }
+    }
+}
+
+// Reads consecutive registers one by one, as the TF-Luna documentation describes
+fn read_registers<I: I2c, const N: usize>(
+    i2c: &mut I,
+    address: u8,
+    start: u8,
+) -> Result<[u8; N], I::Error> {
+    let mut values = [0; N];
+    for (offset, value) in values.iter_mut().enumerate() {
+        let mut buffer = [0];
+        i2c.write_read(address, &[start + offset as u8], &mut buffer)?;
+        *value = buffer[0];
+    }
+    Ok(values)
+}
+
+/// Reads the configuration registers of the TF-Luna at the given I2C address
+pub fn read_tfluna_information<I: I2c>(
+    i2c: &mut I,
+    address: u8,
+) -> Result<TFLunaInformation, I::Error> {
+    TFLunaInformation::read(|register| {
+        read_registers(i2c, address, register).map(|[value]: [u8; 1]| value)
+    })
+}
+
+/// Checks that the TF-Luna and, if it drives the servos, the PCA9685 answer at their addresses
+pub fn check_devices<I: I2c>(
+    i2c: &mut I,
+    tfluna_address: u8,
+    pca9685_address: Option<u8>,
+) -> SelfTestReport {
+    let mut report = SelfTestReport::default();
+    let mut devices = vec![("TF-Luna", tfluna_address, DISTANCE_REGISTER_ADDRESS)];
+    if let Some(address) = pca9685_address {
+        devices.push(("PCA9685", address, PCA9685_MODE1));
+    }
+    for (name, address, register) in devices {
+        let result = read_registers::<_, 1>(i2c, address, register)
+            .map(|_| format!("answers at address {address:#04x}"))
+            .map_err(|err| format!("no answer at address {address:#04x}: {err:?}"));
+        report.check(&format!("{name} on the I2C bus"), result);
+    }
+    report
+}
+
+/// Checks that a temperature in degrees Celsius is within `TEMPERATURE_RANGE`
+pub fn check_temperature(temperature: f32) -> Result<String, String> {
+    let (min, max) = TEMPERATURE_RANGE;
+    match (min..=max).contains(&temperature) {
+        true => Ok(format!("{temperature:.1} °C")),
+        false => Err(format!(
+            "{temperature:.1} °C is outside of [{min}, {max}] °C"
+        )),
+    }
+}
+
+/// Checks that a signal strength gives a reliable distance
+pub fn check_signal_strength(signal_strength: u16) -> Result<String, String> {
+    match signal_strength {
+        SATURATED_SIGNAL_STRENGTH => Err(String::from(
+            "saturated, the target is too close or too reflective",
+        )),
+        strength if strength < MIN_SIGNAL_STRENGTH => Err(format!(
+            "{strength} is below {MIN_SIGNAL_STRENGTH}, the target is too far or too dark"
+        )),
+        strength => Ok(strength.to_string()),
+    }
+}
+
+/// Checks that the pulse widths of a servo over its range of angles, given as (minimum, maximum),
+/// are within `SERVO_PULSE_WIDTH_RANGE`
+pub fn check_pulse_widths(
+    calibration: &ServoCalibration,
+    reversed: bool,
+    (min_angle, max_angle): (f32, f32),
+) -> Result<String, String> {
+    let (low, high) = SERVO_PULSE_WIDTH_RANGE;
+    let pulse_widths = [
+        calibration.pulse_width(min_angle, reversed),
+        calibration.pulse_width(max_angle, reversed),
+    ];
+    let message = format!(
+        "{:.0} µs at {min_angle}° and {:.0} µs at {max_angle}°",
+        pulse_widths[0], pulse_widths[1]
+    );
+    match pulse_widths
+        .iter()
+        .all(|pulse_width| (low..=high).contains(pulse_width))
+    {
+        true => Ok(message),
+        false => Err(format!("{message}, outside of [{low}, {high}] µs")),
+    }
+}
+
+/// Checks that readings (distance, signal strength) made at different poses are not all the same,
+/// which they would be if the servos or the sensor were stuck
+pub fn check_readings_change(readings: &[(u16, u16)]) -> Result<String, String> {
+    let distances = readings.iter().map(|(distance, _)| *distance);
+    let (min, max) = (
+        distances.clone().min().unwrap_or(0),
+        distances.max().unwrap_or(0),
+    );
+    let distinct_signal_strengths = readings.windows(2).any(|pair| pair[0].1 != pair[1].1);
+    if max - min >= MIN_DISTANCE_CHANGE {
+        Ok(format!(
+            "distances between {min} and {max} cm over {} poses",
+            readings.len()
+        ))
+    } else if distinct_signal_strengths {
+        Ok(format!(
+            "distances within {} cm but different signal strengths over {} poses",
+            max - min,
+            readings.len()
+        ))
+    } else {
+        Err(format!(
+            "the same reading at all {} poses, check that the head moves",
+            readings.len()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tfluna::tf_luna::{PowerMode, RangingMode};
+
+    use super::*;
+    use crate::config::ScanConfig;
+    use crate::simulation::{SimulatedBus, TFLUNA_ADDRESS};
+
+    #[test]
+    fn reads_the_tfluna_information_of_the_simulated_head() {
+        let mut bus = SimulatedBus::new(&ScanConfig::default());
+        let information = read_tfluna_information(&mut bus, TFLUNA_ADDRESS).unwrap();
+        assert_eq!(information.firmware_version, "3.3.0");
+        assert_eq!(information.serial_number, "SIMULATED00001");
+        assert_eq!(information.slave_address, TFLUNA_ADDRESS);
+        assert_eq!(information.mode, RangingMode::Continuous);
+        assert!(information.enable);
+        assert_eq!(information.power_mode, PowerMode::Normal);
+        assert_eq!(information.frame_rate, 100);
+    }
+}
@@ -1,21 +1,92 @@
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::ops::{Index, IndexMut};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
 
 use embedded_hal::i2c::I2c as I2cTrait;
 use pwm_pca9685::{Channel, Pca9685};
+use serde::{Deserialize, Serialize};
 
-// Servo configuration.
-// -45 degrees
-const FIRST_ANGLE: f32 = -45.0;
-const FIRST_ANGLE_COUNTER: u32 = 200;
-/// 45 degrees
-const SECOND_ANGLE: f32 = 45.0;
-const SECOND_ANGLE_COUNTER: u32 = 410;
-/// Slope
-const SLOPE: f32 =
-    ((SECOND_ANGLE_COUNTER - FIRST_ANGLE_COUNTER) as f32) / (SECOND_ANGLE - FIRST_ANGLE);
-/// Intercept
-const INTERCEPT: f32 = (SECOND_ANGLE_COUNTER as f32) - SLOPE * SECOND_ANGLE;
+use crate::backlash::{Backlash, BacklashConfig};
+
+/// Nominal frequency of the PCA9685 internal oscillator, in Hz
+pub const NOMINAL_OSCILLATOR_FREQUENCY: f32 = 25_000_000.0;
+
+/// Number of steps of the PCA9685 counter in a PWM period
+pub const PCA9685_RESOLUTION: u16 = 4096;
+
+/// Prescale values accepted by the PCA9685
+const PRESCALE_RANGE: (u8, u8) = (3, 255);
+
+/// Servo calibration.
+/// Two reference angles, in degrees, and their corresponding pulse widths, in microseconds.
+/// Pulses for other angles are linearly interpolated.
+///
+/// Pulse widths do not depend on the PWM frequency nor on the driver,
+/// so a calibration carries over between boards.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServoCalibration {
+    pub first_angle: f32,
+    pub first_pulse_width: f32,
+    pub second_angle: f32,
+    pub second_pulse_width: f32,
+}
+
+impl Default for ServoCalibration {
+    fn default() -> Self {
+        // Counter values 200 and 410 of a PCA9685 with a prescale of 122 and a 25 MHz oscillator
+        ServoCalibration {
+            first_angle: -45.0,
+            first_pulse_width: 984.0,
+            second_angle: 45.0,
+            second_pulse_width: 2017.2,
+        }
+    }
+}
+
+impl ServoCalibration {
+    pub fn slope(&self) -> f32 {
+        (self.second_pulse_width - self.first_pulse_width) / (self.second_angle - self.first_angle)
+    }
+
+    pub fn intercept(&self) -> f32 {
+        self.second_pulse_width - self.slope() * self.second_angle
+    }
+
+    /// Pulse width in microseconds corresponding to the given angle
+    pub fn pulse_width(&self, angle: f32, reversed: bool) -> f32 {
+        match reversed {
+            true => -self.slope() * angle + self.intercept(),
+            false => self.slope() * angle + self.intercept(),
+        }
+    }
+}
+
+/// What generates the control pulses of the servos
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ServoDriver {
+    /// PCA9685 on the I2C bus, see `ServoMotor`
+    Pca9685,
+    /// Hardware PWM channels of the Raspberry Pi, see `crate::hardware_pwm`
+    HardwarePwm,
+}
+
+impl FromStr for ServoDriver {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "pca9685" => Ok(ServoDriver::Pca9685),
+            "hardware-pwm" => Ok(ServoDriver::HardwarePwm),
+            _ => Err(format!(
+                "Unknown servo driver '{s}', expected one of: pca9685, hardware-pwm"
+            )),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum Error {
@@ -23,65 +94,538 @@ pub enum Error {
     Other(String),
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidParameter(message) => write!(f, "Invalid parameter: {message}"),
+            Error::Other(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Converts a channel number between 0 and 15 to the corresponding PCA9685 channel
+pub fn channel_from_index(index: u8) -> Result<Channel, Error> {
+    let channel = match index {
+        0 => Channel::C0,
+        1 => Channel::C1,
+        2 => Channel::C2,
+        3 => Channel::C3,
+        4 => Channel::C4,
+        5 => Channel::C5,
+        6 => Channel::C6,
+        7 => Channel::C7,
+        8 => Channel::C8,
+        9 => Channel::C9,
+        10 => Channel::C10,
+        11 => Channel::C11,
+        12 => Channel::C12,
+        13 => Channel::C13,
+        14 => Channel::C14,
+        15 => Channel::C15,
+        _ => {
+            return Err(Error::InvalidParameter(format!(
+                "Channel '{index}' is outside of valid range [0, 15]"
+            )));
+        }
+    };
+    Ok(channel)
+}
+
+/// Inverse of `channel_from_index`, `None` for `Channel::All`
+pub fn channel_index(channel: Channel) -> Option<usize> {
+    let index = match channel {
+        Channel::C0 => 0,
+        Channel::C1 => 1,
+        Channel::C2 => 2,
+        Channel::C3 => 3,
+        Channel::C4 => 4,
+        Channel::C5 => 5,
+        Channel::C6 => 6,
+        Channel::C7 => 7,
+        Channel::C8 => 8,
+        Channel::C9 => 9,
+        Channel::C10 => 10,
+        Channel::C11 => 11,
+        Channel::C12 => 12,
+        Channel::C13 => 13,
+        Channel::C14 => 14,
+        Channel::C15 => 15,
+        Channel::All => return None,
+    };
+    Some(index)
+}
+
+struct Pca9685State<I2c> {
+    pwm: Pca9685<I2c>,
+    /// Actual PWM frequency in Hz, see `pca9685_frequency`
+    frequency: f32,
+    /// Last counter value at which each channel was turned off, all channels are turned on at 0
+    pulses: [u16; 16],
+}
+
+/// Handle to a PCA9685 that can be cloned and shared between threads,
+/// as long as the I2C device is `Send`.
+///
+/// The handle keeps a copy of the pulse of every channel, so that several channels
+/// can be updated in a single I2C write of all 16 channels without changing the others.
+/// All the channels must therefore be set through the handle.
+pub struct SharedPca9685<I2c> {
+    state: Arc<Mutex<Pca9685State<I2c>>>,
+}
+
+impl<I2c> Clone for SharedPca9685<I2c> {
+    fn clone(&self) -> Self {
+        SharedPca9685 {
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl<I2c: I2cTrait> SharedPca9685<I2c> {
+    /// Sets the prescale closest to the requested PWM `frequency`, in Hz,
+    /// given the measured `oscillator_frequency` or `NOMINAL_OSCILLATOR_FREQUENCY`.
+    ///
+    /// The PCA9685 must not be enabled yet, and the outputs of all channels are assumed to be off.
+    pub fn new(
+        mut pwm: Pca9685<I2c>,
+        frequency: f32,
+        oscillator_frequency: Option<f32>,
+    ) -> Result<Self, Error> {
+        let oscillator_frequency = oscillator_frequency.unwrap_or(NOMINAL_OSCILLATOR_FREQUENCY);
+        let prescale = pca9685_prescale(frequency, oscillator_frequency)?;
+        pwm.set_prescale(prescale)
+            .map_err(|_x| Error::Other(format!("Failed setting prescale: {prescale}")))?;
+        Ok(SharedPca9685 {
+            state: Arc::new(Mutex::new(Pca9685State {
+                pwm,
+                frequency: pca9685_frequency(prescale, oscillator_frequency),
+                pulses: [0; 16],
+            })),
+        })
+    }
+
+    // A thread that panicked while holding the lock cannot leave the PCA9685 in an invalid state,
+    // so the lock is still usable, e.g. to put the rig in a safe state
+    fn lock(&self) -> MutexGuard<'_, Pca9685State<I2c>> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Gives exclusive access to the PCA9685, e.g. to configure it.
+    /// Channels set this way are not known to the handle.
+    pub fn with<R>(&self, f: impl FnOnce(&mut Pca9685<I2c>) -> R) -> R {
+        f(&mut self.lock().pwm)
+    }
+
+    /// Actual PWM frequency in Hz, which differs from the requested one
+    /// because the prescale is an integer
+    pub fn frequency(&self) -> f32 {
+        self.lock().frequency
+    }
+
+    /// Counter value of a pulse width in microseconds, at the actual PWM frequency
+    pub fn pulse_counts(&self, pulse_width: f32) -> u16 {
+        pulse_counts(pulse_width, self.frequency())
+    }
+
+    /// Whether both handles refer to the same PCA9685
+    pub fn is_same(&self, other: &SharedPca9685<I2c>) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+
+    /// Last pulse set on the channel, as a counter value
+    pub fn pulse(&self, channel: Channel) -> Option<u16> {
+        channel_index(channel).map(|index| self.lock().pulses[index])
+    }
+
+    /// Sets the counter value at which the output of the channel is turned off
+    pub fn set_pulse(&self, channel: Channel, pulse: u16) -> Result<(), Error> {
+        let index = channel_index(channel).ok_or(Error::InvalidParameter(String::from(
+            "A single channel is required",
+        )))?;
+        let mut state = self.lock();
+        state
+            .pwm
+            .set_channel_on_off(channel, 0, pulse)
+            .map_err(|_x| Error::Other(format!("Failed setting pulse width: {pulse}")))?;
+        state.pulses[index] = pulse;
+        Ok(())
+    }
+
+    /// Sets the pulses of several channels in a single I2C write,
+    /// so that their outputs change during the same PWM period
+    pub fn set_pulses(&self, pulses: &[(Channel, u16)]) -> Result<(), Error> {
+        let mut state = self.lock();
+        let mut new_pulses = state.pulses;
+        for (channel, pulse) in pulses {
+            let index = channel_index(*channel).ok_or(Error::InvalidParameter(String::from(
+                "A single channel is required",
+            )))?;
+            new_pulses[index] = *pulse;
+        }
+        state
+            .pwm
+            .set_all_on_off(&[0; 16], &new_pulses)
+            .map_err(|_x| Error::Other(format!("Failed setting pulse widths: {pulses:?}")))?;
+        state.pulses = new_pulses;
+        Ok(())
+    }
+}
+
+/// Prescale of the PCA9685 giving the PWM frequency closest to `frequency`, in Hz.
+///
+/// `oscillator_frequency` is the measured frequency of the internal oscillator,
+/// which varies from board to board, or `NOMINAL_OSCILLATOR_FREQUENCY`.
+pub fn pca9685_prescale(frequency: f32, oscillator_frequency: f32) -> Result<u8, Error> {
+    let prescale = (oscillator_frequency / (PCA9685_RESOLUTION as f32 * frequency)).round() - 1.0;
+    let (min, max) = PRESCALE_RANGE;
+    if !prescale.is_finite() || prescale < min as f32 || prescale > max as f32 {
+        let min_frequency = pca9685_frequency(max, oscillator_frequency);
+        let max_frequency = pca9685_frequency(min, oscillator_frequency);
+        return Err(Error::InvalidParameter(format!(
+            "PWM frequency '{frequency}' Hz is outside of valid range [{min_frequency:.1}, {max_frequency:.1}]"
+        )));
+    }
+    Ok(prescale as u8)
+}
+
+/// PWM frequency in Hz of a PCA9685 with the given prescale
+pub fn pca9685_frequency(prescale: u8, oscillator_frequency: f32) -> f32 {
+    oscillator_frequency / (PCA9685_RESOLUTION as f32 * (prescale as f32 + 1.0))
+}
+
+/// PCA9685 counter value at which to turn the output off for a pulse width in microseconds,
+/// at the given PWM frequency in Hz
+pub fn pulse_counts(pulse_width: f32, frequency: f32) -> u16 {
+    let counts = (pulse_width * 1e-6 * frequency * PCA9685_RESOLUTION as f32).round();
+    counts.clamp(0.0, (PCA9685_RESOLUTION - 1) as f32) as u16
+}
+
+/// Duration of a pulse width given in microseconds
+pub fn pulse_duration(pulse_width: f32) -> Duration {
+    Duration::from_secs_f32(pulse_width.max(0.0) * 1e-6)
+}
+
+/// Angle limits, calibration and backlash compensation of a servo, shared by all the backends
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServoSettings {
+    pub min_angle: f32,
+    pub max_angle: f32,
+    pub reversed: bool,
+    pub calibration: ServoCalibration,
+    pub backlash: Backlash,
+}
+
+impl ServoSettings {
+    pub fn is_angle_allowed(&self, angle: f32) -> bool {
+        (angle >= self.min_angle) && (angle <= self.max_angle)
+    }
+
+    /// Pulse width in microseconds of the given angle, if it is allowed
+    pub fn pulse_width(&self, angle: f32) -> Result<f32, Error> {
+        if !self.is_angle_allowed(angle) {
+            Err(Error::InvalidParameter(format!(
+                "Provided angle '{}' is outside of valid range [{}, {}]",
+                angle, self.min_angle, self.max_angle,
+            )))
+        } else {
+            Ok(self.calibration.pulse_width(angle, self.reversed))
+        }
+    }
+
+    /// Pulse widths in microseconds to command in order to reach the given angle, if it is allowed,
+    /// see `Backlash::commands`. The compensated angles stay within the limits.
+    pub fn pulse_widths(&mut self, angle: f32) -> Result<Vec<f32>, Error> {
+        self.pulse_width(angle)?;
+        Ok(self
+            .backlash
+            .commands(angle, self.min_angle, self.max_angle)
+            .into_iter()
+            .map(|command| self.calibration.pulse_width(command, self.reversed))
+            .collect())
+    }
+
+    /// Time to wait between consecutive pulse widths given by `pulse_widths`
+    pub fn overshoot_delay(&self) -> Duration {
+        self.backlash.config().overshoot_delay()
+    }
+}
+
+// Error if one of the angles is not allowed for its servo
+fn check_angles<S: Servo>(servos: &[S], angles: &[f32]) -> Result<(), Error> {
+    match servos
+        .iter()
+        .zip(angles)
+        .find(|(servo, angle)| !servo.is_angle_allowed(**angle))
+    {
+        Some((servo, angle)) => Err(Error::InvalidParameter(format!(
+            "Provided angle '{}' is outside of valid range [{}, {}]",
+            angle,
+            servo.get_min_angle(),
+            servo.get_max_angle(),
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// A servo whose angle can be set within its limits, whatever drives it
+pub trait Servo {
+    fn set_angle(&mut self, angle: f32) -> Result<(), Error>;
+
+    fn get_min_angle(&self) -> f32;
+
+    fn get_max_angle(&self) -> f32;
+
+    fn is_reversed(&self) -> bool;
+
+    fn is_angle_allowed(&self, angle: f32) -> bool {
+        (angle >= self.get_min_angle()) && (angle <= self.get_max_angle())
+    }
+
+    /// Sets the angle of each servo, as close to simultaneously as the backend allows.
+    /// No servo moves if one of the angles is not allowed.
+    ///
+    /// The servos are moved one after the other by default.
+    fn set_angles(servos: &mut [Self], angles: &[f32]) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        check_angles(servos, angles)?;
+        for (servo, angle) in servos.iter_mut().zip(angles) {
+            servo.set_angle(*angle)?;
+        }
+        Ok(())
+    }
+}
+
+/// Servo driven by a channel of a PCA9685
 pub struct ServoMotor<I2c: I2cTrait> {
-    pwm: Rc<RefCell<Pca9685<I2c>>>,
+    pwm: SharedPca9685<I2c>,
     channel: Channel,
-    min_angle: f32,
-    max_angle: f32,
-    reversed: bool,
+    settings: ServoSettings,
 }
 
 impl<I2c: I2cTrait> ServoMotor<I2c> {
     pub fn new(
-        pwm: Rc<RefCell<Pca9685<I2c>>>,
+        pwm: SharedPca9685<I2c>,
         channel: Channel,
         min_angle: f32,
         max_angle: f32,
         reversed: bool,
+        calibration: ServoCalibration,
     ) -> Result<ServoMotor<I2c>, Error> {
         let mut servo: ServoMotor<I2c> = ServoMotor {
             pwm,
             channel,
-            min_angle,
-            max_angle,
-            reversed,
+            settings: ServoSettings {
+                min_angle,
+                max_angle,
+                reversed,
+                calibration,
+                backlash: Backlash::default(),
+            },
         };
         servo.set_angle(0.0)?;
         Ok(servo)
     }
 
-    pub fn set_angle(&mut self, angle: f32) -> Result<(), Error> {
-        if !self.is_angle_allowed(angle) {
-            Err(Error::InvalidParameter(format!(
-                "Provided angle '{}' is outside of valid range [{}, {}]",
-                angle, self.min_angle, self.max_angle,
-            )))
-        } else {
-            let pulse = match self.reversed {
-                true => -SLOPE * angle + INTERCEPT,
-                false => SLOPE * angle + INTERCEPT,
-            };
-            self.pwm
-                .borrow_mut()
-                .set_channel_on(self.channel, 0)
-                .map_err(|_x| Error::Other(format!("Failed setting pulse width: {pulse}")))?;
+    /// PCA9685 counter value of the given angle, if it is allowed
+    pub fn pulse(&self, angle: f32) -> Result<u16, Error> {
+        let pulse_width = self.settings.pulse_width(angle)?;
+        Ok(self.pwm.pulse_counts(pulse_width))
+    }
+
+    pub fn get_channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// Compensates the backlash of the servo from now on, see `Backlash`
+    pub fn with_backlash(mut self, config: BacklashConfig) -> Self {
+        self.settings.backlash = Backlash::new(config);
+        self
+    }
+}
+
+impl<I2c: I2cTrait> Servo for ServoMotor<I2c> {
+    fn set_angle(&mut self, angle: f32) -> Result<(), Error> {
+        let pulse_widths = self.settings.pulse_widths(angle)?;
+        for (index, pulse_width) in pulse_widths.iter().enumerate() {
+            if index > 0 {
+                thread::sleep(self.settings.overshoot_delay());
+            }
             self.pwm
-                .borrow_mut()
-                .set_channel_off(self.channel, pulse as u16)
-                .map_err(|_x| Error::Other(format!("Failed setting pulse width: {pulse}")))?;
-            Ok(())
+                .set_pulse(self.channel, self.pwm.pulse_counts(*pulse_width))?;
         }
+        Ok(())
     }
 
-    pub fn get_min_angle(&self) -> f32 {
-        self.min_angle
+    fn get_min_angle(&self) -> f32 {
+        self.settings.min_angle
     }
 
-    pub fn get_max_angle(&self) -> f32 {
-        self.max_angle
+    fn get_max_angle(&self) -> f32 {
+        self.settings.max_angle
     }
 
-    pub fn is_angle_allowed(&self, angle: f32) -> bool {
-        (angle >= self.min_angle) && (angle <= self.max_angle)
+    fn is_reversed(&self) -> bool {
+        self.settings.reversed
+    }
+
+    /// Servos driven by the same PCA9685 are moved in a single I2C write,
+    /// so that they start moving at the same moment.
+    /// With backlash compensation, the overshoots are written together first.
+    fn set_angles(servos: &mut [Self], angles: &[f32]) -> Result<(), Error> {
+        let Some(pwm) = servos.first().map(|servo| servo.pwm.clone()) else {
+            return Ok(());
+        };
+        if servos.iter().any(|servo| !servo.pwm.is_same(&pwm)) {
+            return Err(Error::InvalidParameter(String::from(
+                "Servos moved together must be driven by the same PCA9685",
+            )));
+        }
+        check_angles(servos, angles)?;
+        let commands = servos
+            .iter_mut()
+            .zip(angles)
+            .map(|(servo, angle)| servo.settings.pulse_widths(*angle))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let stages = commands.iter().map(Vec::len).max().unwrap_or(0);
+        let overshoot_delay = servos
+            .iter()
+            .map(|servo| servo.settings.overshoot_delay())
+            .max()
+            .unwrap_or_default();
+        for stage in 0..stages {
+            if stage > 0 {
+                thread::sleep(overshoot_delay);
+            }
+            // Servos with fewer commands stay at their target
+            let pulses: Vec<(Channel, u16)> = servos
+                .iter()
+                .zip(&commands)
+                .map(|(servo, pulse_widths)| {
+                    let pulse_width = pulse_widths[stage.min(pulse_widths.len() - 1)];
+                    (servo.channel, pwm.pulse_counts(pulse_width))
+                })
+                .collect();
+            pwm.set_pulses(&pulses)?;
+        }
+        Ok(())
+    }
+}
+
+/// Servos of the same backend, indexed in the order they were given.
+///
+/// `set_angles` moves all of them at once, see `Servo::set_angles`.
+pub struct ServoBank<S: Servo> {
+    servos: Vec<S>,
+}
+
+impl<S: Servo> ServoBank<S> {
+    pub fn new(servos: Vec<S>) -> Result<ServoBank<S>, Error> {
+        if servos.is_empty() {
+            return Err(Error::InvalidParameter(String::from(
+                "A servo bank needs at least one servo",
+            )));
+        }
+        Ok(ServoBank { servos })
+    }
+
+    /// Sets the angle of every servo, in the order of the bank.
+    /// No servo moves if one of the angles is not allowed.
+    pub fn set_angles(&mut self, angles: &[f32]) -> Result<(), Error> {
+        if angles.len() != self.servos.len() {
+            return Err(Error::InvalidParameter(format!(
+                "Expected {} angles, got {}",
+                self.servos.len(),
+                angles.len()
+            )));
+        }
+        S::set_angles(&mut self.servos, angles)
+    }
+
+    pub fn len(&self) -> usize {
+        self.servos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.servos.is_empty()
+    }
+}
+
+impl<S: Servo> Index<usize> for ServoBank<S> {
+    type Output = S;
+
+    fn index(&self, index: usize) -> &S {
+        &self.servos[index]
+    }
+}
+
+impl<S: Servo> IndexMut<usize> for ServoBank<S> {
+    fn index_mut(&mut self, index: usize) -> &mut S {
+        &mut self.servos[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backlash::{Approach, BacklashCompensation, BacklashConfig};
+
+    // 10 microseconds per degree, 1500 microseconds at 0 degrees
+    const CALIBRATION: ServoCalibration = ServoCalibration {
+        first_angle: -50.0,
+        first_pulse_width: 1000.0,
+        second_angle: 50.0,
+        second_pulse_width: 2000.0,
+    };
+
+    #[test]
+    fn calibration_interpolates_pulse_widths() {
+        assert_eq!(CALIBRATION.pulse_width(0.0, false), 1500.0);
+        assert_eq!(CALIBRATION.pulse_width(10.0, false), 1600.0);
+        assert_eq!(CALIBRATION.pulse_width(10.0, true), 1400.0);
+        assert_eq!(CALIBRATION.pulse_width(-50.0, false), 1000.0);
+    }
+
+    #[test]
+    fn prescale_matches_frequency() {
+        assert_eq!(pca9685_prescale(50.0, 25_000_000.0).unwrap(), 121);
+        assert!((pca9685_frequency(121, 25_000_000.0) - 50.0).abs() < 0.1);
+        assert!(pca9685_prescale(2000.0, 25_000_000.0).is_err());
+        assert!(pca9685_prescale(20.0, 25_000_000.0).is_err());
+        assert!(pca9685_prescale(f32::NAN, 25_000_000.0).is_err());
+    }
+
+    #[test]
+    fn pulse_counts_are_rounded_and_clamped() {
+        // 1.5 ms of a 20 ms period
+        assert_eq!(pulse_counts(1500.0, 50.0), 307);
+        assert_eq!(pulse_counts(-10.0, 50.0), 0);
+        assert_eq!(pulse_counts(30_000.0, 50.0), PCA9685_RESOLUTION - 1);
+    }
+
+    #[test]
+    fn compensated_pulse_widths_stay_within_limits() {
+        let mut settings = ServoSettings {
+            min_angle: -40.0,
+            max_angle: 40.0,
+            reversed: false,
+            calibration: CALIBRATION,
+            backlash: Backlash::new(BacklashConfig {
+                compensation: BacklashCompensation::Overshoot,
+                approach: Approach::FromAbove,
+                overshoot: 2.0,
+                ..BacklashConfig::default()
+            }),
+        };
+        assert_eq!(settings.pulse_widths(39.0).unwrap(), [1900.0, 1890.0]);
+        assert_eq!(settings.pulse_widths(0.0).unwrap(), [1500.0]);
+        // A target out of the limits is refused before it changes the direction of motion
+        assert!(settings.pulse_widths(41.0).is_err());
+        assert_eq!(settings.pulse_widths(-10.0).unwrap(), [1400.0]);
     }
 }
@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Settings of the settle time characterisation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SettleSettings {
+    /// Angle step in degrees of the search for the edge
+    pub search_step: f32,
+    /// Sizes in degrees of the steps whose settle time is measured
+    pub step_sizes: Vec<f32>,
+    /// Number of times each step is repeated
+    pub repetitions: u32,
+    /// Frame rate of the TF-Luna in continuous ranging mode, in Hz
+    pub framerate: u16,
+    /// Time in milliseconds during which the frames are recorded after each step,
+    /// and during which the servo rests before it
+    pub duration: u32,
+    /// The distance is stable once it stays within this of its final value, in centimeters
+    pub tolerance: f32,
+    /// Angle in degrees past the edge at which the steps end,
+    /// the servo must stay within this of its target for the distance to be stable
+    pub margin: f32,
+}
+
+impl Default for SettleSettings {
+    fn default() -> Self {
+        SettleSettings {
+            search_step: 0.5,
+            step_sizes: vec![1.0, 2.0, 5.0, 10.0, 20.0],
+            repetitions: 3,
+            framerate: 250,
+            duration: 1000,
+            tolerance: 2.0,
+            margin: 0.5,
+        }
+    }
+}
+
+impl SettleSettings {
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration as u64)
+    }
+}
+
+/// Estimates how long the distance takes to stabilise after a step,
+/// from frames given as (milliseconds since the step was commanded, distance in centimeters).
+///
+/// The final distance is the median of the last quarter of the frames, and the settle time
+/// is the time of the first frame after which every distance is within `tolerance` of it.
+/// `None` if the distance did not change, i.e. the step did not end across an edge.
+pub fn estimate_settle_time(frames: &[(f64, u16)], tolerance: f32) -> Option<f64> {
+    let (_, first_distance) = *frames.first()?;
+    let mut last_quarter: Vec<u16> = frames[frames.len() * 3 / 4..]
+        .iter()
+        .map(|(_, distance)| *distance)
+        .collect();
+    last_quarter.sort_unstable();
+    let final_distance = last_quarter[last_quarter.len() / 2] as f32;
+    let is_stable = |distance: u16| (distance as f32 - final_distance).abs() <= tolerance;
+    if is_stable(first_distance) {
+        return None;
+    }
+    // There is an unstable frame, the first one
+    let last_unstable = frames
+        .iter()
+        .rposition(|(_, distance)| !is_stable(*distance))?;
+    frames.get(last_unstable + 1).map(|(time, _)| *time)
+}
+
+/// Table of settle times in milliseconds per step size in degrees, `[step, settle_time]`
+/// sorted by step, from the estimates of every repetition given as (step, settle time).
+/// Each settle time is the median of the estimates of its step.
+pub fn settle_time_table(estimates: &[(f32, f64)]) -> Vec<[f32; 2]> {
+    let mut steps: Vec<f32> = estimates.iter().map(|(step, _)| *step).collect();
+    steps.sort_by(f32::total_cmp);
+    steps.dedup();
+    steps
+        .into_iter()
+        .map(|step| {
+            let mut times: Vec<f64> = estimates
+                .iter()
+                .filter(|(other, _)| *other == step)
+                .map(|(_, time)| *time)
+                .collect();
+            times.sort_by(f64::total_cmp);
+            [step, times[times.len() / 2] as f32]
+        })
+        .collect()
+}
+
+/// Time to wait after moving a servo by `step` degrees, linearly interpolated
+/// from a table given by `settle_time_table`, and clamped to its first and last entries.
+/// `None` if the table is empty.
+pub fn settle_delay(settle_times: &[[f32; 2]], step: f32) -> Option<Duration> {
+    let step = step.abs();
+    let first = settle_times.first()?;
+    let last = settle_times.last()?;
+    let milliseconds = if step <= first[0] {
+        first[1]
+    } else if step >= last[0] {
+        last[1]
+    } else {
+        // The step is strictly between the first and the last entry
+        let upper = settle_times.iter().position(|[s, _]| *s >= step)?;
+        let ([s0, t0], [s1, t1]) = (settle_times[upper - 1], settle_times[upper]);
+        t0 + (t1 - t0) * (step - s0) / (s1 - s0)
+    };
+    Some(Duration::from_micros(
+        (milliseconds.max(0.0) * 1000.0).round() as u64,
+    ))
+}
@@ -0,0 +1,563 @@
+use std::time::Instant;
+
+use embedded_hal::i2c::{ErrorKind, ErrorType, NoAcknowledgeSource, Operation};
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+use tfluna::tf_luna::constants;
+
+use crate::config::ScanConfig;
+use crate::kinematics::PanTiltKinematics;
+use crate::servo::{NOMINAL_OSCILLATOR_FREQUENCY, PCA9685_RESOLUTION, ServoCalibration};
+
+/// I2C address of the TF-Luna, the default of `embedded_tfluna::i2c::Address`
+pub const TFLUNA_ADDRESS: u8 = 0x10;
+/// I2C address of the PCA9685, the default of `pwm_pca9685::Address`
+pub const PCA9685_ADDRESS: u8 = 0x40;
+
+/// Largest distance measured by the TF-Luna, in centimeters
+pub const MAX_RANGE: f32 = 800.0;
+/// Signal strength of a target with a reflectivity of 1 at 1 m, decreasing with the squared distance
+pub const SIGNAL_STRENGTH_AT_1M: f32 = 10_000.0;
+
+// TF-Luna registers, as indices of the register array
+const TFLUNA_DISTANCE: usize = constants::DISTANCE_REGISTER_ADDRESS as usize;
+const TFLUNA_VERSION: usize = constants::FIRMWARE_VERSION_REGISTER_ADDRESS as usize;
+const TFLUNA_SERIAL_NUMBER: usize = constants::SERIAL_NUMBER_REGISTER_ADDRESS as usize;
+const TFLUNA_SLAVE_ADDRESS: usize = constants::SLAVE_ADDRESS_REGISTER_ADDRESS as usize;
+const TFLUNA_MODE: usize = constants::RANGING_MODE_REGISTER_ADDRESS as usize;
+const TFLUNA_TRIGGER: usize = constants::TRIGGER_REGISTER_ADDRESS as usize;
+const TFLUNA_ENABLE: usize = constants::ENABLE_REGISTER_ADDRESS as usize;
+const TFLUNA_FRAMERATE: usize = constants::FRAMERATE_REGISTER_ADDRESS as usize;
+const TFLUNA_SIGNAL_STRENGTH_THRESHOLD: usize =
+    constants::SIGNAL_STRENGTH_THRESHOLD_REGISTER_ADDRESS as usize;
+const TFLUNA_MAXIMUM_DISTANCE: usize = constants::MAXIMUM_DISTANCE_REGISTER_ADDRESS as usize;
+const TFLUNA_SIGNATURE: usize = constants::SIGNATURE_REGISTER_ADDRESS as usize;
+// Number of registers of a measurement: distance, signal strength, temperature and timestamp
+const TFLUNA_MEASUREMENT_LENGTH: usize = 8;
+
+// PCA9685 registers
+const PCA9685_MODE1: usize = 0x00;
+const PCA9685_LED0: usize = 0x06;
+const PCA9685_ALL_LED: usize = 0xFA;
+const PCA9685_PRESCALE: usize = 0xFE;
+const PCA9685_SLEEP: u8 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Shape {
+    Sphere,
+    /// Box aligned with the axes of the base frame
+    Box,
+}
+
+/// Object of the virtual scene, which can move and appear or disappear over time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulatedObject {
+    pub shape: Shape,
+    /// Center in centimeters in the base frame of the head
+    pub center: [f32; 3],
+    /// Radius of a sphere (first value) or half sizes of a box, in centimeters
+    pub size: [f32; 3],
+    /// The center oscillates by up to this amount along each axis, in centimeters
+    pub amplitude: [f32; 3],
+    /// Period of the oscillation in seconds
+    pub period: f32,
+    pub reflectivity: f32,
+    /// Seconds since the start of the simulation after which the object is in the scene
+    pub appear: f32,
+    /// Seconds since the start of the simulation after which the object is gone
+    pub disappear: Option<f32>,
+}
+
+impl Default for SimulatedObject {
+    fn default() -> Self {
+        SimulatedObject {
+            shape: Shape::Sphere,
+            center: [0.0, 150.0, 0.0],
+            size: [20.0, 20.0, 20.0],
+            amplitude: [0.0; 3],
+            period: 10.0,
+            reflectivity: 1.0,
+            appear: 0.0,
+            disappear: None,
+        }
+    }
+}
+
+impl SimulatedObject {
+    pub fn is_present(&self, time: f32) -> bool {
+        time >= self.appear && self.disappear.is_none_or(|disappear| time < disappear)
+    }
+
+    /// Center of the object at the given time, in seconds since the start of the simulation
+    pub fn center_at(&self, time: f32) -> [f32; 3] {
+        let phase = match self.period > 0.0 {
+            true => (time / self.period * std::f32::consts::TAU).sin(),
+            false => 0.0,
+        };
+        [0, 1, 2].map(|i| self.center[i] + self.amplitude[i] * phase)
+    }
+
+    // Distance along the ray to the surface of the object, if it is hit
+    fn intersect(&self, origin: &Vector3<f32>, direction: &Vector3<f32>, time: f32) -> Option<f32> {
+        let center = Vector3::from(self.center_at(time));
+        match self.shape {
+            Shape::Sphere => {
+                let radius = self.size[0];
+                let offset = origin - center;
+                let b = offset.dot(direction);
+                let c = offset.norm_squared() - radius * radius;
+                let discriminant = b * b - c;
+                if discriminant < 0.0 {
+                    return None;
+                }
+                let root = discriminant.sqrt();
+                [-b - root, -b + root].into_iter().find(|t| *t > 0.0)
+            }
+            Shape::Box => {
+                let half = Vector3::from(self.size);
+                box_intersection(origin, direction, &(center - half), &(center + half))
+                    .map(|(near, _)| near)
+                    .filter(|near| *near > 0.0)
+            }
+        }
+    }
+}
+
+/// Virtual scene and head used instead of the hardware with `--simulate`.
+///
+/// The beam is cast from the pose given by the kinematic model into a room with objects,
+/// and the servos move towards the commanded angles at a limited speed, with optional backlash.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+    /// Opposite corners of the room, a box aligned with the axes of the base frame, in centimeters
+    pub room: [[f32; 3]; 2],
+    pub wall_reflectivity: f32,
+    pub objects: Vec<SimulatedObject>,
+    /// Standard deviation of the distance noise in centimeters
+    pub noise: f32,
+    /// Angular speed of the servos in degrees per second
+    pub servo_speed: f32,
+    /// Backlash of the servo gears in degrees
+    pub servo_backlash: f32,
+    /// Temperature of the sensor in degrees Celsius
+    pub temperature: f32,
+    /// Seed of the noise, so that simulated scans can be reproduced
+    pub seed: u64,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        SimulationConfig {
+            room: [[-300.0, -200.0, -100.0], [300.0, 400.0, 200.0]],
+            wall_reflectivity: 1.0,
+            objects: vec![SimulatedObject::default()],
+            noise: 1.0,
+            servo_speed: 400.0,
+            servo_backlash: 0.0,
+            temperature: 35.0,
+            seed: 1,
+        }
+    }
+}
+
+impl SimulationConfig {
+    /// Distance in centimeters to the closest surface along the beam and its reflectivity,
+    /// `None` if nothing is hit within `MAX_RANGE`
+    pub fn cast(&self, origin: [f32; 3], direction: [f32; 3], time: f32) -> Option<(f32, f32)> {
+        let origin = Vector3::from(origin);
+        let direction = Vector3::from(direction).normalize();
+        let [min, max] = self.room.map(Vector3::from);
+        let wall = box_intersection(&origin, &direction, &min, &max)
+            .map(|(_, far)| far)
+            .filter(|far| *far > 0.0)
+            .map(|far| (far, self.wall_reflectivity));
+        self.objects
+            .iter()
+            .filter(|object| object.is_present(time))
+            .filter_map(|object| {
+                object
+                    .intersect(&origin, &direction, time)
+                    .map(|distance| (distance, object.reflectivity))
+            })
+            .chain(wall)
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .filter(|(distance, _)| *distance <= MAX_RANGE)
+    }
+}
+
+// Distances along the ray at which it enters and leaves the box, if it crosses it
+fn box_intersection(
+    origin: &Vector3<f32>,
+    direction: &Vector3<f32>,
+    min: &Vector3<f32>,
+    max: &Vector3<f32>,
+) -> Option<(f32, f32)> {
+    let mut near = f32::NEG_INFINITY;
+    let mut far = f32::INFINITY;
+    for axis in 0..3 {
+        if direction[axis].abs() < 1e-9 {
+            if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let t1 = (min[axis] - origin[axis]) / direction[axis];
+        let t2 = (max[axis] - origin[axis]) / direction[axis];
+        near = near.max(t1.min(t2));
+        far = far.min(t1.max(t2));
+    }
+    (near <= far).then_some((near, far))
+}
+
+// Servo driven by a PCA9685 channel
+struct SimulatedServo {
+    channel: usize,
+    reversed: bool,
+    calibration: ServoCalibration,
+    /// Angle of the motor in degrees
+    motor: f32,
+    /// Angle of the output shaft, which lags behind the motor by up to half the backlash
+    output: f32,
+}
+
+impl SimulatedServo {
+    // Moves towards the commanded angle for `elapsed` seconds
+    fn update(&mut self, command: Option<f32>, elapsed: f32, speed: f32, backlash: f32) {
+        // Without pulses, the servo holds its position
+        let Some(command) = command else {
+            return;
+        };
+        let step = speed * elapsed;
+        self.motor += (command - self.motor).clamp(-step, step);
+        let half_backlash = backlash / 2.0;
+        if self.motor > self.output + half_backlash {
+            self.output = self.motor - half_backlash;
+        } else if self.motor < self.output - half_backlash {
+            self.output = self.motor + half_backlash;
+        }
+    }
+
+    // Inverse of `ServoCalibration::pulse_width`
+    fn angle(&self, pulse_width: f32) -> f32 {
+        let angle = (pulse_width - self.calibration.intercept()) / self.calibration.slope();
+        match self.reversed {
+            true => -angle,
+            false => angle,
+        }
+    }
+}
+
+/// I2C bus with a simulated TF-Luna and PCA9685, driving a simulated head in a virtual scene.
+///
+/// Both devices are emulated at the level of their registers,
+/// so the drivers and the scanner run unchanged.
+pub struct SimulatedBus {
+    config: SimulationConfig,
+    kinematics: PanTiltKinematics,
+    start: Instant,
+    last_update: Instant,
+    /// Bottom (yaw) and top (pitch) servos
+    servos: [SimulatedServo; 2],
+    pca9685: [u8; 256],
+    tfluna: [u8; 256],
+    /// Register address of the next read or write of each device
+    pca9685_pointer: usize,
+    tfluna_pointer: usize,
+    /// Time of the last continuous ranging frame, in milliseconds since the start
+    last_frame: Option<u64>,
+    noise: NoiseGenerator,
+}
+
+impl SimulatedBus {
+    pub fn new(config: &ScanConfig) -> Self {
+        let servo = |servo: &crate::config::ServoConfig| SimulatedServo {
+            channel: servo.channel as usize,
+            reversed: servo.reversed,
+            calibration: servo.calibration,
+            motor: 0.0,
+            output: 0.0,
+        };
+        let mut tfluna = [0; 256];
+        // Firmware version 3.3.0 at the default address, enabled in continuous ranging mode at 100 Hz
+        tfluna[TFLUNA_VERSION..TFLUNA_VERSION + 3].copy_from_slice(&[0, 3, 3]);
+        tfluna[TFLUNA_SERIAL_NUMBER..TFLUNA_SERIAL_NUMBER + 14].copy_from_slice(b"SIMULATED00001");
+        tfluna[TFLUNA_SLAVE_ADDRESS] = TFLUNA_ADDRESS;
+        tfluna[TFLUNA_ENABLE] = 1;
+        tfluna[TFLUNA_FRAMERATE..TFLUNA_FRAMERATE + 2].copy_from_slice(&100u16.to_le_bytes());
+        tfluna[TFLUNA_SIGNAL_STRENGTH_THRESHOLD..TFLUNA_SIGNAL_STRENGTH_THRESHOLD + 2]
+            .copy_from_slice(&100u16.to_le_bytes());
+        tfluna[TFLUNA_MAXIMUM_DISTANCE..TFLUNA_MAXIMUM_DISTANCE + 2]
+            .copy_from_slice(&(MAX_RANGE as u16).to_le_bytes());
+        tfluna[TFLUNA_SIGNATURE..TFLUNA_SIGNATURE + 4].copy_from_slice(b"LUNA");
+        let mut pca9685 = [0; 256];
+        // Power-on state: asleep, with the prescale of 200 Hz
+        pca9685[PCA9685_MODE1] = PCA9685_SLEEP;
+        pca9685[PCA9685_PRESCALE] = 0x1E;
+        let now = Instant::now();
+        SimulatedBus {
+            config: config.simulation.clone(),
+            kinematics: config.kinematics,
+            start: now,
+            last_update: now,
+            servos: [servo(&config.servos.bottom), servo(&config.servos.top)],
+            pca9685,
+            tfluna,
+            pca9685_pointer: 0,
+            tfluna_pointer: 0,
+            last_frame: None,
+            noise: NoiseGenerator::new(config.simulation.seed),
+        }
+    }
+
+    /// Seconds since the start of the simulation, the time of the virtual scene
+    pub fn time(&self) -> f32 {
+        self.start.elapsed().as_secs_f32()
+    }
+
+    /// Actual angles of the bottom and top servos in degrees
+    pub fn angles(&self) -> (f32, f32) {
+        (self.servos[0].output, self.servos[1].output)
+    }
+
+    // Pulse width in microseconds generated on a channel, `None` if the output is off
+    fn pulse_width(&self, channel: usize) -> Option<f32> {
+        if self.pca9685[PCA9685_MODE1] & PCA9685_SLEEP != 0 || channel > 15 {
+            return None;
+        }
+        let registers = &self.pca9685[PCA9685_LED0 + 4 * channel..PCA9685_LED0 + 4 * channel + 4];
+        // Bit 4 of the high bytes turns the output fully on or off
+        if registers[3] & 0x10 != 0 {
+            return None;
+        }
+        let on = u16::from_le_bytes([registers[0], registers[1] & 0x0F]);
+        let off = u16::from_le_bytes([registers[2], registers[3] & 0x0F]);
+        let counts = (off + PCA9685_RESOLUTION - on) % PCA9685_RESOLUTION;
+        let prescale = self.pca9685[PCA9685_PRESCALE] as f32;
+        let frequency =
+            NOMINAL_OSCILLATOR_FREQUENCY / (PCA9685_RESOLUTION as f32 * (prescale + 1.0));
+        Some(counts as f32 / PCA9685_RESOLUTION as f32 / frequency * 1e6)
+    }
+
+    // Moves the servos up to now
+    fn update_servos(&mut self) {
+        let now = Instant::now();
+        let elapsed = (now - self.last_update).as_secs_f32();
+        self.last_update = now;
+        for index in 0..self.servos.len() {
+            let command = self
+                .pulse_width(self.servos[index].channel)
+                .map(|pulse_width| self.servos[index].angle(pulse_width));
+            self.servos[index].update(
+                command,
+                elapsed,
+                self.config.servo_speed,
+                self.config.servo_backlash,
+            );
+        }
+    }
+
+    // Writes a measurement of the current pose to the TF-Luna registers
+    fn measure(&mut self) {
+        self.update_servos();
+        let (yaw, pitch) = self.angles();
+        let pose = self.kinematics.pose(yaw, pitch);
+        let time = self.time();
+        let (distance, signal_strength) =
+            match self.config.cast(pose.origin(), pose.beam_direction(), time) {
+                Some((distance, reflectivity)) => {
+                    let distance = distance + self.config.noise * self.noise.gaussian();
+                    let signal_strength =
+                        SIGNAL_STRENGTH_AT_1M * reflectivity / (distance.max(1.0) / 100.0).powi(2);
+                    (
+                        distance.round().clamp(0.0, MAX_RANGE) as u16,
+                        signal_strength.min(u16::MAX as f32) as u16,
+                    )
+                }
+                None => (0, 0),
+            };
+        let temperature = (self.config.temperature * 100.0) as u16;
+        let timestamp = self.start.elapsed().as_millis() as u16;
+        let registers = &mut self.tfluna[TFLUNA_DISTANCE..TFLUNA_DISTANCE + 8];
+        registers[0..2].copy_from_slice(&distance.to_le_bytes());
+        registers[2..4].copy_from_slice(&signal_strength.to_le_bytes());
+        registers[4..6].copy_from_slice(&temperature.to_le_bytes());
+        registers[6..8].copy_from_slice(&timestamp.to_le_bytes());
+    }
+
+    // In continuous ranging mode, measures a new frame if one is due
+    fn update_continuous_ranging(&mut self) {
+        let is_continuous = self.tfluna[TFLUNA_MODE] == 0 && self.tfluna[TFLUNA_ENABLE] != 0;
+        let framerate = u16::from_le_bytes([
+            self.tfluna[TFLUNA_FRAMERATE],
+            self.tfluna[TFLUNA_FRAMERATE + 1],
+        ]);
+        if !is_continuous || framerate == 0 {
+            return;
+        }
+        let now = self.start.elapsed().as_millis() as u64;
+        let period = 1000 / framerate.max(1) as u64;
+        if self.last_frame.is_none_or(|last| now >= last + period) {
+            self.last_frame = Some(now - now % period.max(1));
+            self.measure();
+        }
+    }
+
+    fn write_tfluna(&mut self, bytes: &[u8]) {
+        let Some((register, data)) = bytes.split_first() else {
+            return;
+        };
+        self.tfluna_pointer = *register as usize;
+        for byte in data {
+            let register = self.tfluna_pointer % 256;
+            self.tfluna[register] = *byte;
+            if register == TFLUNA_TRIGGER && self.tfluna[TFLUNA_ENABLE] != 0 {
+                self.measure();
+            }
+            self.tfluna_pointer += 1;
+        }
+    }
+
+    fn read_tfluna(&mut self, buffer: &mut [u8]) {
+        if (TFLUNA_DISTANCE..TFLUNA_DISTANCE + TFLUNA_MEASUREMENT_LENGTH)
+            .contains(&self.tfluna_pointer)
+        {
+            self.update_continuous_ranging();
+        }
+        for byte in buffer {
+            *byte = self.tfluna[self.tfluna_pointer % 256];
+            self.tfluna_pointer += 1;
+        }
+    }
+
+    fn write_pca9685(&mut self, bytes: &[u8]) {
+        // The servos move towards the previous command until now
+        self.update_servos();
+        let Some((register, data)) = bytes.split_first() else {
+            return;
+        };
+        self.pca9685_pointer = *register as usize;
+        for byte in data {
+            let register = self.pca9685_pointer % 256;
+            // The prescale can only be changed while asleep
+            if register != PCA9685_PRESCALE || self.pca9685[PCA9685_MODE1] & PCA9685_SLEEP != 0 {
+                self.pca9685[register] = *byte;
+            }
+            if (PCA9685_ALL_LED..PCA9685_ALL_LED + 4).contains(&register) {
+                for channel in 0..16 {
+                    self.pca9685[PCA9685_LED0 + 4 * channel + register - PCA9685_ALL_LED] = *byte;
+                }
+            }
+            self.pca9685_pointer += 1;
+        }
+    }
+
+    fn read_pca9685(&mut self, buffer: &mut [u8]) {
+        for byte in buffer {
+            *byte = self.pca9685[self.pca9685_pointer % 256];
+            self.pca9685_pointer += 1;
+        }
+    }
+}
+
+impl ErrorType for SimulatedBus {
+    type Error = ErrorKind;
+}
+
+impl embedded_hal::i2c::I2c for SimulatedBus {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        for operation in operations {
+            match (address, operation) {
+                (TFLUNA_ADDRESS, Operation::Write(bytes)) => self.write_tfluna(bytes),
+                (TFLUNA_ADDRESS, Operation::Read(buffer)) => self.read_tfluna(buffer),
+                (PCA9685_ADDRESS, Operation::Write(bytes)) => self.write_pca9685(bytes),
+                (PCA9685_ADDRESS, Operation::Read(buffer)) => self.read_pca9685(buffer),
+                _ => return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Bus of the rig, the Raspberry Pi I2C bus or a simulated one
+pub enum RigBus {
+    Hardware(rppal::i2c::I2c),
+    Simulated(Box<SimulatedBus>),
+}
+
+#[derive(Debug)]
+pub enum BusError {
+    Hardware(rppal::i2c::Error),
+    Simulated(ErrorKind),
+}
+
+impl std::fmt::Display for BusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BusError::Hardware(err) => write!(f, "{err}"),
+            BusError::Simulated(kind) => write!(f, "Simulated bus: {kind}"),
+        }
+    }
+}
+
+impl std::error::Error for BusError {}
+
+impl embedded_hal::i2c::Error for BusError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            BusError::Hardware(err) => embedded_hal::i2c::Error::kind(err),
+            BusError::Simulated(kind) => *kind,
+        }
+    }
+}
+
+impl ErrorType for RigBus {
+    type Error = BusError;
+}
+
+impl embedded_hal::i2c::I2c for RigBus {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        match self {
+            RigBus::Hardware(i2c) => embedded_hal::i2c::I2c::transaction(i2c, address, operations)
+                .map_err(BusError::Hardware),
+            RigBus::Simulated(bus) => bus
+                .transaction(address, operations)
+                .map_err(BusError::Simulated),
+        }
+    }
+}
+
+// Pseudo-random normal distribution, xorshift64* with the Box-Muller transform
+struct NoiseGenerator {
+    state: u64,
+}
+
+impl NoiseGenerator {
+    fn new(seed: u64) -> Self {
+        NoiseGenerator { state: seed.max(1) }
+    }
+
+    // Uniform in (0, 1]
+    fn uniform(&mut self) -> f32 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        let value = self.state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        ((value >> 40) as f32 + 1.0) / (1u64 << 24) as f32
+    }
+
+    fn gaussian(&mut self) -> f32 {
+        let radius = (-2.0 * self.uniform().ln()).sqrt();
+        radius * (std::f32::consts::TAU * self.uniform()).cos()
+    }
+}
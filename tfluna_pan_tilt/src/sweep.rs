@@ -0,0 +1,194 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::scan::{TrajectorySummary, grid_angle_count};
+
+/// Settings of continuous-sweep scans
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SweepSettings {
+    /// Speed of the bottom servo in degrees per second
+    pub speed: f32,
+    /// Frame rate of the TF-Luna in continuous ranging mode, in Hz
+    pub framerate: u16,
+    /// Delay in milliseconds between the commanded angle and the frames measured at that angle,
+    /// see `estimate_latency`
+    pub latency: f32,
+    /// Interval in milliseconds between two servo commands along the trajectory
+    pub command_interval: u32,
+}
+
+impl Default for SweepSettings {
+    fn default() -> Self {
+        SweepSettings {
+            speed: 10.0,
+            framerate: 100,
+            latency: 0.0,
+            // One period of the 50 Hz servo signal
+            command_interval: 20,
+        }
+    }
+}
+
+/// A single sweep of the bottom servo at a constant pitch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepRow {
+    /// Top servo angle in degrees
+    pub pitch: f32,
+    /// Bottom servo angle in degrees at the start of the sweep
+    pub start_yaw: f32,
+    /// Bottom servo angle in degrees at the end of the sweep
+    pub end_yaw: f32,
+}
+
+/// Computes the rows of a sweep scan, from the maximum to the minimum pitch,
+/// alternating the direction of the sweeps.
+/// Ranges are given as (minimum, maximum) in degrees.
+pub fn plan_sweep_rows(
+    yaw_range: (f32, f32),
+    pitch_range: (f32, f32),
+    angle_step: f32,
+) -> Vec<SweepRow> {
+    let mut rows = Vec::new();
+    if yaw_range.1 < yaw_range.0 {
+        return rows;
+    }
+    for i in 0..grid_angle_count(pitch_range, angle_step) {
+        let (start_yaw, end_yaw) = match i % 2 {
+            0 => yaw_range,
+            _ => (yaw_range.1, yaw_range.0),
+        };
+        rows.push(SweepRow {
+            pitch: pitch_range.1 - i as f32 * angle_step,
+            start_yaw,
+            end_yaw,
+        });
+    }
+    rows
+}
+
+/// Summarizes the rows of a sweep scan, visited in order by the scanner,
+/// which waits `servo_motor_delay` after moving both servos to the start of each row.
+pub fn summarize_rows(
+    rows: &[SweepRow],
+    settings: &SweepSettings,
+    servo_motor_delay: Duration,
+) -> TrajectorySummary {
+    let mut summary = TrajectorySummary {
+        measurements: 0,
+        duration: Duration::ZERO,
+        yaw_travel: 0.0,
+        pitch_travel: 0.0,
+    };
+    let mut previous: Option<&SweepRow> = None;
+    for row in rows {
+        if let Some(previous) = previous {
+            summary.yaw_travel += (row.start_yaw - previous.end_yaw).abs();
+            summary.pitch_travel += (row.pitch - previous.pitch).abs();
+        }
+        let sweep = (row.end_yaw - row.start_yaw).abs();
+        let sweep_duration =
+            Duration::try_from_secs_f32(sweep / settings.speed).unwrap_or_default();
+        summary.yaw_travel += sweep;
+        summary.duration += servo_motor_delay + sweep_duration;
+        summary.measurements += (sweep_duration.as_secs_f32() * settings.framerate as f32) as usize;
+        previous = Some(row);
+    }
+    summary
+}
+
+/// Linear motion of the bottom servo during a sweep.
+/// Times are in milliseconds on the host clock.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepTrajectory {
+    pub start_yaw: f32,
+    pub end_yaw: f32,
+    /// Degrees per second, always positive
+    pub speed: f32,
+    pub start_time: f64,
+}
+
+impl SweepTrajectory {
+    pub fn duration(&self) -> f64 {
+        ((self.end_yaw - self.start_yaw).abs() / self.speed) as f64 * 1000.0
+    }
+
+    pub fn end_time(&self) -> f64 {
+        self.start_time + self.duration()
+    }
+
+    /// Commanded yaw at the given time, clamped to the start and end of the sweep
+    pub fn yaw_at(&self, time: f64) -> f32 {
+        let progress = match self.duration() {
+            duration if duration > 0.0 => ((time - self.start_time) / duration).clamp(0.0, 1.0),
+            _ => 1.0,
+        };
+        self.start_yaw + (self.end_yaw - self.start_yaw) * progress as f32
+    }
+
+    pub fn contains(&self, time: f64) -> bool {
+        time >= self.start_time && time <= self.end_time()
+    }
+}
+
+/// Clock of the TF-Luna, synchronised with the host clock.
+///
+/// The 16-bit millisecond timestamps of the frames wrap around every 65.536 seconds
+/// and are first converted to a monotonic sensor time.
+/// A frame is always read after it was measured, so the smallest difference
+/// between the host time at which a frame is read and its sensor time
+/// is the best estimate of the offset between both clocks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SensorClock {
+    last_timestamp: Option<u16>,
+    wraps: u64,
+    offset: Option<f64>,
+}
+
+impl SensorClock {
+    /// Registers a frame read at the given host time, in milliseconds,
+    /// and returns its monotonic sensor time
+    pub fn observe(&mut self, timestamp: u16, host_time: f64) -> u64 {
+        if self.last_timestamp.is_some_and(|last| timestamp < last) {
+            self.wraps += 1;
+        }
+        self.last_timestamp = Some(timestamp);
+        let sensor_time = self.wraps * (u16::MAX as u64 + 1) + timestamp as u64;
+        let offset = host_time - sensor_time as f64;
+        self.offset = Some(self.offset.map_or(offset, |current| current.min(offset)));
+        sensor_time
+    }
+
+    /// Host time of a sensor time, `None` before the first frame
+    pub fn to_host(&self, sensor_time: u64) -> Option<f64> {
+        self.offset.map(|offset| sensor_time as f64 + offset)
+    }
+}
+
+/// Yaw of the largest distance jump between consecutive frames,
+/// given as (yaw, distance) in the order they were measured
+pub fn edge_yaw(frames: &[(f32, u16)]) -> Option<f32> {
+    frames
+        .windows(2)
+        .max_by_key(|pair| pair[0].1.abs_diff(pair[1].1))
+        .map(|pair| (pair[0].0 + pair[1].0) / 2.0)
+}
+
+/// Estimates the latency from two sweeps over the same edge, at the same speed,
+/// the first towards increasing yaw and the second towards decreasing yaw.
+///
+/// A latency error shifts the edge by `speed * error` in the direction of each sweep,
+/// so the edges of both sweeps are `2 * speed * error` apart.
+/// `latency` is the value with which the yaws of the frames were interpolated,
+/// the returned value is in milliseconds.
+pub fn estimate_latency(
+    forward: &[(f32, u16)],
+    backward: &[(f32, u16)],
+    speed: f32,
+    latency: f32,
+) -> Option<f32> {
+    let forward_edge = edge_yaw(forward)?;
+    let backward_edge = edge_yaw(backward)?;
+    Some(latency + (forward_edge - backward_edge) / (2.0 * speed) * 1000.0)
+}
@@ -0,0 +1,714 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use rerun::{RecordingStream, RecordingStreamResult};
+use serde::{Deserialize, Serialize};
+
+use crate::checkpoint::{self, Checkpoint};
+use crate::config::{self, ScanConfig};
+use crate::point_cloud::PointRecord;
+use crate::range_image::{RangeImage, is_valid_measurement};
+use crate::remote::ScanControl;
+use crate::scanner::{self, Rig, new_recording_id, scan, scan_ranges};
+use crate::servo::Servo;
+
+/// Extension of the recordings saved by time-lapse scans
+pub const RECORDING_EXTENSION: &str = "rrd";
+
+/// Interval at which a time-lapse checks for signals while waiting for the next scan
+pub const POLLING_INTERVAL: Duration = Duration::from_millis(200);
+
+// Colors of the changed poses
+const CLOSER_COLOR: [u8; 4] = [230, 60, 60, 255];
+const FARTHER_COLOR: [u8; 4] = [60, 120, 230, 255];
+const VALIDITY_COLOR: [u8; 4] = [240, 200, 40, 255];
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, std::io::Error),
+    Config(config::Error),
+    Checkpoint(checkpoint::Error),
+    Scanner(scanner::Error),
+    /// The reference scan does not have the mode, angle step and ranges of the time-lapse scans
+    Reference(PathBuf),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(path, err) => write!(f, "Failed accessing '{}': {err}", path.display()),
+            Error::Config(err) => write!(f, "{err}"),
+            Error::Checkpoint(err) => write!(f, "{err}"),
+            Error::Scanner(err) => write!(f, "{err}"),
+            Error::Reference(path) => write!(
+                f,
+                "The reference scan '{}' does not have the mode, angle step and ranges of the time-lapse scans",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Repeated scans of the same poses, compared to a reference scan
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimelapseSettings {
+    /// Minutes between the starts of two scans,
+    /// a scan taking longer is followed immediately by the next one
+    pub interval: f32,
+    /// Number of scans, until stopped if not given
+    pub scans: Option<u32>,
+    /// Reference scan, in the checkpoint format. It is loaded if the file exists,
+    /// otherwise the first complete scan becomes the reference and is saved to it.
+    pub reference: Option<PathBuf>,
+    /// Directory to which each scan is saved as `<recording_id>.rrd`,
+    /// scans are sent to the rerun server if not given
+    pub directory: Option<PathBuf>,
+    /// Largest number of recordings kept in `directory`, the oldest are removed first
+    pub keep_recordings: usize,
+    /// Largest total size of the recordings kept in `directory`, in megabytes
+    pub max_disk_usage: Option<u64>,
+    /// A distance changed when it differs from the reference
+    /// by more than this many standard deviations of the noise
+    pub sigmas: f32,
+    /// Standard deviation of the distance noise as a fraction of the distance,
+    /// combined with the spread of the samples of each pose
+    pub relative_noise: f32,
+    /// Smallest change in centimeters, whatever the noise
+    pub minimum_change: f32,
+    /// Smallest number of neighbouring changed poses forming a region,
+    /// the changes of smaller regions are ignored as outliers
+    pub minimum_region_size: usize,
+}
+
+impl Default for TimelapseSettings {
+    fn default() -> Self {
+        TimelapseSettings {
+            interval: 10.0,
+            scans: None,
+            reference: None,
+            directory: None,
+            keep_recordings: 144,
+            max_disk_usage: None,
+            sigmas: 3.0,
+            relative_noise: 0.01,
+            minimum_change: 5.0,
+            minimum_region_size: 1,
+        }
+    }
+}
+
+/// Difference between the measurements of a pose in the reference and in a later scan
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoseChange {
+    /// Bottom servo angle in degrees
+    pub yaw: f32,
+    /// Top servo angle in degrees
+    pub pitch: f32,
+    /// Index of the pose in the range image of the scan
+    pub pixel: usize,
+    /// Position in centimeters of the new measurement, or of the reference one when the new is invalid
+    pub position: [f32; 3],
+    /// Distance in centimeters in the reference, `None` if it was invalid
+    pub reference_distance: Option<f32>,
+    /// Distance in centimeters in the new scan, `None` if it is invalid
+    pub distance: Option<f32>,
+    /// Difference in centimeters above which the distance changed
+    pub threshold: f32,
+}
+
+impl PoseChange {
+    /// New minus reference distance, negative when something came closer,
+    /// `None` when one of the measurements is invalid
+    pub fn difference(&self) -> Option<f32> {
+        Some(self.distance? - self.reference_distance?)
+    }
+
+    fn color(&self) -> [u8; 4] {
+        match self.difference() {
+            Some(difference) if difference < 0.0 => CLOSER_COLOR,
+            Some(_) => FARTHER_COLOR,
+            None => VALIDITY_COLOR,
+        }
+    }
+}
+
+/// Neighbouring changed poses
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangedRegion {
+    pub changes: Vec<PoseChange>,
+}
+
+impl ChangedRegion {
+    /// Opposite corners of the box containing the positions of the changes
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        self.changes.iter().fold(
+            ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]),
+            |(min, max), change| {
+                (
+                    [0, 1, 2].map(|i| min[i].min(change.position[i])),
+                    [0, 1, 2].map(|i| max[i].max(change.position[i])),
+                )
+            },
+        )
+    }
+
+    /// Mean of the differences of the poses valid in both scans
+    pub fn mean_difference(&self) -> Option<f32> {
+        let differences: Vec<f32> = self
+            .changes
+            .iter()
+            .filter_map(PoseChange::difference)
+            .collect();
+        (!differences.is_empty())
+            .then(|| differences.iter().sum::<f32>() / differences.len() as f32)
+    }
+
+    fn label(&self) -> String {
+        match self.mean_difference() {
+            Some(difference) => format!("{} poses, {difference:+.0} cm", self.changes.len()),
+            None => format!("{} poses", self.changes.len()),
+        }
+    }
+}
+
+/// Outcome of the comparison of a scan to the reference
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeReport {
+    /// Number of poses measured in both scans
+    pub compared_poses: usize,
+    pub regions: Vec<ChangedRegion>,
+}
+
+impl ChangeReport {
+    pub fn changed_poses(&self) -> usize {
+        self.regions.iter().map(|region| region.changes.len()).sum()
+    }
+
+    /// Logs the changed poses as `<entity_path>/points`, colored by the sign of the change,
+    /// and the boxes around the regions as `<entity_path>/regions`
+    pub fn log(
+        &self,
+        rec: &RecordingStream,
+        entity_path: &str,
+        radius: f32,
+    ) -> RecordingStreamResult<()> {
+        let changes = self.regions.iter().flat_map(|region| &region.changes);
+        rec.log(
+            format!("{entity_path}/points"),
+            &rerun::Points3D::new(changes.clone().map(|change| change.position))
+                .with_colors(changes.map(|change| change.color()))
+                .with_radii([radius]),
+        )?;
+        let bounds: Vec<([f32; 3], [f32; 3])> =
+            self.regions.iter().map(ChangedRegion::bounds).collect();
+        rec.log(
+            format!("{entity_path}/regions"),
+            &rerun::Boxes3D::from_mins_and_sizes(
+                bounds.iter().map(|(min, _)| *min),
+                bounds
+                    .iter()
+                    .map(|(min, max)| [0, 1, 2].map(|i| (max[i] - min[i]).max(radius))),
+            )
+            .with_labels(self.regions.iter().map(ChangedRegion::label)),
+        )
+    }
+}
+
+/// Standard deviation in centimeters of the distance of a measurement
+pub fn distance_noise(record: &PointRecord, settings: &TimelapseSettings) -> f32 {
+    let relative = settings.relative_noise * record.distance as f32;
+    (record.distance_spread.powi(2) + relative.powi(2)).sqrt()
+}
+
+/// Compares the poses measured in both scans, matched by their pixel of `grid`,
+/// the range image of the scan, e.g. an empty one.
+/// The scans must have at most one pose per pixel, as grid and planar scans do.
+/// A pose changed when its distance differs from the reference by more than
+/// `settings.sigmas` times the combined noise of both measurements,
+/// and at least `settings.minimum_change`, or when only one of them is valid.
+/// Changed poses that are neighbours on the grid form regions.
+pub fn detect_changes(
+    reference: &[PointRecord],
+    records: &[PointRecord],
+    grid: &RangeImage,
+    settings: &TimelapseSettings,
+) -> ChangeReport {
+    let reference = records_by_pixel(reference, grid);
+    let records = records_by_pixel(records, grid);
+    let mut compared_poses = 0;
+    let mut changes = BTreeMap::new();
+    for (pixel, record) in &records {
+        let Some(reference) = reference.get(pixel) else {
+            continue;
+        };
+        compared_poses += 1;
+        let distance = |record: &PointRecord| {
+            is_valid_measurement(record.distance, record.signal_strength)
+                .then_some(record.distance as f32)
+        };
+        let noise = distance_noise(record, settings).hypot(distance_noise(reference, settings));
+        let change = PoseChange {
+            yaw: record.yaw,
+            pitch: record.pitch,
+            pixel: *pixel,
+            position: match distance(record) {
+                Some(_) => record.position,
+                None => reference.position,
+            },
+            reference_distance: distance(reference),
+            distance: distance(record),
+            threshold: (settings.sigmas * noise).max(settings.minimum_change),
+        };
+        let is_changed = match (change.reference_distance, change.distance) {
+            (Some(_), Some(_)) => change.difference().unwrap().abs() > change.threshold,
+            (None, None) => false,
+            _ => true,
+        };
+        if is_changed {
+            changes.insert(*pixel, change);
+        }
+    }
+    let regions = changed_regions(changes, grid.width)
+        .into_iter()
+        .filter(|region| region.changes.len() >= settings.minimum_region_size.max(1))
+        .collect();
+    ChangeReport {
+        compared_poses,
+        regions,
+    }
+}
+
+// Records by their pixel of the grid, a later record replacing an earlier one like in range images
+fn records_by_pixel<'a>(
+    records: &'a [PointRecord],
+    grid: &RangeImage,
+) -> BTreeMap<usize, &'a PointRecord> {
+    records
+        .iter()
+        .filter_map(|record| {
+            grid.pixel_index(record.yaw, record.pitch)
+                .map(|pixel| (pixel, record))
+        })
+        .collect()
+}
+
+// Groups the changes whose pixels are neighbours in a row or a column of an image of the given width
+fn changed_regions(mut changes: BTreeMap<usize, PoseChange>, width: usize) -> Vec<ChangedRegion> {
+    let mut regions = Vec::new();
+    while let Some((_, first)) = changes.pop_first() {
+        let mut region = Vec::new();
+        let mut queue = VecDeque::from([first]);
+        while let Some(change) = queue.pop_front() {
+            let pixel = change.pixel;
+            let column = pixel % width;
+            let neighbours = [
+                (column > 0).then(|| pixel - 1),
+                (column + 1 < width).then(|| pixel + 1),
+                pixel.checked_sub(width),
+                Some(pixel + width),
+            ];
+            for neighbour in neighbours.into_iter().flatten() {
+                if let Some(change) = changes.remove(&neighbour) {
+                    queue.push_back(change);
+                }
+            }
+            region.push(change);
+        }
+        regions.push(ChangedRegion { changes: region });
+    }
+    regions
+}
+
+/// Removes the oldest recordings of the directory until at most `keep` remain
+/// and their total size is at most `max_bytes`, returns the removed files.
+/// The most recent recording is always kept.
+pub fn rotate_recordings(
+    directory: &Path,
+    keep: usize,
+    max_bytes: Option<u64>,
+) -> Result<Vec<PathBuf>, Error> {
+    let io_error = |err| Error::Io(directory.to_path_buf(), err);
+    let mut recordings = Vec::new();
+    for entry in fs::read_dir(directory).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        if path
+            .extension()
+            .is_none_or(|ext| ext != RECORDING_EXTENSION)
+        {
+            continue;
+        }
+        let metadata = fs::metadata(&path).map_err(|err| Error::Io(path.clone(), err))?;
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        recordings.push((modified, metadata.len(), path));
+    }
+    // Oldest first
+    recordings.sort();
+    let mut total_bytes: u64 = recordings.iter().map(|(_, size, _)| size).sum();
+    let mut removed = Vec::new();
+    for (_, size, path) in recordings.iter().take(recordings.len().saturating_sub(1)) {
+        let remaining = recordings.len() - removed.len();
+        if remaining <= keep && max_bytes.is_none_or(|max_bytes| total_bytes <= max_bytes) {
+            break;
+        }
+        fs::remove_file(path).map_err(|err| Error::Io(path.clone(), err))?;
+        total_bytes -= size;
+        removed.push(path.clone());
+    }
+    Ok(removed)
+}
+
+/// Repeats the scan every `timelapse.interval` minutes until the configured number of scans
+/// or a signal, and compares each one to the reference scan.
+/// Each scan gets a new recording, saved to the time-lapse directory when given,
+/// whose oldest recordings are removed to bound its size.
+/// A failed scan is reported and followed by the next one.
+pub fn run<S: Servo>(
+    config: &ScanConfig,
+    rig: &mut Rig<S>,
+    control: &ScanControl,
+) -> Result<(), Error> {
+    let settings = &config.timelapse;
+    let interval = Duration::from_secs_f32(settings.interval.max(0.0) * 60.0);
+    let mut reference = match &settings.reference {
+        Some(path) if path.exists() => {
+            let reference = load_reference(path, config)?;
+            println!(
+                "Comparing the scans to the reference {}",
+                reference.recording_id
+            );
+            Some(reference)
+        }
+        _ => None,
+    };
+    if let Some(directory) = &settings.directory {
+        fs::create_dir_all(directory).map_err(|err| Error::Io(directory.clone(), err))?;
+    }
+    let mut scans = 0;
+    while settings.scans.is_none_or(|count| scans < count) && !control.is_interrupted() {
+        let start = Instant::now();
+        let mut job = config.clone();
+        let recording_id = new_recording_id().map_err(Error::Scanner)?;
+        if let Some(directory) = &settings.directory {
+            job.output.rrd_file =
+                Some(directory.join(format!("{recording_id}.{RECORDING_EXTENSION}")));
+        }
+        let mut checkpoint = Checkpoint::new(recording_id, job.to_toml().map_err(Error::Config)?);
+        scans += 1;
+        println!(
+            "Starting scan {scans} of the time-lapse, {}",
+            checkpoint.recording_id
+        );
+        // A failed scan is reported by its events, the next one may succeed
+        match scan(&job, &mut checkpoint, rig, control, reference.as_ref()) {
+            Err(err) => println!("Scan {} failed: {err}", checkpoint.recording_id),
+            Ok(()) if reference.is_none() && !control.is_interrupted() => {
+                println!("The scan {} is the reference", checkpoint.recording_id);
+                if let Some(path) = &settings.reference {
+                    checkpoint.save(path).map_err(Error::Checkpoint)?;
+                }
+                reference = Some(checkpoint);
+            }
+            Ok(()) => {}
+        }
+        if let Some(directory) = &settings.directory {
+            let max_bytes = settings
+                .max_disk_usage
+                .map(|megabytes| megabytes * 1_000_000);
+            for path in rotate_recordings(directory, settings.keep_recordings, max_bytes)? {
+                println!("Removed {}", path.display());
+            }
+        }
+        if settings.scans.is_some_and(|count| scans >= count) {
+            break;
+        }
+        if start.elapsed() > interval {
+            println!("The scan took longer than the interval, starting the next one");
+        }
+        while start.elapsed() < interval && !control.is_interrupted() {
+            thread::sleep(POLLING_INTERVAL.min(interval.saturating_sub(start.elapsed())));
+        }
+    }
+    Ok(())
+}
+
+// Loads the reference scan, whose poses must be those of the time-lapse scans
+// since they are compared by their angles
+fn load_reference(path: &Path, config: &ScanConfig) -> Result<Checkpoint, Error> {
+    let reference = Checkpoint::load(path).map_err(Error::Checkpoint)?;
+    let reference_config = ScanConfig::from_toml(&reference.config).map_err(Error::Config)?;
+    if reference_config.scan.mode != config.scan.mode
+        || reference_config.scan.angle_step != config.scan.angle_step
+        || scan_ranges(&reference_config) != scan_ranges(config)
+    {
+        return Err(Error::Reference(path.to_path_buf()));
+    }
+    Ok(reference)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
+
+    use super::*;
+    use crate::events::{Event, EventRecord};
+    use crate::scanner::tests::{simulated_bus, simulated_config, simulated_rig, test_path};
+
+    // Grid of 3 × 3 poses, 1 degree apart
+    fn grid() -> RangeImage {
+        RangeImage::new((0.0, 2.0), (0.0, 2.0), 1.0, 1.0)
+    }
+
+    fn record(yaw: f32, pitch: f32, distance: u16) -> PointRecord {
+        PointRecord {
+            position: [yaw, distance as f32, pitch],
+            distance,
+            signal_strength: 1000,
+            temperature: 35.0,
+            yaw,
+            pitch,
+            capture_time: 0.0,
+            distance_spread: 0.0,
+        }
+    }
+
+    fn invalid(record: PointRecord) -> PointRecord {
+        PointRecord {
+            signal_strength: 0,
+            ..record
+        }
+    }
+
+    // Changes of a scan in which the given poses moved, the reference is at 200 cm everywhere
+    fn changes(records: &[PointRecord], settings: &TimelapseSettings) -> ChangeReport {
+        let grid = grid();
+        let reference: Vec<PointRecord> = (0..9)
+            .map(|pixel| record((pixel % 3) as f32, 2.0 - (pixel / 3) as f32, 200))
+            .collect();
+        detect_changes(&reference, records, &grid, settings)
+    }
+
+    fn pixels(region: &ChangedRegion) -> Vec<usize> {
+        let mut pixels: Vec<usize> = region.changes.iter().map(|change| change.pixel).collect();
+        pixels.sort();
+        pixels
+    }
+
+    #[test]
+    fn change_threshold_combines_the_noise_of_both_measurements() {
+        let settings = TimelapseSettings::default();
+        // 1 % of 200 and 210 cm, times 3 sigmas
+        let report = changes(&[record(0.0, 2.0, 210)], &settings);
+        assert_eq!(report.compared_poses, 1);
+        let change = report.regions[0].changes[0];
+        assert!((change.threshold - 3.0 * 2.0f32.hypot(2.1)).abs() < 1e-4);
+        assert_eq!(change.difference(), Some(10.0));
+        assert_eq!(
+            changes(&[record(0.0, 2.0, 208)], &settings).changed_poses(),
+            0
+        );
+        assert_eq!(
+            changes(&[record(0.0, 2.0, 192)], &settings).changed_poses(),
+            0
+        );
+    }
+
+    #[test]
+    fn change_threshold_grows_with_the_spread_of_the_samples() {
+        let settings = TimelapseSettings::default();
+        let spread = PointRecord {
+            distance_spread: 5.0,
+            ..record(0.0, 2.0, 215)
+        };
+        assert_eq!(
+            changes(&[record(0.0, 2.0, 215)], &settings).changed_poses(),
+            1
+        );
+        assert_eq!(changes(&[spread], &settings).changed_poses(), 0);
+    }
+
+    #[test]
+    fn change_threshold_is_at_least_the_minimum_change() {
+        let settings = TimelapseSettings {
+            relative_noise: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            changes(&[record(0.0, 2.0, 205)], &settings).changed_poses(),
+            0
+        );
+        let report = changes(&[record(0.0, 2.0, 206)], &settings);
+        assert_eq!(
+            report.regions[0].changes[0].threshold,
+            settings.minimum_change
+        );
+    }
+
+    #[test]
+    fn invalid_measurement_in_one_scan_is_a_change() {
+        let settings = TimelapseSettings::default();
+        let report = changes(&[invalid(record(0.0, 2.0, 0))], &settings);
+        let change = report.regions[0].changes[0];
+        assert_eq!(change.reference_distance, Some(200.0));
+        assert_eq!(change.distance, None);
+        assert_eq!(change.difference(), None);
+        // The position of the valid reference measurement is kept
+        assert_eq!(change.position, [0.0, 200.0, 2.0]);
+
+        let reference = [invalid(record(0.0, 2.0, 0)), invalid(record(1.0, 2.0, 0))];
+        let records = [record(0.0, 2.0, 100), invalid(record(1.0, 2.0, 0))];
+        let report = detect_changes(&reference, &records, &grid(), &settings);
+        assert_eq!(report.compared_poses, 2);
+        assert_eq!(report.changed_poses(), 1);
+        assert_eq!(report.regions[0].changes[0].distance, Some(100.0));
+    }
+
+    #[test]
+    fn poses_missing_from_a_scan_are_not_compared() {
+        let records = [record(0.0, 2.0, 100), record(5.0, 2.0, 100)];
+        let report = changes(&records, &TimelapseSettings::default());
+        assert_eq!(report.compared_poses, 1);
+    }
+
+    #[test]
+    fn regions_group_neighbours_in_rows_and_columns() {
+        // Pixels 2 and 3 follow each other but are at the end and the start of two rows,
+        // pixel 5 is below pixel 2
+        let records = [
+            record(2.0, 2.0, 100),
+            record(0.0, 1.0, 100),
+            record(2.0, 1.0, 100),
+        ];
+        let report = changes(&records, &TimelapseSettings::default());
+        let mut regions: Vec<Vec<usize>> = report.regions.iter().map(pixels).collect();
+        regions.sort();
+        assert_eq!(regions, vec![vec![2, 5], vec![3]]);
+        // Diagonal neighbours are separate regions
+        let records = [record(0.0, 2.0, 100), record(1.0, 1.0, 100)];
+        let report = changes(&records, &TimelapseSettings::default());
+        assert_eq!(report.regions.len(), 2);
+    }
+
+    #[test]
+    fn regions_smaller_than_the_minimum_size_are_ignored() {
+        let records = [
+            record(0.0, 2.0, 100),
+            record(1.0, 2.0, 100),
+            record(2.0, 0.0, 300),
+        ];
+        let settings = TimelapseSettings {
+            minimum_region_size: 2,
+            ..Default::default()
+        };
+        let report = changes(&records, &settings);
+        assert_eq!(report.compared_poses, 3);
+        assert_eq!(report.regions.len(), 1);
+        assert_eq!(pixels(&report.regions[0]), vec![0, 1]);
+        assert_eq!(report.regions[0].mean_difference(), Some(-100.0));
+        let (min, max) = report.regions[0].bounds();
+        assert_eq!((min, max), ([0.0, 100.0, 2.0], [1.0, 100.0, 2.0]));
+    }
+
+    // Directory of recordings of 10 bytes each, the first being the oldest, and another file
+    fn recordings_directory(name: &str, count: usize) -> (PathBuf, Vec<PathBuf>) {
+        let directory = test_path(name, "recordings");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("notes.txt"), [0; 100]).unwrap();
+        let start = SystemTime::now() - Duration::from_secs(3600);
+        let paths = (0..count)
+            .map(|index| {
+                let path = directory.join(format!("{index}.{RECORDING_EXTENSION}"));
+                let file = fs::File::create(&path).unwrap();
+                file.set_len(10).unwrap();
+                file.set_modified(start + Duration::from_secs(index as u64 * 60))
+                    .unwrap();
+                path
+            })
+            .collect();
+        (directory, paths)
+    }
+
+    #[test]
+    fn rotation_keeps_the_newest_recordings() {
+        let (directory, paths) = recordings_directory("rotate-keep", 4);
+        let removed = rotate_recordings(&directory, 2, None).unwrap();
+        assert_eq!(removed, paths[..2]);
+        assert!(paths[2].exists() && paths[3].exists());
+        assert!(directory.join("notes.txt").exists());
+        assert!(rotate_recordings(&directory, 2, None).unwrap().is_empty());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn rotation_bounds_the_size_of_the_recordings() {
+        let (directory, paths) = recordings_directory("rotate-size", 4);
+        // Other files do not count
+        let removed = rotate_recordings(&directory, 10, Some(25)).unwrap();
+        assert_eq!(removed, paths[..2]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn rotation_always_keeps_the_most_recent_recording() {
+        let (directory, paths) = recordings_directory("rotate-newest", 3);
+        let removed = rotate_recordings(&directory, 0, Some(0)).unwrap();
+        assert_eq!(removed, paths[..2]);
+        assert!(paths[2].exists());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn object_appearing_in_the_scene_is_a_changed_region() {
+        let mut config = simulated_config("timelapse");
+        let reference = test_path("timelapse", "reference");
+        let event_log = test_path("timelapse", "events");
+        let _ = fs::remove_file(&reference);
+        let _ = fs::remove_file(&event_log);
+        config.timelapse.scans = Some(1);
+        config.timelapse.reference = Some(reference.clone());
+        config.output.event_log = Some(event_log.clone());
+        let control = ScanControl::new(Arc::new(AtomicBool::new(false)));
+
+        // The object in front of the head only appears in the scene of the second run,
+        // whose scan is compared to the reference saved by the first one
+        config.simulation.objects[0].appear = 1e6;
+        let i2c = simulated_bus(&config);
+        run(&config, &mut simulated_rig(&config, &i2c), &control).unwrap();
+        assert!(reference.exists());
+        config.simulation.objects[0].appear = 0.0;
+        let i2c = simulated_bus(&config);
+        run(&config, &mut simulated_rig(&config, &i2c), &control).unwrap();
+
+        let comparisons: Vec<Event> = fs::read_to_string(&event_log)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<EventRecord>(line).unwrap().event)
+            .filter(|event| matches!(event, Event::ReferenceCompared { .. }))
+            .collect();
+        match comparisons.as_slice() {
+            [
+                Event::ReferenceCompared {
+                    compared_poses,
+                    changed_poses,
+                    regions,
+                    ..
+                },
+            ] => {
+                assert_eq!(*compared_poses, 9);
+                assert!(*changed_poses >= 1);
+                assert_eq!(*regions, 1);
+            }
+            events => panic!("Expected one comparison, got {events:?}"),
+        }
+        for path in [reference, event_log, test_path("timelapse", "rrd")] {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use serde::{Deserialize, Serialize};
+
+use crate::range_image::is_valid_measurement;
+use crate::scan::{ScanPattern, plan_grid};
+
+/// Settings of the tracking mode
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrackingSettings {
+    /// Targets are only searched for between these distances, in centimeters
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// Angle in degrees between the aim and the four probes measured around it
+    pub probe_step: f32,
+    /// A probe is on the target when its distance differs from the distance of the target
+    /// by less than this, in centimeters
+    pub distance_tolerance: f32,
+    /// Number of consecutive updates without any probe on the target after which
+    /// the target is considered lost and searched for again
+    pub max_misses: u32,
+    /// Duration of the tracking in seconds, until stopped if not given
+    pub duration: Option<u32>,
+}
+
+impl Default for TrackingSettings {
+    fn default() -> Self {
+        TrackingSettings {
+            min_distance: 20.0,
+            max_distance: 300.0,
+            probe_step: 2.0,
+            distance_tolerance: 20.0,
+            max_misses: 3,
+            duration: None,
+        }
+    }
+}
+
+/// Measurement made by the tracker at the given angles in degrees
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Probe {
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Distance in centimeters
+    pub distance: u16,
+    pub signal_strength: u16,
+}
+
+/// Tracked object, given by the angles at which the beam hits it and its distance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Target {
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Distance in centimeters
+    pub distance: f32,
+}
+
+/// Keeps the beam on the nearest object.
+///
+/// Without a target, the whole range is searched with a grid of `angle_step`
+/// and the nearest valid measurement within the distance window becomes the target.
+/// The tracker then alternates between measuring the aim and its four neighbours
+/// and moving the aim to the mean angle of the probes that are on the target,
+/// which follows the target as it moves and keeps the beam near its middle.
+pub struct Tracker {
+    settings: TrackingSettings,
+    yaw_range: (f32, f32),
+    pitch_range: (f32, f32),
+    angle_step: f32,
+    target: Option<Target>,
+    misses: u32,
+}
+
+impl Tracker {
+    /// Ranges are given as (minimum, maximum) in degrees
+    pub fn new(
+        settings: TrackingSettings,
+        yaw_range: (f32, f32),
+        pitch_range: (f32, f32),
+        angle_step: f32,
+    ) -> Self {
+        Tracker {
+            settings,
+            yaw_range,
+            pitch_range,
+            angle_step,
+            target: None,
+            misses: 0,
+        }
+    }
+
+    pub fn target(&self) -> Option<Target> {
+        self.target
+    }
+
+    /// Angles (yaw, pitch) to measure next, the search grid or the aim followed by its neighbours
+    pub fn next_probes(&self) -> Vec<(f32, f32)> {
+        let Some(target) = self.target else {
+            return plan_grid(
+                self.yaw_range,
+                self.pitch_range,
+                self.angle_step,
+                ScanPattern::Serpentine,
+            )
+            .iter()
+            .map(|pose| (pose.yaw, pose.pitch))
+            .collect();
+        };
+        let step = self.settings.probe_step;
+        let mut probes = vec![(target.yaw, target.pitch)];
+        for (yaw, pitch) in [
+            (target.yaw - step, target.pitch),
+            (target.yaw + step, target.pitch),
+            (target.yaw, target.pitch - step),
+            (target.yaw, target.pitch + step),
+        ] {
+            let probe = self.clamp(yaw, pitch);
+            if !probes.contains(&probe) {
+                probes.push(probe);
+            }
+        }
+        probes
+    }
+
+    /// Updates the target from the measurements of the probes given by `next_probes`,
+    /// returns the target, `None` while it is searched for
+    pub fn update(&mut self, probes: &[Probe]) -> Option<Target> {
+        let in_window = |probe: &&Probe| {
+            let distance = probe.distance as f32;
+            is_valid_measurement(probe.distance, probe.signal_strength)
+                && distance >= self.settings.min_distance
+                && distance <= self.settings.max_distance
+        };
+        let Some(target) = self.target else {
+            self.target = probes
+                .iter()
+                .filter(in_window)
+                .min_by_key(|probe| probe.distance)
+                .map(|probe| Target {
+                    yaw: probe.yaw,
+                    pitch: probe.pitch,
+                    distance: probe.distance as f32,
+                });
+            self.misses = 0;
+            return self.target;
+        };
+        let on_target: Vec<&Probe> = probes
+            .iter()
+            .filter(in_window)
+            .filter(|probe| {
+                (probe.distance as f32 - target.distance).abs() <= self.settings.distance_tolerance
+            })
+            .collect();
+        if on_target.is_empty() {
+            self.misses += 1;
+            if self.misses > self.settings.max_misses {
+                self.target = None;
+            }
+            return self.target;
+        }
+        self.misses = 0;
+        let count = on_target.len() as f32;
+        let mean =
+            |value: fn(&Probe) -> f32| on_target.iter().map(|p| value(p)).sum::<f32>() / count;
+        let (yaw, pitch) = self.clamp(mean(|p| p.yaw), mean(|p| p.pitch));
+        self.target = Some(Target {
+            yaw,
+            pitch,
+            distance: mean(|p| p.distance as f32),
+        });
+        self.target
+    }
+
+    fn clamp(&self, yaw: f32, pitch: f32) -> (f32, f32) {
+        (
+            yaw.clamp(self.yaw_range.0, self.yaw_range.1),
+            pitch.clamp(self.pitch_range.0, self.pitch_range.1),
+        )
+    }
+}